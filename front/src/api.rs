@@ -1,57 +1,290 @@
+use gloo::storage::{LocalStorage, Storage};
 use reqwasm::http::Request;
-use web_sys::window;
+use std::collections::HashMap;
+use web_sys::{window, Url};
 
-use crate::app::PlayersCreate;
+use crate::app::{EventDetails, PlayersCreate, Schedule};
+use crate::components::{NewMessage, Wishlist};
+
+const ADMIN_TOKEN_KEY: &str = "secret-santa-admin-token";
+
+fn query_param(name: &str) -> Option<String> {
+    let href = window()?.location().href().ok()?;
+    Url::new(&href).ok()?.search_params().get(name)
+}
+
+// Every game lives under its own id, carried in the page url as `?game=`.
+pub fn game_id() -> Option<String> {
+    query_param("game")
+}
+
+// A player's personal link carries their token as `?token=`.
+pub fn player_token() -> Option<String> {
+    query_param("token")
+}
+
+// The organizer opens the link printed by the server, which carries the
+// admin secret as `?admin=`; keep it around for the following visits.
+pub fn admin_token(game_id: &str) -> Option<String> {
+    let key = format!("{}-{}", ADMIN_TOKEN_KEY, game_id);
+    if let Some(token) = query_param("admin") {
+        let _ = LocalStorage::set(&key, &token);
+        return Some(token);
+    }
+    LocalStorage::get(&key).ok()
+}
 
 #[derive(Debug, Clone)]
 pub struct Api {
     url: String,
+    game_id: Option<String>,
+    admin_token: Option<String>,
 }
 
 impl Api {
     pub fn new() -> Self {
-        let mut url = window()
-            .unwrap()
-            .location()
-            .href()
-            .unwrap_or_else(|_| "unknown".to_string());
+        // personal links carry a query string, so build the url from its parts
+        let location = window().unwrap().location();
+        let mut url = format!(
+            "{}{}",
+            location.origin().unwrap_or_else(|_| "unknown".to_string()),
+            location.pathname().unwrap_or_default()
+        );
 
         if cfg!(debug_assertions) {
             url = "http://localhost:8080/".to_string();
         }
         let url = url + "secret-santa";
-        Api { url }
+        let game_id = game_id();
+        let admin_token = game_id.as_deref().and_then(admin_token);
+        Api {
+            url,
+            game_id,
+            admin_token,
+        }
+    }
+
+    pub fn game_id(&self) -> Option<&str> {
+        self.game_id.as_deref()
+    }
+
+    fn game_url(&self) -> String {
+        format!("{}/{}", self.url, self.game_id.as_deref().unwrap_or_default())
     }
+
+    pub fn is_organizer(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    fn as_organizer(&self, request: Request) -> Request {
+        match &self.admin_token {
+            Some(token) => request.header("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
     pub async fn remove_player(
         &self,
         name: &String,
     ) -> Result<reqwasm::http::Response, reqwasm::Error> {
-        let url = format!("{}/remove-player/{}", self.url, name);
+        let url = format!("{}/remove-player/{}", self.game_url(), name);
+        self.as_organizer(Request::post(&url))
+            .header("Content-Type", "application/json")
+            .send()
+            .await
+    }
+
+    // `language` picks the template of the emails sent to the givers
+    pub async fn start_game(
+        &self,
+        language: &str,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/start-game", self.game_url());
+        self.as_organizer(Request::post(&url))
+            .header("Content-Type", "application/json")
+            .body(serde_json::json!({ "language": language }).to_string())
+            .send()
+            .await
+    }
+
+    pub async fn create_game(
+        &self,
+        schedule: &Schedule,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/games", self.url);
         Request::post(&url)
             .header("Content-Type", "application/json")
+            .body(serde_json::to_string(schedule).unwrap())
+            .send()
+            .await
+    }
+
+    pub async fn set_schedule(
+        &self,
+        schedule: &Schedule,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/set-schedule", self.game_url());
+        self.as_organizer(Request::post(&url))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(schedule).unwrap())
             .send()
             .await
     }
 
-    pub async fn start_game(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
-        let url = format!("{}/start-game", self.url);
-        Request::post(&url).send().await
+    pub fn events_url(&self) -> String {
+        format!("{}/events", self.game_url())
+    }
+
+    // same origin as the api, over ws:// or wss://
+    pub fn lobby_url(&self) -> String {
+        format!("{}/lobby", self.game_url()).replacen("http", "ws", 1)
     }
 
     pub async fn info(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
-        Request::get(&self.url).send().await
+        Request::get(&self.game_url()).send().await
+    }
+
+    pub async fn player_tokens(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/player-tokens", self.game_url());
+        self.as_organizer(Request::get(&url)).send().await
+    }
+
+    // close-registration, reopen-registration, open-reveals, close-exchange,
+    // archive or clear-players
+    pub async fn change_phase(
+        &self,
+        action: &str,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/{}", self.game_url(), action);
+        self.as_organizer(Request::post(&url)).send().await
     }
 
     pub async fn reset_game(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
-        let url = format!("{}/reset-game", self.url);
-        Request::post(&url).send().await
+        let url = format!("{}/reset-game", self.game_url());
+        self.as_organizer(Request::post(&url)).send().await
     }
 
-    pub async fn pick_player(
+    pub async fn reveal_seed(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/reveal-seed", self.game_url());
+        self.as_organizer(Request::post(&url)).send().await
+    }
+
+    pub async fn add_late_player(
         &self,
         name: &String,
     ) -> Result<reqwasm::http::Response, reqwasm::Error> {
-        let url = format!("{}/player-pick/{}", self.url, name);
+        let body = serde_json::json!({ "name": name }).to_string();
+        let url = format!("{}/add-late-player", self.game_url());
+        self.as_organizer(Request::post(&url))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+    }
+
+    pub async fn withdraw_player(
+        &self,
+        name: &String,
+        replacement: Option<String>,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let replacement = replacement.map(|name| serde_json::json!({ "name": name }));
+        let body = serde_json::json!({ "name": name, "replacement": replacement }).to_string();
+        let url = format!("{}/withdraw-player", self.game_url());
+        self.as_organizer(Request::post(&url))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+    }
+
+    pub async fn set_event(
+        &self,
+        event: &EventDetails,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/set-event", self.game_url());
+        self.as_organizer(Request::post(&url))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(event).unwrap())
+            .send()
+            .await
+    }
+
+    // recipients already revealed, with their current wishlists
+    pub async fn recipients(&self, token: &str) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/recipients/{}", self.game_url(), token);
+        Request::get(&url).send().await
+    }
+
+    pub async fn messages(&self, token: &str) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/messages/{}", self.game_url(), token);
+        Request::get(&url).send().await
+    }
+
+    // `to` is "recipient/<name>" or "santa/<thread id>"
+    pub async fn send_message(
+        &self,
+        token: &str,
+        to: &str,
+        message: &NewMessage,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/messages/{}/{}", self.game_url(), token, to);
+        Request::post(&url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(message).unwrap())
+            .send()
+            .await
+    }
+
+    pub async fn wishlist(&self, token: &str) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/wishlist/{}", self.game_url(), token);
+        Request::get(&url).send().await
+    }
+
+    pub async fn set_wishlist(
+        &self,
+        token: &str,
+        wishlist: &Wishlist,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/wishlist/{}", self.game_url(), token);
+        Request::post(&url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(wishlist).unwrap())
+            .send()
+            .await
+    }
+
+    pub async fn webhooks(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/show-webhooks", self.game_url());
+        self.as_organizer(Request::get(&url)).send().await
+    }
+
+    pub async fn add_webhook(&self, url: &str) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let endpoint = format!("{}/add-webhook", self.game_url());
+        self.as_organizer(Request::post(&endpoint))
+            .header("Content-Type", "application/json")
+            .body(serde_json::json!({ "url": url }).to_string())
+            .send()
+            .await
+    }
+
+    pub async fn remove_webhook(
+        &self,
+        id: &str,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/remove-webhook/{}", self.game_url(), id);
+        self.as_organizer(Request::post(&url)).send().await
+    }
+
+    pub async fn webhook_deliveries(&self) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/webhook-deliveries", self.game_url());
+        self.as_organizer(Request::get(&url)).send().await
+    }
+
+    pub async fn pick_player(
+        &self,
+        token: &String,
+    ) -> Result<reqwasm::http::Response, reqwasm::Error> {
+        let url = format!("{}/player-pick/{}", self.game_url(), token);
         Request::get(&url)
             .header("Content-Type", "application/json")
             .send()
@@ -61,12 +294,20 @@ impl Api {
     pub async fn add_player(
         &self,
         name: &String,
+        email: &str,
     ) -> Result<reqwasm::http::Response, reqwasm::Error> {
         let players = Vec::from([name.clone()]);
-        let players_create = PlayersCreate { names: players };
+        let mut emails = HashMap::new();
+        if !email.trim().is_empty() {
+            emails.insert(name.clone(), email.trim().to_string());
+        }
+        let players_create = PlayersCreate {
+            names: players,
+            emails,
+        };
         let players_create = serde_json::to_string(&players_create).unwrap();
-        let url = format!("{}/add-players", self.url);
-        Request::post(&url)
+        let url = format!("{}/add-players", self.game_url());
+        self.as_organizer(Request::post(&url))
             .header("Content-Type", "application/json")
             .body(players_create)
             .send()
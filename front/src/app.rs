@@ -1,38 +1,109 @@
 use gloo::console::log;
+use gloo::storage::{LocalStorage, Storage};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::Deref;
-use web_sys::HtmlInputElement;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{EventSource, HtmlInputElement, MessageEvent, WebSocket};
 use yew::{function_component, html, prelude::*, use_effect_with, Html};
 use yew_i18n::use_translation;
 use yew_i18n::I18nProvider;
 
-use crate::api::Api;
-use crate::components::{InProgressGame, InitGame};
+use crate::api::{game_id, player_token, Api};
+use crate::components::{
+    CreateGame, EventForm, EventHeader, InProgressGame, InitGame, ScheduleBanner, ScheduleForm,
+    Webhooks,
+};
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum GameStatus {
-    NotStarted,
-    InProgress,
-    Finished,
+    Open,
+    Draft,
+    Drawn,
+    Revealing,
+    Closed,
+    Archived,
 }
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Properties)]
 pub struct Player {
     pub name: String,
+    // the name as typed, `name` is the normalized one the server matches on
+    #[serde(default)]
+    pub display_name: String,
     pub has_picked: bool,
 }
 
+impl Player {
+    pub fn label(&self) -> String {
+        if self.display_name.is_empty() {
+            self.name.clone()
+        } else {
+            self.display_name.clone()
+        }
+    }
+}
+
+// unix timestamps in seconds, see `Schedule` on the server
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub reveals_at: Option<u64>,
+    #[serde(default)]
+    pub exchange_at: Option<u64>,
+}
+
+// see `EventDetails` on the server, budgets are whole amounts in `currency`
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventDetails {
+    pub title: Option<String>,
+    pub budget_min: Option<u32>,
+    pub budget_max: Option<u32>,
+    pub currency: Option<String>,
+    pub location: Option<String>,
+    pub theme: Option<String>,
+    pub rules: Option<String>,
+}
+
+impl EventDetails {
+    pub fn budget(&self) -> Option<String> {
+        let currency = self.currency.as_deref().unwrap_or_default();
+        let amount = match (self.budget_min, self.budget_max) {
+            (Some(min), Some(max)) if min == max => format!("{}", max),
+            (Some(min), Some(max)) => format!("{} – {}", min, max),
+            (Some(min), None) => format!("{}+", min),
+            (None, Some(max)) => format!("≤ {}", max),
+            (None, None) => return None,
+        };
+        Some(format!("{} {}", amount, currency).trim_end().to_string())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SantaGameInfo {
     pub status: GameStatus,
     pub players: Vec<Player>,
+    // hash of the draw, published at start; the seed follows once revealed
+    #[serde(default)]
+    pub commitment: Option<String>,
+    #[serde(default)]
+    pub seed: Option<String>,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub event: EventDetails,
 }
 
 impl Default for SantaGameInfo {
     fn default() -> Self {
         SantaGameInfo {
-            status: GameStatus::NotStarted,
+            status: GameStatus::Open,
             players: vec![],
+            commitment: None,
+            seed: None,
+            schedule: Schedule::default(),
+            event: EventDetails::default(),
         }
     }
 }
@@ -40,11 +111,102 @@ impl Default for SantaGameInfo {
 #[derive(Serialize, Deserialize)]
 pub struct ApiError {
     pub error: String,
+    // machine readable, see `GameError::code` on the server
+    #[serde(default)]
+    pub code: String,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PlayersCreate {
     pub names: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub emails: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerReport {
+    pub name: String,
+    pub accepted: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub warning: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+// add-players answers with a per-name report, other errors have no players
+#[derive(Serialize, Deserialize)]
+pub struct AddPlayersReport {
+    #[serde(default)]
+    pub applied: bool,
+    #[serde(default)]
+    pub players: Vec<PlayerReport>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl AddPlayersReport {
+    // the first rejected name explains the failure better than the batch code
+    pub fn rejection(&self) -> (String, String) {
+        let rejected = self.players.iter().find(|player| !player.accepted);
+        let error = rejected
+            .and_then(|player| player.error.clone())
+            .or_else(|| self.error.clone())
+            .unwrap_or_default();
+        let code = rejected
+            .and_then(|player| player.code.clone())
+            .or_else(|| self.code.clone())
+            .unwrap_or_default();
+        (error, code)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerTokens {
+    pub tokens: HashMap<String, String>,
+    #[serde(default)]
+    pub emails: Option<EmailReport>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EmailFailure {
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EmailReport {
+    pub sent: Vec<String>,
+    pub failed: Vec<EmailFailure>,
+    pub without_email: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewGame {
+    pub id: String,
+    pub admin_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LobbyMessage {
+    Presence {
+        online: Vec<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+const PLAYER_TOKENS_KEY: &str = "secret-santa-player-tokens";
+
+// an organizer may run several games from the same browser
+pub fn player_tokens_key() -> String {
+    format!("{}-{}", PLAYER_TOKENS_KEY, game_id().unwrap_or_default())
 }
 
 #[function_component(Loading)]
@@ -80,17 +242,76 @@ pub fn wrap() -> Html {
             "Remove Player": "Remover Jogador",
             "Enter the names of the participants": "Digite os nomes dos participantes",
             "John": "Fulano",
+            "Email (optional)": "Email (opcional)",
+            "Could not email": "Não foi possível enviar email para",
             "Loading...": "Carregando...",
             "Start Game": "Iniciar Jogo",
             "You picked": "Você tirou ",
             "Select your name": "Selecione seu nome",
-            "Who are you ?": "Quem é você ?",
             "Pick": "Sortear",
             "Share with other players": "Compartilhe com outros jogadores 👇 ",
             "Copy link": "Copiar link",
             "Game Finished": "Jogo Finalizado",
+            "Close registration": "Encerrar inscrições",
+            "Reopen registration": "Reabrir inscrições",
+            "Clear players": "Remover todos",
+            "Registration is closed": "Inscrições encerradas",
+            "Open reveals": "Liberar sorteio",
+            "Reveals are not open yet": "O sorteio ainda não foi liberado",
+            "Exchange done": "Troca concluída",
+            "Archive": "Arquivar",
+            "Game archived": "Jogo arquivado",
+            "Reveals open on": "O sorteio abre em",
+            "Gift exchange on": "Troca de presentes em",
+            "Save dates": "Salvar datas",
+            "Budget": "Orçamento",
+            "Location": "Local",
+            "Theme": "Tema",
+            "Rules": "Regras",
+            "Event title": "Nome do evento",
+            "Minimum budget": "Orçamento mínimo",
+            "Maximum budget": "Orçamento máximo",
+            "Currency": "Moeda",
+            "Save event details": "Salvar detalhes do evento",
+            "Reveals must open before the gift exchange": "O sorteio precisa abrir antes da troca de presentes",
             "Copied to the clipboard": "Copiado para a área de transferência",
             "You already picked": "Você já tirou",
+            "My wishlist": "Minha lista de desejos",
+            "Wishlist": "Lista de desejos",
+            "Nothing on the wishlist yet": "Nada na lista de desejos ainda",
+            "Title": "Título",
+            "Link": "Link",
+            "Price hint": "Faixa de preço",
+            "Notes": "Observações",
+            "Add item": "Adicionar item",
+            "Save wishlist": "Salvar lista",
+            "Wishlist saved": "Lista salva",
+            "Webhooks": "Webhooks",
+            "Add webhook": "Adicionar webhook",
+            "Remove": "Remover",
+            "Keep this secret to check the signatures:": "Guarde este segredo para conferir as assinaturas:",
+            "Messages": "Mensagens",
+            "My Santa": "Meu amigo secreto",
+            "Write a message": "Escreva uma mensagem",
+            "Send": "Enviar",
+            "Personal links": "Envie a cada participante o seu link pessoal 👇 ",
+            "Open your personal link": "Abra o link pessoal que o organizador te enviou para sortear",
+            "Waiting for the organizer": "Aguardando o organizador iniciar o jogo",
+            "Create a new game": "Criar um novo jogo",
+            "Online now": "Online agora",
+            "Invalid personal link": "Link pessoal inválido, peça um novo ao organizador",
+            "Game not found": "Jogo não encontrado",
+            "Draw commitment": "Compromisso do sorteio",
+            "Draw seed": "Semente do sorteio",
+            "Reveal seed": "Revelar semente",
+            "Late participant": "Participante atrasado",
+            "Add late participant": "Adicionar atrasado",
+            "Cannot add late player": "Não foi possível adicionar",
+            "Participant already exists": "Participante já existe",
+            "Who is leaving ?": "Quem vai sair ?",
+            "Replacement (optional)": "Substituto (opcional)",
+            "Withdraw": "Retirar",
+            "These players must reveal again": "Estes participantes precisam sortear de novo",
         }),
     );
 
@@ -104,17 +325,76 @@ pub fn wrap() -> Html {
             "Remove Player": "Remove Player",
             "Enter the names of the participants": "Enter the names of the participants",
             "John": "John",
+            "Email (optional)": "Email (optional)",
+            "Could not email": "Could not email",
             "Loading...": "Loading...",
             "Start Game": "Start Game",
             "You picked": "You picked ",
             "Select your name": "Select your name",
-            "Who are you ?": "Who are you ?",
             "Pick": "Pick",
             "Share with other players": "Share with other players 👇 ",
             "Copy link": "Copy link",
             "Game Finished": "Game Finished",
+            "Close registration": "Close registration",
+            "Reopen registration": "Reopen registration",
+            "Clear players": "Clear players",
+            "Registration is closed": "Registration is closed",
+            "Open reveals": "Open reveals",
+            "Reveals are not open yet": "Reveals are not open yet",
+            "Exchange done": "Exchange done",
+            "Archive": "Archive",
+            "Game archived": "Game archived",
+            "Reveals open on": "Reveals open on",
+            "Gift exchange on": "Gift exchange on",
+            "Save dates": "Save dates",
+            "Budget": "Budget",
+            "Location": "Location",
+            "Theme": "Theme",
+            "Rules": "Rules",
+            "Event title": "Event title",
+            "Minimum budget": "Minimum budget",
+            "Maximum budget": "Maximum budget",
+            "Currency": "Currency",
+            "Save event details": "Save event details",
+            "Reveals must open before the gift exchange": "Reveals must open before the gift exchange",
             "Copied to the clipboard": "Copied to the clipboard",
             "You already picked": "You already picked",
+            "My wishlist": "My wishlist",
+            "Wishlist": "Wishlist",
+            "Nothing on the wishlist yet": "Nothing on the wishlist yet",
+            "Title": "Title",
+            "Link": "Link",
+            "Price hint": "Price hint",
+            "Notes": "Notes",
+            "Add item": "Add item",
+            "Save wishlist": "Save wishlist",
+            "Wishlist saved": "Wishlist saved",
+            "Webhooks": "Webhooks",
+            "Add webhook": "Add webhook",
+            "Remove": "Remove",
+            "Keep this secret to check the signatures:": "Keep this secret to check the signatures:",
+            "Messages": "Messages",
+            "My Santa": "My Santa",
+            "Write a message": "Write a message",
+            "Send": "Send",
+            "Personal links": "Send each player their personal link 👇 ",
+            "Open your personal link": "Open the personal link the organizer sent you to pick",
+            "Waiting for the organizer": "Waiting for the organizer to start the game",
+            "Create a new game": "Create a new game",
+            "Online now": "Online now",
+            "Invalid personal link": "Invalid personal link, ask the organizer for a new one",
+            "Game not found": "Game not found",
+            "Draw commitment": "Draw commitment",
+            "Draw seed": "Draw seed",
+            "Reveal seed": "Reveal seed",
+            "Late participant": "Late participant",
+            "Add late participant": "Add late participant",
+            "Cannot add late player": "Cannot add late participant",
+            "Participant already exists": "Participant already exists",
+            "Who is leaving ?": "Who is leaving ?",
+            "Replacement (optional)": "Replacement (optional)",
+            "Withdraw": "Withdraw",
+            "These players must reveal again": "These players must reveal again",
         }),
     );
     html! {
@@ -131,9 +411,13 @@ pub fn wrap() -> Html {
 #[function_component(App)]
 pub fn app() -> Html {
     let santa_game_info: UseStateHandle<SantaGameInfo> = use_state(|| SantaGameInfo::default());
+    let player_tokens: UseStateHandle<HashMap<String, String>> =
+        use_state(|| LocalStorage::get(player_tokens_key()).unwrap_or_default());
     let is_loading = use_state(|| true);
 
     let api = Api::new();
+    let is_organizer = api.is_organizer();
+    let game_missing = use_state(|| api.game_id().is_none());
 
     let counter = santa_game_info.deref().players.len();
 
@@ -142,16 +426,38 @@ pub fn app() -> Html {
 
     // load initial data
     let api_clone = api.clone();
+    let player_tokens_clone = player_tokens.clone();
+    let game_missing_clone = game_missing.clone();
     use_effect_with((), move |_| {
         let santa_game_info = santa_game_info_clone.clone();
         is_loading_clone.set(true);
         let api = api_clone.clone();
         wasm_bindgen_futures::spawn_local(async move {
+            if api.game_id().is_none() {
+                return;
+            }
             let santa_game_info_clone = santa_game_info.clone();
-            let response = api.info().await.unwrap().json::<SantaGameInfo>().await;
+            let response = api.info().await.unwrap();
+            if response.status() == 404 {
+                game_missing_clone.set(true);
+                return;
+            }
+            let response = response.json::<SantaGameInfo>().await;
 
             match response {
-                Ok(response) => santa_game_info_clone.set(response),
+                Ok(response) => {
+                    santa_game_info_clone.set(response);
+
+                    // the organizer may be on another browser than the one that added the players
+                    if api.is_organizer() {
+                        if let Ok(response) = api.player_tokens().await {
+                            if let Ok(response) = response.json::<PlayerTokens>().await {
+                                let _ = LocalStorage::set(player_tokens_key(), &response.tokens);
+                                player_tokens_clone.set(response.tokens);
+                            }
+                        }
+                    }
+                }
                 Err(err) => {
                     log!(format!("something bad happend: {}", err));
                 }
@@ -162,20 +468,188 @@ pub fn app() -> Html {
         || {}
     });
 
+    // keep the game info live while the page is open
+    let api_clone = api.clone();
+    let santa_game_info_clone = santa_game_info.clone();
+    use_effect_with((), move |_| {
+        let event_source = api_clone
+            .game_id()
+            .and_then(|_| EventSource::new(&api_clone.events_url()).ok());
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let Some(data) = event.data().as_string() else {
+                return;
+            };
+            match serde_json::from_str::<SantaGameInfo>(&data) {
+                Ok(info) => santa_game_info_clone.set(info),
+                Err(err) => log!(format!("something bad happend: {}", err)),
+            }
+        });
+        if let Some(event_source) = &event_source {
+            event_source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        }
+
+        move || {
+            if let Some(event_source) = event_source {
+                event_source.close();
+            }
+            drop(on_message);
+        }
+    });
+
+    // lobby presence: who has the page open right now
+    let online: UseStateHandle<Vec<String>> = use_state(Vec::new);
+    let api_clone = api.clone();
+    let online_clone = online.clone();
+    use_effect_with((), move |_| {
+        let socket = api_clone
+            .game_id()
+            .and_then(|_| WebSocket::new(&api_clone.lobby_url()).ok());
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let Some(data) = event.data().as_string() else {
+                return;
+            };
+            if let Ok(LobbyMessage::Presence { online }) = serde_json::from_str(&data) {
+                online_clone.set(online);
+            }
+        });
+        // a personal link tells the lobby who is behind this page
+        let socket_clone = socket.clone();
+        let on_open = Closure::<dyn FnMut()>::new(move || {
+            if let (Some(socket), Some(token)) = (&socket_clone, player_token()) {
+                let hello = serde_json::json!({ "type": "hello", "token": token });
+                let _ = socket.send_with_str(&hello.to_string());
+            }
+        });
+        if let Some(socket) = &socket {
+            socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+            socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        }
+
+        move || {
+            if let Some(socket) = socket {
+                let _ = socket.close();
+            }
+            drop(on_message);
+            drop(on_open);
+        }
+    });
+
+    let on_late_join = {
+        let player_tokens = player_tokens.clone();
+        Callback::from(move |(name, token): (String, String)| {
+            let mut tokens = player_tokens.deref().clone();
+            tokens.insert(name, token);
+            let _ = LocalStorage::set(player_tokens_key(), &tokens);
+            player_tokens.set(tokens);
+        })
+    };
+
+    // anyone can check the draw against the commitment once the seed is out
+    let reveal_seed = {
+        let api = api.clone();
+        move |_| {
+            let api = api.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api.reveal_seed().await {
+                    Ok(response) => log!(response.status()),
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        }
+    };
+
+    // moves the game to its next phase, the server answers with the new info
+    let change_phase = {
+        let api = api.clone();
+        let santa_game_info = santa_game_info.clone();
+        Callback::from(move |action: String| {
+            let api = api.clone();
+            let santa_game_info = santa_game_info.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api.change_phase(&action).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(info) = response.json::<SantaGameInfo>().await {
+                            santa_game_info.set(info);
+                        }
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        log!(format!("Error msg : {}", api_response.error));
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+
+    let save_schedule = {
+        let api = api.clone();
+        Callback::from(move |schedule: Schedule| {
+            let api = api.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                // the new dates come back through the event stream
+                match api.set_schedule(&schedule).await {
+                    Ok(response) if response.status() == 200 => {}
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        log!(format!("Error msg : {}", api_response.error));
+                        gloo::dialogs::alert(&api_response.error);
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+
+    let save_event = {
+        let api = api.clone();
+        Callback::from(move |event: EventDetails| {
+            let api = api.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api.set_event(&event).await {
+                    Ok(response) if response.status() == 200 => {}
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        log!(format!("Error msg : {}", api_response.error));
+                        gloo::dialogs::alert(&api_response.error);
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+
     let reset_send = {
         let is_loading_clone = is_loading.clone();
 
         let santa_game_info_clone = santa_game_info.clone();
+        let player_tokens_clone = player_tokens.clone();
 
         move |_| {
             is_loading_clone.set(true);
             let api_clone = api.clone();
 
             let santa_game_info_clone = santa_game_info_clone.clone();
+            let player_tokens_clone = player_tokens_clone.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 let response = api_clone.reset_game().await.unwrap();
                 log!(response.status());
-                santa_game_info_clone.set(SantaGameInfo::default());
+                LocalStorage::delete(player_tokens_key());
+                player_tokens_clone.set(HashMap::new());
+                // players stay, only the draw is gone
+                let santa_game = santa_game_info_clone.deref().clone();
+                let players = santa_game
+                    .players
+                    .into_iter()
+                    .map(|player| Player {
+                        has_picked: false,
+                        ..player
+                    })
+                    .collect();
+                santa_game_info_clone.set(SantaGameInfo {
+                    players,
+                    ..SantaGameInfo::default()
+                });
             });
             is_loading_clone.set(false);
         }
@@ -214,37 +688,103 @@ pub fn app() -> Html {
 
             if *is_loading {
                 <Loading />
+            } else if *game_missing {
+                <CreateGame selected_language={selected_language.deref().clone()} />
             } else {
                 <div class="overflow-y-auto full max-h-screen p-4 text-center bg-white border border-gray-200 rounded-lg shadow sm:p-8 dark:bg-gray-800 dark:border-gray-700">
                     <div class="flex flex-col pb-10">
-                        <h1 class="mb-1 text-xl font-medium text-gray-900 dark:text-white">{&i18n.t("Secret Santa")}</h1>
+                        <h1 class="mb-1 text-xl font-medium text-gray-900 dark:text-white">{
+                            santa_game_info.event.title.clone().unwrap_or_else(|| i18n.t("Secret Santa"))
+                        }</h1>
+                        <EventHeader
+                            event={santa_game_info.event.clone()}
+                            selected_language={selected_language.deref().clone()}
+                        />
                         <span class="text-sm text-gray-500 dark:text-gray-400"> { format!("{} {}", &i18n.t("Amount of participants"), counter) }</span>
+                        <ScheduleBanner
+                            schedule={santa_game_info.schedule}
+                            selected_language={selected_language.deref().clone()}
+                        />
+                        if is_organizer && !matches!(santa_game_info.status, GameStatus::Closed | GameStatus::Archived) {
+                            <ScheduleForm
+                                schedule={santa_game_info.schedule}
+                                on_submit={save_schedule}
+                                label={i18n.t("Save dates")}
+                                selected_language={selected_language.deref().clone()}
+                            />
+                        }
+                        if is_organizer && santa_game_info.status != GameStatus::Archived {
+                            <EventForm
+                                event={santa_game_info.event.clone()}
+                                on_submit={save_event}
+                                selected_language={selected_language.deref().clone()}
+                            />
+                        }
+                        if is_organizer {
+                            <Webhooks selected_language={selected_language.deref().clone()} />
+                        }
                         <div class="flex mt-4 md:mt-6">
                         </div>
 
                         {match santa_game_info.deref().status {
-                            GameStatus::NotStarted => {
+                            GameStatus::Open | GameStatus::Draft => {
                                 html! {
-                                    <InitGame santa_game_info={santa_game_info.clone()} selected_language={selected_language.deref().clone()} />
+                                    <InitGame
+                                        santa_game_info={santa_game_info.clone()}
+                                        player_tokens={player_tokens.clone()}
+                                        online={online.deref().clone()}
+                                        on_phase={change_phase.clone()}
+                                        selected_language={selected_language.deref().clone()}
+                                    />
                                 }
                             }
-                            GameStatus::InProgress => {
+                            GameStatus::Drawn | GameStatus::Revealing => {
                                 html! {
                                     <InProgressGame
+                                        status={santa_game_info.status.clone()}
+                                        on_phase={change_phase.clone()}
                                         participants={santa_game_info.players.clone()}
+                                        player_tokens={player_tokens.deref().clone()}
+                                        on_late_join={on_late_join.clone()}
                                         selected_language={selected_language.deref().clone()}
                                     />
                                 }
                             }
-                            GameStatus::Finished => {
+                            GameStatus::Archived => {
+                                html! {
+                                    <h3 class="text-3xl font-bold dark:text-white">{ &i18n.t("Game archived")}</h3>
+                                }
+                            }
+                            GameStatus::Closed => {
+                                let archive = {
+                                    let change_phase = change_phase.clone();
+                                    move |_| change_phase.emit("archive".to_string())
+                                };
                                 html! {
                                     <>
                                         <h3 class="text-3xl font-bold dark:text-white">{ &i18n.t("Game Finished")}</h3>
-                                        <button class="mt-10 px-6 py-3.5 text-base font-medium text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800" onclick={reset_send} type="submit">{&i18n.t("Restart Game") }</button>
+                                        if is_organizer {
+                                            <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={archive} type="button">{&i18n.t("Archive") }</button>
+                                            if santa_game_info.seed.is_none() {
+                                                <button class="mt-10 px-6 py-3.5 text-base font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={reveal_seed} type="button">{&i18n.t("Reveal seed") }</button>
+                                            }
+                                            <button class="mt-10 px-6 py-3.5 text-base font-medium text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800" onclick={reset_send} type="submit">{&i18n.t("Restart Game") }</button>
+                                        }
                                     </>
                                 }
                             }
                         }}
+
+                        if let Some(commitment) = &santa_game_info.commitment {
+                            <p class="mt-6 text-xs text-gray-500 dark:text-gray-400 break-all font-mono">
+                                { format!("{}: {}", &i18n.t("Draw commitment"), commitment) }
+                            </p>
+                        }
+                        if let Some(seed) = &santa_game_info.seed {
+                            <p class="text-xs text-gray-500 dark:text-gray-400 break-all font-mono">
+                                { format!("{}: {}", &i18n.t("Draw seed"), seed) }
+                            </p>
+                        }
                     </div>
                 </div>
             }
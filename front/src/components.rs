@@ -1,15 +1,20 @@
-use crate::api::Api;
-use crate::app::{ApiError, GameStatus, Player, PlayersCreate, SantaGameInfo};
+use crate::api::{game_id, player_token, Api};
+use crate::app::{
+    player_tokens_key, AddPlayersReport, ApiError, EventDetails, GameStatus, NewGame, Player,
+    PlayerTokens, SantaGameInfo, Schedule,
+};
 use gloo::console::log;
 use gloo::dialogs::alert;
+use gloo::storage::{LocalStorage, Storage};
+use js_sys::Date;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::Deref;
-use wasm_bindgen::JsCast;
-use web_sys::{Url, window, HtmlInputElement};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{window, HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement, Url};
 use yew::{function_component, html, prelude::*, Html};
-use yew_i18n::use_translation;
 use yew_hooks::prelude::*;
+use yew_i18n::use_translation;
 
 #[function_component(Confetti)]
 pub fn confetti() -> Html {
@@ -30,18 +35,307 @@ pub fn confetti() -> Html {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsCreateGame {
+    pub selected_language: String,
+}
+
+#[function_component(CreateGame)]
+pub fn create_game(props: &PropsCreateGame) -> Html {
+    let api = Api::new();
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    let on_submit = Callback::from(move |schedule: Schedule| {
+        let api = api.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match api.create_game(&schedule).await {
+                Ok(response) if response.status() == 200 => {
+                    let new_game = response.json::<NewGame>().await.unwrap();
+                    // the organizer lands on the new game with its admin secret
+                    let search = format!("?game={}&admin={}", new_game.id, new_game.admin_token);
+                    let _ = window().unwrap().location().set_search(&search);
+                }
+                Ok(response) => {
+                    let api_response = response.json::<ApiError>().await.unwrap();
+                    log!(format!("Error msg : {}", api_response.error));
+                }
+                Err(err) => {
+                    log!(format!("something bad happend: {}", err));
+                    alert("Erro no servidor");
+                }
+            }
+        });
+    });
+
+    html! {
+        <div class="flex flex-col items-center">
+            if game_id().is_some() {
+                <span class="text-sm text-gray-500 dark:text-gray-400"> { &i18n.t("Game not found") }</span>
+            }
+            <ScheduleForm
+                schedule={Schedule::default()}
+                {on_submit}
+                label={i18n.t("Create a new game")}
+                selected_language={props.selected_language.clone()}
+            />
+        </div>
+    }
+}
+
+// datetime-local inputs speak the browser's time zone
+fn to_timestamp(value: &str) -> Option<u64> {
+    if value.is_empty() {
+        return None;
+    }
+    let millis = Date::new(&JsValue::from_str(value)).get_time();
+    (!millis.is_nan()).then(|| (millis / 1000.0) as u64)
+}
+
+fn to_input_value(timestamp: Option<u64>) -> String {
+    let Some(timestamp) = timestamp else {
+        return String::new();
+    };
+    let date = Date::new(&JsValue::from_f64(timestamp as f64 * 1000.0));
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}",
+        date.get_full_year(),
+        date.get_month() + 1,
+        date.get_date(),
+        date.get_hours(),
+        date.get_minutes()
+    )
+}
+
+fn format_date(timestamp: u64, language: &str) -> String {
+    Date::new(&JsValue::from_f64(timestamp as f64 * 1000.0))
+        .to_locale_string(&language.replace('_', "-"), &JsValue::UNDEFINED)
+        .into()
+}
+
+fn countdown(seconds: u64) -> String {
+    format!(
+        "{}d {:02}h {:02}m {:02}s",
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsScheduleForm {
+    pub schedule: Schedule,
+    pub on_submit: Callback<Schedule>,
+    pub label: String,
+    pub selected_language: String,
+}
+
+#[function_component(ScheduleForm)]
+pub fn schedule_form(props: &PropsScheduleForm) -> Html {
+    let reveals_at = use_state(|| to_input_value(props.schedule.reveals_at));
+    let exchange_at = use_state(|| to_input_value(props.schedule.exchange_at));
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    let on_input = |state: UseStateHandle<String>| {
+        Callback::from(move |event: Event| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            state.set(input.value());
+        })
+    };
+    let onsubmit = {
+        let reveals_at = reveals_at.clone();
+        let exchange_at = exchange_at.clone();
+        let on_submit = props.on_submit.clone();
+        let i18n = i18n.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            let schedule = Schedule {
+                reveals_at: to_timestamp(&reveals_at),
+                exchange_at: to_timestamp(&exchange_at),
+            };
+            if let (Some(reveals_at), Some(exchange_at)) =
+                (schedule.reveals_at, schedule.exchange_at)
+            {
+                if reveals_at >= exchange_at {
+                    alert(&i18n.t("Reveals must open before the gift exchange"));
+                    return;
+                }
+            }
+            on_submit.emit(schedule);
+        })
+    };
+
+    html! {
+        <form class="mt-6 flex flex-col items-center gap-2" {onsubmit}>
+            <label class="text-sm text-gray-500 dark:text-gray-400">
+                { &i18n.t("Reveals open on") }
+                <input type="datetime-local" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white ml-2" value={reveals_at.deref().clone()} onchange={on_input(reveals_at.clone())} />
+            </label>
+            <label class="text-sm text-gray-500 dark:text-gray-400">
+                { &i18n.t("Gift exchange on") }
+                <input type="datetime-local" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white ml-2" value={exchange_at.deref().clone()} onchange={on_input(exchange_at.clone())} />
+            </label>
+            <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" type="submit">{ &props.label }</button>
+        </form>
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsEventHeader {
+    pub event: EventDetails,
+    pub selected_language: String,
+}
+
+// Budget, place, theme and rules under the event title.
+#[function_component(EventHeader)]
+pub fn event_header(props: &PropsEventHeader) -> Html {
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+    let event = &props.event;
+    let line = |label: &str, value: Option<String>| match value {
+        Some(value) => html! {
+            <span class="text-sm text-gray-500 dark:text-gray-400">
+                { format!("{}: ", i18n.t(label)) }
+                <span class="font-medium text-gray-900 dark:text-white">{ value }</span>
+            </span>
+        },
+        None => html! {},
+    };
+
+    html! {
+        <div class="mt-1 flex flex-col">
+            { line("Budget", event.budget()) }
+            { line("Location", event.location.clone()) }
+            { line("Theme", event.theme.clone()) }
+            if let Some(rules) = &event.rules {
+                <p class="mt-2 text-sm text-gray-500 dark:text-gray-400 whitespace-pre-line">{ rules }</p>
+            }
+        </div>
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsEventForm {
+    pub event: EventDetails,
+    pub on_submit: Callback<EventDetails>,
+    pub selected_language: String,
+}
+
+#[function_component(EventForm)]
+pub fn event_form(props: &PropsEventForm) -> Html {
+    let event = use_state(|| props.event.clone());
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    let on_text = |set: fn(&mut EventDetails, Option<String>)| {
+        let event = event.clone();
+        Callback::from(move |input_event: Event| {
+            let input: HtmlInputElement = input_event.target_unchecked_into();
+            let mut details = event.deref().clone();
+            set(&mut details, optional(input.value()));
+            event.set(details);
+        })
+    };
+    let on_amount = |set: fn(&mut EventDetails, Option<u32>)| {
+        let event = event.clone();
+        Callback::from(move |input_event: Event| {
+            let input: HtmlInputElement = input_event.target_unchecked_into();
+            let mut details = event.deref().clone();
+            set(&mut details, input.value().trim().parse().ok());
+            event.set(details);
+        })
+    };
+    let on_rules = {
+        let event = event.clone();
+        Callback::from(move |input_event: Event| {
+            let input: HtmlTextAreaElement = input_event.target_unchecked_into();
+            let mut details = event.deref().clone();
+            details.rules = optional(input.value());
+            event.set(details);
+        })
+    };
+    let onsubmit = {
+        let event = event.clone();
+        let on_submit = props.on_submit.clone();
+        Callback::from(move |submit: SubmitEvent| {
+            submit.prevent_default();
+            on_submit.emit(event.deref().clone());
+        })
+    };
+    let amount = |value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+
+    html! {
+        <form class="mt-6 flex flex-col items-center gap-2" {onsubmit}>
+            <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Event title")} value={event.title.clone().unwrap_or_default()} onchange={on_text(|event, value| event.title = value)} />
+            <div class="flex gap-2">
+                <input type="number" min="0" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white w-32" placeholder={i18n.t("Minimum budget")} value={amount(event.budget_min)} onchange={on_amount(|event, value| event.budget_min = value)} />
+                <input type="number" min="0" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white w-32" placeholder={i18n.t("Maximum budget")} value={amount(event.budget_max)} onchange={on_amount(|event, value| event.budget_max = value)} />
+                <input type="text" maxlength="3" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white w-20" placeholder={i18n.t("Currency")} value={event.currency.clone().unwrap_or_default()} onchange={on_text(|event, value| event.currency = value)} />
+            </div>
+            <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Location")} value={event.location.clone().unwrap_or_default()} onchange={on_text(|event, value| event.location = value)} />
+            <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Theme")} value={event.theme.clone().unwrap_or_default()} onchange={on_text(|event, value| event.theme = value)} />
+            <textarea class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white w-full max-w-[24rem]" rows="3" placeholder={i18n.t("Rules")} value={event.rules.clone().unwrap_or_default()} onchange={on_rules} />
+            <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" type="submit">{ &i18n.t("Save event details") }</button>
+        </form>
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsScheduleBanner {
+    pub schedule: Schedule,
+    pub selected_language: String,
+}
+
+// Dates of the game, with a countdown to those still ahead.
+#[function_component(ScheduleBanner)]
+pub fn schedule_banner(props: &PropsScheduleBanner) -> Html {
+    let now = use_state(|| (Date::now() / 1000.0) as u64);
+    {
+        let now = now.clone();
+        use_interval(move || now.set((Date::now() / 1000.0) as u64), 1000);
+    }
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    let line = |label: &str, timestamp: Option<u64>| match timestamp {
+        Some(timestamp) => html! {
+            <span class="text-sm text-gray-500 dark:text-gray-400">
+                { format!("{} {}", i18n.t(label), format_date(timestamp, &props.selected_language)) }
+                if timestamp > *now {
+                    <span class="ml-2 font-medium text-gray-900 dark:text-white">{ countdown(timestamp - *now) }</span>
+                }
+            </span>
+        },
+        None => html! {},
+    };
+
+    html! {
+        <div class="mt-2 flex flex-col">
+            { line("Reveals open on", props.schedule.reveals_at) }
+            { line("Gift exchange on", props.schedule.exchange_at) }
+        </div>
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Properties)]
 pub struct PropsStartGame {
     pub santa_game_info: UseStateHandle<SantaGameInfo>,
+    pub player_tokens: UseStateHandle<HashMap<String, String>>,
+    pub online: Vec<String>,
+    pub on_phase: Callback<String>,
     pub selected_language: String,
 }
 
 #[function_component(InitGame)]
 pub fn init_game(props: &PropsStartGame) -> Html {
     let api = Api::new();
+    let is_organizer = api.is_organizer();
     let error_msg: UseStateHandle<Option<String>> = use_state(|| None);
     let sante_game_info = props.santa_game_info.clone();
     let participant_name: UseStateHandle<String> = use_state(|| "".to_string());
+    let participant_email: UseStateHandle<String> = use_state(|| "".to_string());
     let is_loading: UseStateHandle<bool> = use_state(|| false);
     let mut i18n = use_translation();
     let _ = i18n.set_translation_language(&props.selected_language);
@@ -56,22 +350,50 @@ pub fn init_game(props: &PropsStartGame) -> Html {
         })
     };
 
+    let participant_email_on_change = {
+        let participant_email = participant_email.clone();
+        Callback::from(move |event: Event| {
+            let input: HtmlInputElement = event.target().unwrap().unchecked_into();
+            participant_email.set(input.value());
+        })
+    };
+
     let start_game = {
         let santa_game_info_clone = sante_game_info.clone();
+        let player_tokens_clone = props.player_tokens.clone();
         let api = api.clone();
+        let language = props.selected_language.clone();
+        let could_not_email = i18n.t("Could not email");
         move |_| {
             let santa_game_info_clone = santa_game_info_clone.clone();
+            let player_tokens_clone = player_tokens_clone.clone();
             let api = api.clone();
+            let language = language.clone();
+            let could_not_email = could_not_email.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 let api = api.clone();
-                let response = api.start_game().await.unwrap();
+                let response = api.start_game(&language).await.unwrap();
                 if response.status() != 200 {
                     let api_response = response.json::<ApiError>().await.unwrap();
                     log!(format!("Error msg : {}", api_response.error));
                 } else {
+                    let PlayerTokens {
+                        tokens: player_tokens,
+                        emails,
+                    } = response.json::<PlayerTokens>().await.unwrap();
+                    if let Some(emails) = emails.filter(|emails| !emails.failed.is_empty()) {
+                        let names: Vec<String> = emails
+                            .failed
+                            .into_iter()
+                            .map(|failure| failure.name)
+                            .collect();
+                        alert(&format!("{} {}", could_not_email, names.join(", ")));
+                    }
+                    let _ = LocalStorage::set(player_tokens_key(), &player_tokens);
+                    player_tokens_clone.set(player_tokens);
                     let santa_game = santa_game_info_clone.deref().clone();
                     santa_game_info_clone.set(SantaGameInfo {
-                        status: GameStatus::InProgress,
+                        status: GameStatus::Revealing,
                         ..santa_game
                     });
                 }
@@ -81,8 +403,10 @@ pub fn init_game(props: &PropsStartGame) -> Html {
 
     let onsubmit = {
         let participant_name_clone = participant_name.clone();
+        let participant_email = participant_email.clone();
         let is_loading_clone = is_loading.clone();
         let santa_game_info_clone = sante_game_info.clone();
+        let player_tokens = props.player_tokens.clone();
         let error_msg_clone = error_msg.clone();
         let api = api.clone();
 
@@ -108,36 +432,59 @@ pub fn init_game(props: &PropsStartGame) -> Html {
             }
 
             let participant_name_clone = participant_name_clone.clone();
+            let participant_email = participant_email.clone();
             let is_loading_clone = is_loading_clone.clone();
             let santa_game_info_clone = santa_game_info_clone.clone();
+            let player_tokens = player_tokens.clone();
             let api = api.clone();
 
             is_loading_clone.set(true);
             wasm_bindgen_futures::spawn_local(async move {
-                let response = api.add_player(&participant_name_clone.deref()).await;
+                let response = api
+                    .add_player(&participant_name_clone.deref(), &participant_email)
+                    .await;
                 let error_msg_clone = error_msg_clone.clone();
 
                 match response {
                     Ok(response) => {
-                        if response.status() != 200 {
-                            let api_response = response.json::<ApiError>().await.unwrap();
-                            log!(format!("Error msg : {}", api_response.error));
-                            if api_response.error.contains("already exists") {
-                                error_msg_clone.set(Some(
-                                    "Participante já existe, tente outro nome".to_string(),
-                                ));
+                        let report = response.json::<AddPlayersReport>().await.unwrap();
+                        if !report.applied {
+                            let (error, code) = report.rejection();
+                            log!(format!("Error msg : {}", error));
+                            match code.as_str() {
+                                "player_already_exists" | "duplicate_player" => error_msg_clone
+                                    .set(Some(
+                                        "Participante já existe, tente outro nome".to_string(),
+                                    )),
+                                "empty_player_name" => {
+                                    error_msg_clone.set(Some("Nome não pode ser vazio".to_string()))
+                                }
+                                _ => error_msg_clone.set(Some(error)),
                             }
                             return;
                         }
-                        let response = response.json::<PlayersCreate>().await.unwrap();
-                        log!(serde_json::to_string_pretty(&response).unwrap());
+                        log!(serde_json::to_string_pretty(&report).unwrap());
+                        let Some(added) = report.players.into_iter().next() else {
+                            return;
+                        };
+                        if let Some(warning) = &added.warning {
+                            alert(warning);
+                        }
+                        if let Some(token) = &added.token {
+                            let mut tokens = player_tokens.deref().clone();
+                            tokens.insert(added.name.clone(), token.clone());
+                            let _ = LocalStorage::set(player_tokens_key(), &tokens);
+                            player_tokens.set(tokens);
+                        }
                         // update participants
                         let participant_name = participant_name_clone.deref().clone();
                         participant_name_clone.set("".to_string());
+                        participant_email.set("".to_string());
                         // update game info
                         let mut participant_list = santa_game_info_clone.deref().clone().players;
                         let player = Player {
-                            name: participant_name.clone(),
+                            name: added.name,
+                            display_name: participant_name.trim().to_string(),
                             has_picked: false,
                         };
 
@@ -187,12 +534,32 @@ pub fn init_game(props: &PropsStartGame) -> Html {
         });
     });
 
+    let registration_open = props.santa_game_info.status == GameStatus::Open;
+    let phase_button = |action: &'static str| {
+        let on_phase = props.on_phase.clone();
+        Callback::from(move |_: MouseEvent| on_phase.emit(action.to_string()))
+    };
+
+    // the organizer hands out the personal links, they open the lobby
+    let clipboard = use_clipboard();
+    let copy_link = {
+        let player_tokens = props.player_tokens.clone();
+        let i18n = i18n.clone();
+        let link = format!("{}?game={}", get_url(), game_id().unwrap_or_default());
+        Callback::from(move |name: String| {
+            if let Some(token) = player_tokens.get(&name) {
+                clipboard.write_text(format!("{}&token={}", link, token));
+                alert(&i18n.t("Copied to the clipboard"));
+            }
+        })
+    };
+
     // for better visualization
     let sante_game_info_clone = props.santa_game_info.clone();
     let mut new_participants = HashMap::new();
     for (id, player) in sante_game_info_clone.deref().players.iter().enumerate() {
         let id = id + 1;
-        new_participants.insert(id as i32, player.name.clone());
+        new_participants.insert(id as i32, (player.name.clone(), player.label()));
     }
     let mut keys = new_participants.keys().collect::<Vec<&i32>>();
     keys.sort();
@@ -209,6 +576,11 @@ pub fn init_game(props: &PropsStartGame) -> Html {
 
             <div class="flex justify-center">
                 <div class="w-full max-w-[48rem]">
+                    if !is_organizer {
+                        <span class="text-sm text-gray-500 dark:text-gray-400"> { &i18n.t("Waiting for the organizer") }</span>
+                    } else if !registration_open {
+                        <span class="text-sm text-gray-500 dark:text-gray-400"> { &i18n.t("Registration is closed") }</span>
+                    } else {
                     <span class="text-sm text-gray-500 dark:text-gray-400"> { &i18n.t("Enter the names of the participants") }</span>
                         <form class="mt-4" onsubmit={onsubmit}>
                                 <div class="flex">
@@ -219,29 +591,45 @@ pub fn init_game(props: &PropsStartGame) -> Html {
                                         </button>
                                 </div>
                             </div>
+                            <input onchange={participant_email_on_change} value={participant_email.deref().clone()} type="email" class="mt-2 block p-2.5 w-full text-sm text-gray-900 bg-gray-50 rounded-lg border border-gray-300 focus:ring-blue-500 focus:border-blue-500 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white" placeholder={i18n.t("Email (optional)")}/>
 
                             if let Some(error_msg) = error_msg.deref() {
                                     <p class="mt-2 text-sm text-red-600 dark:text-red-500"><span class="font-medium">{"Ops! "}</span>{error_msg}</p>
                             }
                         </form>
+                    }
                     <div class="flow-root">
                         <ul role="list" class="divide-y divide-gray-200 dark:divide-gray-700">
                             {for keys.iter().map(|key| {
-                                let participant_name = new_participants.get(key).unwrap().clone();
+                                let (participant_name, participant_label) =
+                                    new_participants.get(key).unwrap().clone();
                                 let participant_name_clone = participant_name.clone();
                                 let remove_player = remove_player.clone();
+                                let has_link = props.player_tokens.contains_key(&participant_name);
+                                let copy_link = copy_link.clone();
+                                let link_name = participant_name.clone();
                                 return html! {
                                     <li class="py-3 sm:py-4 hover:bg-gray-50 dark:hover:bg-gray-700">
                                         <div class="flex items-center justify-between">
                                             <p class="text-sm font-medium text-gray-900 truncate dark:text-white ml-2">
-                                                {participant_name.clone()}
+                                                if props.online.contains(&participant_name) {
+                                                    <span class="inline-block w-2 h-2 mr-2 bg-green-500 rounded-full" title={i18n.t("Online now")}></span>
+                                                }
+                                                {participant_label}
                                             </p>
+                                            if is_organizer && has_link {
+                                            <button onclick={move |_| copy_link.emit(link_name.clone())} type="button" class="ml-auto mr-2 px-3 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-700 hover:text-white rounded-lg dark:border-blue-500 dark:text-blue-500">
+                                                {&i18n.t("Copy link")}
+                                            </button>
+                                            }
+                                            if is_organizer && registration_open {
                                             <button onclick={move |_| remove_player.emit(participant_name_clone.clone())} type="button" class=" text-blue-700 border border-blue-700 hover:bg-blue-700 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-full text-sm p-2.5 text-centeritems-center dark:border-blue-500 dark:text-blue-500 dark:hover:text-white dark:focus:ring-blue-800 dark:hover:bg-blue-500 mr-2">
                                                 <svg xmlns="http://www.w3.org/2000/svg" width="20" height="20" viewBox="0 0 24 24" fill="none">
     <path d="M4 7H20M10 10V18M14 10V18M10 3H14C14.2652 3 14.5196 3.10536 14.7071 3.29289C14.8946 3.48043 15 3.73478 15 4V7H9V4C9 3.73478 9.10536 3.48043 9.29289 3.29289C9.48043 3.10536 9.73478 3 10 3ZM6 7H18V20C18 20.2652 17.8946 20.5196 17.7071 20.7071C17.5196 20.8946 17.2652 21 17 21H7C6.73478 21 6.48043 20.8946 6.29289 20.7071C6.10536 20.5196 6 20.2652 6 20V7Z" stroke="white" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"/>
     </svg>
                                                 <span class="sr-only">{&i18n.t("Remover participante")}</span>
                                             </button>
+                                            }
                                         </div>
                                     </li>
                                 }
@@ -251,7 +639,18 @@ pub fn init_game(props: &PropsStartGame) -> Html {
                         </ul>
                     </div>
 
-                    {if keys.len() > 2 {
+                    if is_organizer {
+                        <div class="flex justify-center gap-2">
+                            if registration_open {
+                                <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={phase_button("close-registration")} type="button">{ &i18n.t("Close registration") }</button>
+                                <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={phase_button("clear-players")} type="button">{ &i18n.t("Clear players") }</button>
+                            } else {
+                                <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={phase_button("reopen-registration")} type="button">{ &i18n.t("Reopen registration") }</button>
+                            }
+                        </div>
+                    }
+
+                    {if is_organizer && keys.len() > 2 {
                         html! {
                             <div class="flex justify-center mt-4">
                                 <button class="px-6 py-3.5 text-base font-medium text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800" onclick={start_game}>{ &i18n.t("Start Game") }</button>
@@ -268,15 +667,376 @@ pub fn init_game(props: &PropsStartGame) -> Html {
 
 #[derive(Debug, PartialEq, Clone, Properties)]
 pub struct PropsInProgressGame {
+    pub status: GameStatus,
+    pub on_phase: Callback<String>,
     pub participants: Vec<Player>,
+    pub player_tokens: HashMap<String, String>,
+    pub on_late_join: Callback<(String, String)>,
     pub selected_language: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LateJoin {
+    pub name: String,
+    pub token: String,
+    pub reassigned: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WithdrawalReport {
+    pub reassigned: Vec<String>,
+    pub reveal_again: Vec<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Person {
+    // one name per gift the player has to buy
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub recipients: Vec<Recipient>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Recipient {
     pub name: String,
+    #[serde(default)]
+    pub wishlist: Vec<WishlistItem>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct WishlistItem {
+    pub title: String,
+    #[serde(default)]
+    pub link: Option<String>,
+    #[serde(default)]
+    pub price_hint: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Wishlist {
+    pub items: Vec<WishlistItem>,
+}
+
+fn wishlist_view(items: &[WishlistItem], empty: String) -> Html {
+    if items.is_empty() {
+        return html! {
+            <p class="text-sm text-gray-500 dark:text-gray-400">{ empty }</p>
+        };
+    }
+    html! {
+        <ul class="text-left text-sm text-gray-700 dark:text-gray-300 list-disc list-inside">
+        { for items.iter().map(|item| html! {
+            <li>
+                if let Some(link) = &item.link {
+                    <a class="font-medium text-blue-600 dark:text-blue-500 hover:underline" href={link.clone()} target="_blank" rel="noopener noreferrer">{ &item.title }</a>
+                } else {
+                    <span class="font-medium">{ &item.title }</span>
+                }
+                if let Some(price_hint) = &item.price_hint {
+                    <span class="ml-2 text-gray-500 dark:text-gray-400">{ format!("({})", price_hint) }</span>
+                }
+                if let Some(notes) = &item.notes {
+                    <p class="ml-5 text-gray-500 dark:text-gray-400">{ notes }</p>
+                }
+            </li>
+        })}
+        </ul>
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ThreadMessage {
+    pub mine: bool,
+    pub text: String,
+    pub sent_at: u64,
+}
+
+// `with` is the recipient's name, or an id standing for an anonymous Santa
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Thread {
+    pub with: String,
+    pub messages: Vec<ThreadMessage>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Conversations {
+    pub recipients: Vec<Thread>,
+    pub santas: Vec<Thread>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewMessage {
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsMessages {
+    pub token: String,
+    pub selected_language: String,
+}
+
+// Anonymous threads: the player writes to their recipients by name and to
+// their Santas without ever learning who they are.
+#[function_component(Messages)]
+pub fn messages(props: &PropsMessages) -> Html {
+    let conversations = use_state(Conversations::default);
+    let drafts: UseStateHandle<HashMap<String, String>> = use_state(HashMap::new);
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    let load = {
+        let conversations = conversations.clone();
+        let token = props.token.clone();
+        move || {
+            let conversations = conversations.clone();
+            let token = token.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Api::new().messages(&token).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(loaded) = response.json::<Conversations>().await {
+                            conversations.set(loaded);
+                        }
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        log!(format!("Error msg : {}", api_response.error));
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        }
+    };
+    {
+        let load = load.clone();
+        use_effect_with((), move |_| load());
+    }
+    // replies come in while the page is open
+    use_interval(load, 15_000);
+
+    let send = {
+        let conversations = conversations.clone();
+        let drafts = drafts.clone();
+        let token = props.token.clone();
+        Callback::from(move |to: String| {
+            let text = drafts.get(&to).cloned().unwrap_or_default();
+            if text.trim().is_empty() {
+                return;
+            }
+            let conversations = conversations.clone();
+            let drafts = drafts.clone();
+            let token = token.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let message = NewMessage { text };
+                match Api::new().send_message(&token, &to, &message).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(loaded) = response.json::<Conversations>().await {
+                            conversations.set(loaded);
+                        }
+                        let mut cleared = drafts.deref().clone();
+                        cleared.remove(&to);
+                        drafts.set(cleared);
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        alert(&api_response.error);
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+
+    let thread_view = |title: String, to: String, thread: &Thread| {
+        let on_draft = {
+            let drafts = drafts.clone();
+            let to = to.clone();
+            Callback::from(move |event: Event| {
+                let input: HtmlInputElement = event.target_unchecked_into();
+                let mut updated = drafts.deref().clone();
+                updated.insert(to.clone(), input.value());
+                drafts.set(updated);
+            })
+        };
+        let onsubmit = {
+            let send = send.clone();
+            let to = to.clone();
+            Callback::from(move |event: SubmitEvent| {
+                event.prevent_default();
+                send.emit(to.clone());
+            })
+        };
+        html! {
+            <div class="mt-4 w-full max-w-[28rem]">
+                <h4 class="mb-2 text-base font-medium text-gray-900 dark:text-white">{ title }</h4>
+                <ul class="flex flex-col gap-1 text-sm">
+                { for thread.messages.iter().map(|message| {
+                    let side = if message.mine {
+                        "self-end bg-blue-600 text-white"
+                    } else {
+                        "self-start bg-gray-100 text-gray-900 dark:bg-gray-700 dark:text-white"
+                    };
+                    html! {
+                        <li class={classes!("px-3", "py-1", "rounded-lg", side)}>{ &message.text }</li>
+                    }
+                })}
+                </ul>
+                <form class="mt-2 flex gap-2" {onsubmit}>
+                    <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white flex-1" placeholder={i18n.t("Write a message")} value={drafts.get(&to).cloned().unwrap_or_default()} onchange={on_draft} />
+                    <button class="px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" type="submit">{ &i18n.t("Send") }</button>
+                </form>
+            </div>
+        }
+    };
+
+    let several_santas = conversations.santas.len() > 1;
+    html! {
+        if !conversations.recipients.is_empty() || !conversations.santas.is_empty() {
+            <div class="mt-10 flex flex-col items-center">
+                <h3 class="mb-2 text-xl font-medium text-gray-900 dark:text-white">{ &i18n.t("Messages") }</h3>
+                { for conversations.recipients.iter().map(|thread| {
+                    thread_view(thread.with.clone(), format!("recipient/{}", thread.with), thread)
+                })}
+                { for conversations.santas.iter().enumerate().map(|(index, thread)| {
+                    let title = if several_santas {
+                        format!("{} {}", i18n.t("My Santa"), index + 1)
+                    } else {
+                        i18n.t("My Santa")
+                    };
+                    thread_view(title, format!("santa/{}", thread.with), thread)
+                })}
+            </div>
+        }
+    }
+}
+
+fn optional(value: String) -> Option<String> {
+    (!value.trim().is_empty()).then_some(value)
 }
 
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsWishlistEditor {
+    pub token: String,
+    pub selected_language: String,
+}
+
+// The participant's own wishlist, their personal link is what lets them in.
+#[function_component(WishlistEditor)]
+pub fn wishlist_editor(props: &PropsWishlistEditor) -> Html {
+    let items: UseStateHandle<Vec<WishlistItem>> = use_state(Vec::new);
+    let draft: UseStateHandle<WishlistItem> = use_state(WishlistItem::default);
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    {
+        let items = items.clone();
+        let token = props.token.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                match Api::new().wishlist(&token).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(wishlist) = response.json::<Wishlist>().await {
+                            items.set(wishlist.items);
+                        }
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        log!(format!("Error msg : {}", api_response.error));
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        });
+    }
+
+    let save = {
+        let token = props.token.clone();
+        let i18n = i18n.clone();
+        move |wishlist: Vec<WishlistItem>, items: UseStateHandle<Vec<WishlistItem>>| {
+            let token = token.clone();
+            let i18n = i18n.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let wishlist = Wishlist { items: wishlist };
+                match Api::new().set_wishlist(&token, &wishlist).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(saved) = response.json::<Wishlist>().await {
+                            items.set(saved.items);
+                        }
+                        alert(&i18n.t("Wishlist saved"));
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        alert(&api_response.error);
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        }
+    };
+
+    let on_field = |set: fn(&mut WishlistItem, String)| {
+        let draft = draft.clone();
+        Callback::from(move |event: Event| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            let mut item = draft.deref().clone();
+            let value = input.value();
+            set(&mut item, value);
+            draft.set(item);
+        })
+    };
+
+    let add_item = {
+        let items = items.clone();
+        let draft = draft.clone();
+        let save = save.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            if draft.title.trim().is_empty() {
+                return;
+            }
+            let mut wishlist = items.deref().clone();
+            wishlist.push(draft.deref().clone());
+            draft.set(WishlistItem::default());
+            save(wishlist, items.clone());
+        })
+    };
+
+    let remove_item = {
+        let items = items.clone();
+        Callback::from(move |index: usize| {
+            let mut wishlist = items.deref().clone();
+            wishlist.remove(index);
+            save(wishlist, items.clone());
+        })
+    };
+
+    html! {
+        <div class="mt-10 flex flex-col items-center">
+            <h3 class="mb-2 text-xl font-medium text-gray-900 dark:text-white">{ &i18n.t("My wishlist") }</h3>
+            <ul class="text-sm text-gray-700 dark:text-gray-300">
+            { for items.iter().enumerate().map(|(index, item)| {
+                let remove_item = remove_item.clone();
+                html! {
+                    <li class="flex items-center gap-2">
+                        { wishlist_view(std::slice::from_ref(item), String::new()) }
+                        <button onclick={move |_| remove_item.emit(index)} type="button" class="text-gray-500 hover:text-red-600">{ "✕" }</button>
+                    </li>
+                }
+            })}
+            </ul>
+            <form class="mt-2 flex flex-col gap-2 w-full max-w-[24rem]" onsubmit={add_item}>
+                <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Title")} value={draft.title.clone()} onchange={on_field(|item, value| item.title = value)} required=true />
+                <input type="url" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Link")} value={draft.link.clone().unwrap_or_default()} onchange={on_field(|item, value| item.link = optional(value))} />
+                <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Price hint")} value={draft.price_hint.clone().unwrap_or_default()} onchange={on_field(|item, value| item.price_hint = optional(value))} />
+                <input type="text" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white" placeholder={i18n.t("Notes")} value={draft.notes.clone().unwrap_or_default()} onchange={on_field(|item, value| item.notes = optional(value))} />
+                <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" type="submit">{ &i18n.t("Add item") }</button>
+            </form>
+        </div>
+    }
+}
 
 fn get_url() -> String {
     if cfg!(debug_assertions) {
@@ -301,40 +1061,179 @@ fn get_url() -> String {
 
 #[function_component(InProgressGame)]
 pub fn in_progress(props: &PropsInProgressGame) -> Html {
-    let partcipant_selected: UseStateHandle<String> = use_state(|| "".to_string());
+    let player_token: Option<String> = player_token();
     let sorted_participant: UseStateHandle<Option<Person>> = use_state(|| None);
     let mut i18n = use_translation();
     let _ = i18n.set_translation_language(&props.selected_language);
     let url = get_url();
+    let game_id = game_id().unwrap_or_default();
     let api = Api::new();
+    let is_organizer = api.is_organizer();
+    let late_name = use_state(String::new);
+    let phase_button = |action: &'static str| {
+        let on_phase = props.on_phase.clone();
+        Callback::from(move |_: MouseEvent| on_phase.emit(action.to_string()))
+    };
 
-    let onchange = {
-        let partcipant_selected = partcipant_selected.clone();
+    let late_name_on_change = {
+        let late_name = late_name.clone();
         Callback::from(move |event: Event| {
-            let input: HtmlInputElement =
-                event.target().unwrap().unchecked_into::<HtmlInputElement>();
-            let value = input.value();
-            partcipant_selected.set(value.clone());
+            let input: HtmlInputElement = event.target().unwrap().unchecked_into();
+            late_name.set(input.value());
+        })
+    };
+
+    // someone showed up after the draw: splice them in without a restart
+    let add_late_player = {
+        let late_name = late_name.clone();
+        let on_late_join = props.on_late_join.clone();
+        let api = api.clone();
+        let i18n = i18n.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            let name = late_name.deref().trim().to_string();
+            if name.is_empty() {
+                return;
+            }
+            let late_name = late_name.clone();
+            let on_late_join = on_late_join.clone();
+            let api = api.clone();
+            let i18n = i18n.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api.add_late_player(&name).await {
+                    Ok(response) => {
+                        if response.status() != 200 {
+                            let api_response = response.json::<ApiError>().await.unwrap();
+                            let message = match api_response.code.as_str() {
+                                "late_join_impossible" => {
+                                    format!(
+                                        "{}: {}",
+                                        i18n.t("Cannot add late player"),
+                                        api_response.error
+                                    )
+                                }
+                                "player_already_exists" => i18n.t("Participant already exists"),
+                                _ => format!("Error msg : {}", api_response.error),
+                            };
+                            alert(&message);
+                            return;
+                        }
+                        let joined = response.json::<LateJoin>().await.unwrap();
+                        log!(format!(
+                            "{} now gives to {}",
+                            joined.reassigned.join(", "),
+                            joined.name
+                        ));
+                        late_name.set(String::new());
+                        on_late_join.emit((joined.name, joined.token));
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+
+    let leaving = use_state(String::new);
+    let replacement = use_state(String::new);
+
+    let leaving_on_change = {
+        let leaving = leaving.clone();
+        Callback::from(move |event: Event| {
+            let select: HtmlSelectElement = event.target().unwrap().unchecked_into();
+            leaving.set(select.value());
+        })
+    };
+
+    let replacement_on_change = {
+        let replacement = replacement.clone();
+        Callback::from(move |event: Event| {
+            let input: HtmlInputElement = event.target().unwrap().unchecked_into();
+            replacement.set(input.value());
+        })
+    };
+
+    // someone dropped out: take them out, with or without a replacement
+    let withdraw_player = {
+        let leaving = leaving.clone();
+        let replacement = replacement.clone();
+        let on_late_join = props.on_late_join.clone();
+        let api = api.clone();
+        let i18n = i18n.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            let name = leaving.deref().clone();
+            if name.is_empty() {
+                return;
+            }
+            let replacement_name =
+                Some(replacement.deref().trim().to_string()).filter(|name| !name.is_empty());
+            let replacement = replacement.clone();
+            let on_late_join = on_late_join.clone();
+            let api = api.clone();
+            let i18n = i18n.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api.withdraw_player(&name, replacement_name.clone()).await {
+                    Ok(response) => {
+                        if response.status() != 200 {
+                            let api_response = response.json::<ApiError>().await.unwrap();
+                            alert(&format!("Error msg : {}", api_response.error));
+                            return;
+                        }
+                        let report = response.json::<WithdrawalReport>().await.unwrap();
+                        replacement.set(String::new());
+                        if let (Some(name), Some(token)) = (replacement_name, report.token) {
+                            on_late_join.emit((name, token));
+                        }
+                        if !report.reveal_again.is_empty() {
+                            alert(&format!(
+                                "{}: {}",
+                                i18n.t("These players must reveal again"),
+                                report.reveal_again.join(", ")
+                            ));
+                        }
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
         })
     };
 
     let onclick = {
-        let partcipant_selected = partcipant_selected.clone();
+        let player_token = player_token.clone().unwrap_or_default();
         let sorted_paticipant_clone = sorted_participant.clone();
+        let i18n = i18n.clone();
         Callback::from(move |_| {
-            let participant_selected = partcipant_selected.deref().clone();
+            let player_token = player_token.clone();
             let sorted_paticipant_clone = sorted_paticipant_clone.clone();
             let api = api.clone();
+            let i18n = i18n.clone();
             wasm_bindgen_futures::spawn_local(async move {
-                let response = api.pick_player(&participant_selected).await;
+                let response = api.pick_player(&player_token).await;
 
                 let sorted_participant = sorted_paticipant_clone.clone();
                 match response {
                     Ok(response) => {
                         if response.status() != 200 {
                             let api_response = response.json::<ApiError>().await.unwrap();
-                            let message = format!("Error msg : {}", api_response.error.to_string());
+                            if api_response.code == "already_picked" {
+                                // show the recipients again, wishlists may have changed
+                                if let Ok(response) = api.recipients(&player_token).await {
+                                    if let Ok(person) = response.json::<Person>().await {
+                                        sorted_participant.set(Some(person));
+                                        return;
+                                    }
+                                }
+                            }
+                            let message = match api_response.code.as_str() {
+                                "already_picked" => i18n.t("You already picked"),
+                                "reveals_not_open" | "reveals_scheduled" => {
+                                    i18n.t("Reveals are not open yet")
+                                }
+                                "invalid_token" => i18n.t("Invalid personal link"),
+                                _ => format!("Error msg : {}", api_response.error),
+                            };
                             alert(&message);
+                            return;
                         }
                         let response = response.json::<Person>().await.unwrap();
                         sorted_participant.set(Some(response));
@@ -345,24 +1244,29 @@ pub fn in_progress(props: &PropsInProgressGame) -> Html {
         })
     };
 
-    let url_clone = url.clone();
     let clipboard = use_clipboard();
 
     let onclick_clipboard = {
         let clipboard = clipboard.clone();
         let i18n = i18n.clone();
-        Callback::from(move |_| {
-            clipboard.write_text(url_clone.clone());
-            alert( &i18n.t("Copied to the clipboard"));
+        Callback::from(move |link: String| {
+            clipboard.write_text(link);
+            alert(&i18n.t("Copied to the clipboard"));
         })
     };
 
-    let participants_filtred = props
+    let mut personal_links = props
         .participants
         .iter()
         .filter(move |participant| !participant.has_picked)
-        .map(|participant| participant.clone())
-        .collect::<Vec<Player>>();
+        .filter_map(|participant| {
+            props.player_tokens.get(&participant.name).map(|token| {
+                let link = format!("{}?game={}&token={}", url, game_id, token);
+                (participant.label(), link)
+            })
+        })
+        .collect::<Vec<(String, String)>>();
+    personal_links.sort();
 
     html! {
         <div>
@@ -371,64 +1275,282 @@ pub fn in_progress(props: &PropsInProgressGame) -> Html {
                     <>
                     <Confetti/>
                     <h1 class="animate__animated animate__rubberBand mb-4 text-4xl font-extrabold leading-none tracking-tight text-gray-900 md:text-5xl lg:text-6xl dark:text-white">{ &i18n.t("You picked")}
-                    <span class="text-blue-600 dark:text-blue-500">{sorted_participant.name.clone()}</span>
+                    <span class="text-blue-600 dark:text-blue-500">{
+                        sorted_participant
+                            .names
+                            .iter()
+                            .map(|name| {
+                                props
+                                    .participants
+                                    .iter()
+                                    .find(|participant| &participant.name == name)
+                                    .map(|participant| participant.label())
+                                    .unwrap_or_else(|| name.clone())
+                            })
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    }</span>
                     </h1>
+                    { for sorted_participant.recipients.iter().map(|recipient| html! {
+                        <div class="mt-6 flex flex-col items-center">
+                            <h3 class="mb-2 text-lg font-medium text-gray-900 dark:text-white">{ format!("{} · {}", i18n.t("Wishlist"), recipient.name) }</h3>
+                            { wishlist_view(&recipient.wishlist, i18n.t("Nothing on the wishlist yet")) }
+                        </div>
+                    })}
                     </>
                 }
+            } else if player_token.is_some() && props.status == GameStatus::Drawn {
+                html! {
+                    <p class="mt-10 text-sm text-gray-500 dark:text-gray-400">{ &i18n.t("Reveals are not open yet") }</p>
+                }
+            } else if player_token.is_some() {
+                html! {
+                    <button class="mt-10 px-6 py-3.5 text-base font-medium text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800" onclick={onclick}>{ &i18n.t("Pick")}</button>
+                }
+            } else if personal_links.is_empty() {
+                html! {
+                    <p class="animate__tada text-sm text-gray-500 dark:text-gray-400 mb-4"> { &i18n.t("Open your personal link") }</p>
+                }
             } else {
                 html! {
-
                     <div class="">
-
-                        <p class="animate__tada text-sm text-gray-500 dark:text-gray-400 mb-4"> { &i18n.t("Who are you ?") }</p>
-                        <select onchange={onchange} class="block w-full px-4 py-3 text-base text-gray-900 border border-gray-300 rounded-lg bg-gray-50 focus:ring-blue-500 focus:border-blue-500 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white dark:focus:ring-blue-500 dark:focus:border-blue-500" >
-                            <option selected={true}  value={""}>{&i18n.t("Select your name")}</option>
-                        {
-                            participants_filtred.iter().map(|participant| html! {
-                                <option value={participant.name.clone()}>{participant.name.clone()}</option>
-                            }).collect::<Html>()
-                        }
-                        </select>
-                        { if !partcipant_selected.deref().is_empty() {
+                        <h2 class="animate__animated animate__rubberBand mb-4 mt-10 text-4xl font-extrabold leading-none tracking-tight text-gray-900 md:text-5xl lg:text-6xl dark:text-white">{&i18n.t("Personal links")}</h2>
+                        <ul role="list" class="divide-y divide-gray-200 dark:divide-gray-700">
+                        { for personal_links.into_iter().map(|(name, link)| {
+                            let onclick_clipboard = onclick_clipboard.clone();
+                            let link_clone = link.clone();
                             html! {
-                                <button class="mt-10 px-6 py-3.5 text-base font-medium text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800" onclick={onclick}>{ &i18n.t("Pick")}</button>
+                                <li class="py-3 sm:py-4">
+                                    <div class="flex justify-center">
+                                    <div class="grid grid-cols-8 gap-2 w-full max-w-[32rem] items-center">
+                                        <span class="col-span-2 text-sm font-medium text-gray-900 truncate dark:text-white">{name}</span>
+                                        <input
+                                            type="text"
+                                            class="col-span-4 bg-gray-50 border border-gray-300 text-gray-500 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 block w-full p-2.5 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-gray-400 dark:focus:ring-blue-500 dark:focus:border-blue-500"
+                                            value={link}
+                                            readonly={true}
+                                            disabled={true}
+                                        />
+                                        <button
+                                            class="col-span-2 text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm w-full sm:w-auto py-2.5 text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800 items-center inline-flex justify-center"
+                                            onclick={move |_| onclick_clipboard.emit(link_clone.clone())}
+                                        >
+                                            {&i18n.t("Copy link")}
+                                        </button>
+                                    </div>
+                                    </div>
+                                </li>
                             }
-                        }else{
-                            html!{}
-                        }}
-
-                        <h2 class="animate__animated animate__rubberBand mb-4 mt-20 text-4xl font-extrabold leading-none tracking-tight text-gray-900 md:text-5xl lg:text-6xl dark:text-white">{&i18n.t("Share with other players")}</h2>
-                        <div class="flex justify-center">
-                        <div class="grid grid-cols-8 gap-2 w-full max-w-[23rem]">
-                            <label for="link" class="sr-only">{"Label"} </label>
-                            <input
-                                id="link"
-                                type="text"
-                                class="col-span-6 bg-gray-50 border border-gray-300 text-gray-500 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 block w-full p-2.5 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-gray-400 dark:focus:ring-blue-500 dark:focus:border-blue-500"
-                                value={url.clone()}
-                                readonly={true}
-                                disabled={true}
-                            />
-
-                            <button
-                                class="col-span-2 text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm w-full sm:w-auto py-2.5 text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800 items-center inline-flex justify-center"
-                                onclick={onclick_clipboard}
-                            >
-
-                                <span id="default-message">{&i18n.t("Copy link")}</span>
-                                <span id="success-message" class="hidden inline-flex items-center">
-                                    <svg class="w-3 h-3 text-white me-1.5" aria-hidden="true" xmlns="http://www.w3.org/2000/svg" fill="none" viewBox="0 0 16 12">
-                                        <path stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M1 5.917 5.724 10.5 15 1.5"/>
-                                    </svg>
-                                    {"Copied!"}
-                                </span>
-                            </button>
-                        </div>
-                        </div>
+                        })}
+                        </ul>
                     </div>
                 }
             }}
 
+            if let Some(token) = player_token.clone() {
+                <WishlistEditor token={token.clone()} selected_language={props.selected_language.clone()} />
+                <Messages {token} selected_language={props.selected_language.clone()} />
+            }
+
+            if is_organizer && sorted_participant.is_none() {
+                <div class="mt-10 flex justify-center">
+                    if props.status == GameStatus::Drawn {
+                        <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={phase_button("open-reveals")} type="button">{ &i18n.t("Open reveals") }</button>
+                    } else {
+                        <button class="mt-4 px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" onclick={phase_button("close-exchange")} type="button">{ &i18n.t("Exchange done") }</button>
+                    }
+                </div>
+                <form class="mt-10 flex justify-center" onsubmit={add_late_player}>
+                    <div class="flex w-full max-w-[32rem] gap-2">
+                        <input onchange={late_name_on_change} value={late_name.deref().clone()} type="text" class="block p-2.5 w-full text-sm text-gray-900 bg-gray-50 rounded-lg border border-gray-300 focus:ring-blue-500 focus:border-blue-500 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white" placeholder={i18n.t("Late participant")} required={true}/>
+                        <button type="submit" class="px-4 text-sm font-medium text-white bg-blue-700 rounded-lg hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800">{&i18n.t("Add late participant")}</button>
+                    </div>
+                </form>
+                <form class="mt-4 flex justify-center" onsubmit={withdraw_player}>
+                    <div class="flex w-full max-w-[32rem] gap-2">
+                        <select onchange={leaving_on_change} class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 block w-full p-2.5 dark:bg-gray-700 dark:border-gray-600 dark:text-white">
+                            <option selected=true disabled=true>{ &i18n.t("Who is leaving ?") }</option>
+                            { for props.participants.iter().map(|participant| html! {
+                                <option value={participant.name.clone()}>{participant.label()}</option>
+                            })}
+                        </select>
+                        <input onchange={replacement_on_change} value={replacement.deref().clone()} type="text" class="block p-2.5 w-full text-sm text-gray-900 bg-gray-50 rounded-lg border border-gray-300 focus:ring-blue-500 focus:border-blue-500 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white" placeholder={i18n.t("Replacement (optional)")}/>
+                        <button type="submit" class="px-4 text-sm font-medium text-blue-700 border border-blue-700 rounded-lg hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 dark:border-blue-500 dark:text-blue-500">{&i18n.t("Withdraw")}</button>
+                    </div>
+                </form>
+            }
+        </div>
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookInfo {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookList {
+    pub webhooks: Vec<WebhookInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub attempts: u32,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDeliveries {
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Debug, PartialEq, Clone, Properties)]
+pub struct PropsWebhooks {
+    pub selected_language: String,
+}
+
+// Organizer only, URLs that receive the game events and how the last
+// deliveries went.
+#[function_component(Webhooks)]
+pub fn webhooks(props: &PropsWebhooks) -> Html {
+    let webhooks: UseStateHandle<Vec<WebhookInfo>> = use_state(Vec::new);
+    let deliveries: UseStateHandle<Vec<WebhookDelivery>> = use_state(Vec::new);
+    let url: UseStateHandle<String> = use_state(String::new);
+    let mut i18n = use_translation();
+    let _ = i18n.set_translation_language(&props.selected_language);
+
+    let refresh = {
+        let webhooks = webhooks.clone();
+        let deliveries = deliveries.clone();
+        move || {
+            let webhooks = webhooks.clone();
+            let deliveries = deliveries.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let api = Api::new();
+                if let Ok(response) = api.webhooks().await {
+                    if let Ok(list) = response.json::<WebhookList>().await {
+                        webhooks.set(list.webhooks);
+                    }
+                }
+                if let Ok(response) = api.webhook_deliveries().await {
+                    if let Ok(log) = response.json::<WebhookDeliveries>().await {
+                        deliveries.set(log.deliveries);
+                    }
+                }
+            });
+        }
+    };
+    {
+        let refresh = refresh.clone();
+        use_effect_with((), move |_| refresh());
+    }
+    {
+        let refresh = refresh.clone();
+        use_interval(refresh, 15_000);
+    }
+
+    let on_url = {
+        let url = url.clone();
+        Callback::from(move |event: Event| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            url.set(input.value());
+        })
+    };
+    let add_webhook = {
+        let url = url.clone();
+        let webhooks = webhooks.clone();
+        let i18n = i18n.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            let new_url = url.deref().trim().to_string();
+            if new_url.is_empty() {
+                return;
+            }
+            let url = url.clone();
+            let webhooks = webhooks.clone();
+            let i18n = i18n.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Api::new().add_webhook(&new_url).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(added) = response.json::<WebhookInfo>().await {
+                            alert(&format!(
+                                "{} {}",
+                                i18n.t("Keep this secret to check the signatures:"),
+                                added.secret.clone().unwrap_or_default()
+                            ));
+                            let mut list = webhooks.deref().clone();
+                            list.push(WebhookInfo {
+                                secret: None,
+                                ..added
+                            });
+                            webhooks.set(list);
+                            url.set(String::new());
+                        }
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        alert(&api_response.error);
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+    let remove_webhook = |id: String| {
+        let webhooks = webhooks.clone();
+        Callback::from(move |_: MouseEvent| {
+            let id = id.clone();
+            let webhooks = webhooks.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match Api::new().remove_webhook(&id).await {
+                    Ok(response) if response.status() == 200 => {
+                        if let Ok(list) = response.json::<WebhookList>().await {
+                            webhooks.set(list.webhooks);
+                        }
+                    }
+                    Ok(response) => {
+                        let api_response = response.json::<ApiError>().await.unwrap();
+                        alert(&api_response.error);
+                    }
+                    Err(err) => log!(format!("something bad happend: {}", err)),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="mt-6 flex flex-col items-center gap-2 w-full max-w-[32rem]">
+            <span class="text-sm font-medium text-gray-900 dark:text-white">{ &i18n.t("Webhooks") }</span>
+            { for webhooks.iter().map(|webhook| html! {
+                <div class="flex w-full items-center justify-between text-sm text-gray-500 dark:text-gray-400">
+                    <span class="truncate">{ &webhook.url }</span>
+                    <button class="ml-2 text-red-700 dark:text-red-500" onclick={remove_webhook(webhook.id.clone())}>{ &i18n.t("Remove") }</button>
+                </div>
+            })}
+            <form class="flex w-full gap-2" onsubmit={add_webhook}>
+                <input type="url" class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2 dark:bg-gray-700 dark:border-gray-600 dark:text-white w-full" placeholder="https://" value={url.deref().clone()} onchange={on_url} />
+                <button class="px-4 py-2 text-sm font-medium text-blue-700 border border-blue-700 hover:bg-blue-800 hover:text-white focus:ring-4 focus:outline-none focus:ring-blue-300 rounded-lg text-center dark:border-blue-500 dark:text-blue-500" type="submit">{ &i18n.t("Add webhook") }</button>
+            </form>
+            { for deliveries.iter().take(10).map(|delivery| html! {
+                <span class="w-full text-xs text-gray-500 dark:text-gray-400">
+                    { format!(
+                        "{} {} · {} {}",
+                        if delivery.delivered { "✓" } else { "✗" },
+                        delivery.event,
+                        delivery.attempts,
+                        delivery.error.clone().unwrap_or_default()
+                    ) }
+                </span>
+            })}
         </div>
     }
 }
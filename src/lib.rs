@@ -1,350 +1,3873 @@
+use derive_more::{Display, Error};
+use rand::distributions::Alphanumeric;
 use rand::prelude::*;
-use serde::{Deserialize, Serialize};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub name: String,
-    picked: Option<String>,
+    // the name as it was typed, `name` is its normalized form
+    #[serde(default)]
+    display_name: String,
+    pub group: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    picked: Vec<String>,
+    token: Option<String>,
     pub has_picked: bool,
+    #[serde(default)]
+    pub wishlist: Vec<WishlistItem>,
+    // where the recipient is sent when the game starts, if anywhere
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
 impl Player {
     pub fn new(name: &str) -> Self {
         Player {
-            name: name.to_string(),
-            picked: None,
+            name: normalize_name(name),
+            display_name: name.trim().to_string(),
+            group: None,
+            picked: vec![],
+            token: None,
             has_picked: false,
+            wishlist: vec![],
+            email: None,
+        }
+    }
+
+    // Rebuilds a player loaded from storage, assignment and token included.
+    pub fn restore(
+        name: &str,
+        display_name: &str,
+        group: Option<String>,
+        picked: Vec<String>,
+        token: Option<String>,
+        has_picked: bool,
+    ) -> Self {
+        Player {
+            name: name.to_string(),
+            display_name: display_name.to_string(),
+            group,
+            picked,
+            token,
+            has_picked,
+            wishlist: vec![],
+            email: None,
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        if self.display_name.is_empty() {
+            &self.name
+        } else {
+            &self.display_name
+        }
+    }
+
+    pub fn picked(&self) -> &[String] {
+        &self.picked
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn with_email(self, email: &str) -> Self {
+        Player {
+            email: Some(email.trim().to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_group(name: &str, group: &str) -> Self {
+        Player {
+            group: Some(group.to_string()),
+            ..Player::new(name)
+        }
+    }
+}
+
+pub const MAX_WISHLIST_ITEMS: usize = 20;
+
+// Something a player would like to get, only the title is required.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WishlistItem {
+    pub title: String,
+    #[serde(default)]
+    pub link: Option<String>,
+    #[serde(default)]
+    pub price_hint: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+impl WishlistItem {
+    pub fn new(title: &str) -> Self {
+        WishlistItem {
+            title: title.to_string(),
+            link: None,
+            price_hint: None,
+            notes: None,
+        }
+    }
+
+    fn cleaned(self) -> Result<Self, GameError> {
+        let optional = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let title = self.title.trim().to_string();
+        if title.is_empty() {
+            return Err(GameError::EmptyWishlistTitle);
+        }
+        Ok(WishlistItem {
+            title,
+            link: optional(self.link),
+            price_hint: optional(self.price_hint),
+            notes: optional(self.notes),
+        })
+    }
+}
+
+pub const WEBHOOK_EVENTS: [&str; 7] = [
+    "player_added",
+    "player_removed",
+    "game_started",
+    "reveals_opened",
+    "player_revealed",
+    "game_finished",
+    "game_reset",
+];
+pub const MAX_WEBHOOKS: usize = 10;
+const MAX_WEBHOOK_URL_LENGTH: usize = 500;
+
+// Where the server posts game events, signed with `secret`. An empty
+// `events` subscribes to all of them.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+}
+
+pub const MAX_MESSAGE_LENGTH: usize = 1000;
+
+// A line of the anonymous thread between a giver and one of their recipients.
+// Stored with both names, but only ever shown through `Thread`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub giver: String,
+    pub receiver: String,
+    pub from_santa: bool,
+    pub text: String,
+    pub sent_at: u64,
+}
+
+// A thread as one side sees it. Givers talk to their recipient by name,
+// recipients only know their Santa by an id that hides who it is.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Thread {
+    pub with: String,
+    pub messages: Vec<ThreadMessage>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ThreadMessage {
+    pub mine: bool,
+    pub text: String,
+    pub sent_at: u64,
+}
+
+// Derived from the giver's secret token, so neither guessable nor traceable
+// back to a name.
+fn santa_id(santa: &Player, recipient: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(santa.token().unwrap_or_default().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(recipient.as_bytes());
+    format!("{:x}", hasher.finalize())[..16].to_string()
+}
+
+// Only catches typos, the SMTP relay has the final word.
+fn looks_like_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.contains(char::is_whitespace)
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+fn clean_message(text: &str) -> Result<String, GameError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(GameError::EmptyMessage);
+    }
+    if text.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(GameError::MessageTooLong {
+            max: MAX_MESSAGE_LENGTH,
+        });
+    }
+    Ok(text.to_string())
+}
+
+// Snapshots written before players could give several gifts hold a single
+// recipient, or null before the draw.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Picked {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Option::<Picked>::deserialize(deserializer)? {
+        None => vec![],
+        Some(Picked::One(name)) => vec![name],
+        Some(Picked::Many(names)) => names,
+    })
+}
+
+// Trims the name, collapses inner whitespace and applies Unicode NFC, so the
+// same name typed on different keyboards ends up identical.
+pub fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .nfc()
+        .collect()
+}
+
+// Names that only differ in case are the same player.
+fn name_key(name: &str) -> String {
+    normalize_name(name).to_lowercase()
+}
+
+// Lowercase letters and digits without accents, "Ána-Luísa" becomes "analuisa".
+fn name_skeleton(name: &str) -> String {
+    name.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+// Same skeleton, or one typo apart once names are long enough for a typo
+// to stand out.
+fn looks_alike(first: &str, second: &str) -> bool {
+    let first: Vec<char> = name_skeleton(first).chars().collect();
+    let second: Vec<char> = name_skeleton(second).chars().collect();
+    if first == second {
+        return true;
+    }
+    first.len().min(second.len()) >= 4 && edit_distance(&first, &second) <= 1
+}
+
+fn edit_distance(first: &[char], second: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=second.len()).collect();
+    for (i, a) in first.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in second.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
         }
+        previous = current;
     }
+    previous[second.len()]
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NameRules {
+    pub min_length: usize,
+    pub max_length: usize,
+}
+
+impl Default for NameRules {
+    fn default() -> Self {
+        NameRules {
+            min_length: 1,
+            max_length: 50,
+        }
+    }
+}
+
+// The phases of a game, in order. Older snapshots used NotStarted,
+// InProgress and Finished.
+#[derive(Debug, Display, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum GameStatus {
+    // registration open, players come and go
+    #[serde(alias = "NotStarted")]
+    Open,
+    // registration closed, the draw settings can still change
+    Draft,
+    // everybody has recipients, reveals are not open yet
+    Drawn,
+    #[serde(alias = "InProgress")]
+    Revealing,
+    // the exchange is done
+    #[serde(alias = "Finished")]
+    Closed,
+    // kept for the record, nothing changes anymore
+    Archived,
+}
+
+impl GameStatus {
+    pub fn before_draw(self) -> bool {
+        matches!(self, GameStatus::Open | GameStatus::Draft)
+    }
+}
+
+// When reveals open and when gifts are exchanged, as unix timestamps in
+// seconds. Either can be left out.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    pub reveals_at: Option<u64>,
+    pub exchange_at: Option<u64>,
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), GameError> {
+        if let (Some(reveals_at), Some(exchange_at)) = (self.reveals_at, self.exchange_at) {
+            if reveals_at >= exchange_at {
+                return Err(GameError::InvalidSchedule);
+            }
+        }
+        Ok(())
+    }
+}
+
+// What the organizer tells everyone about the event. Budgets are whole
+// amounts in `currency`, an ISO 4217 code such as "EUR".
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventDetails {
+    pub title: Option<String>,
+    pub budget_min: Option<u32>,
+    pub budget_max: Option<u32>,
+    pub currency: Option<String>,
+    pub location: Option<String>,
+    pub theme: Option<String>,
+    pub rules: Option<String>,
+}
+
+const MAX_EVENT_FIELD_LENGTH: usize = 100;
+const MAX_EVENT_RULES_LENGTH: usize = 2000;
+
+impl EventDetails {
+    fn cleaned(self) -> Result<Self, GameError> {
+        let text = |field: &str, value: Option<String>, max: usize| {
+            let value = value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty());
+            match value {
+                Some(value) if value.chars().count() > max => Err(GameError::EventFieldTooLong {
+                    field: field.to_string(),
+                    max,
+                }),
+                value => Ok(value),
+            }
+        };
+        if let (Some(min), Some(max)) = (self.budget_min, self.budget_max) {
+            if min > max {
+                return Err(GameError::InvalidBudget);
+            }
+        }
+        let currency = text("currency", self.currency, MAX_EVENT_FIELD_LENGTH)?
+            .map(|code| code.to_uppercase());
+        if currency
+            .as_ref()
+            .is_some_and(|code| code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()))
+        {
+            return Err(GameError::InvalidCurrency);
+        }
+        Ok(EventDetails {
+            title: text("title", self.title, MAX_EVENT_FIELD_LENGTH)?,
+            budget_min: self.budget_min,
+            budget_max: self.budget_max,
+            currency,
+            location: text("location", self.location, MAX_EVENT_FIELD_LENGTH)?,
+            theme: text("theme", self.theme, MAX_EVENT_FIELD_LENGTH)?,
+            rules: text("rules", self.rules, MAX_EVENT_RULES_LENGTH)?,
+        })
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+// "2026-12-01 09:30 UTC", without pulling a date library in for one message
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let minutes = timestamp % 86_400 / 60;
+    // days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Exclusion {
+    pub giver: String,
+    pub receiver: String,
+    #[serde(default)]
+    pub symmetric: bool,
+}
+
+impl Exclusion {
+    pub fn new(giver: &str, receiver: &str) -> Self {
+        Exclusion {
+            giver: giver.to_string(),
+            receiver: receiver.to_string(),
+            symmetric: false,
+        }
+    }
+
+    pub fn symmetric(first: &str, second: &str) -> Self {
+        Exclusion {
+            symmetric: true,
+            ..Exclusion::new(first, second)
+        }
+    }
+
+    fn forbids(&self, giver: &str, receiver: &str) -> bool {
+        (self.giver == giver && self.receiver == receiver)
+            || (self.symmetric && self.giver == receiver && self.receiver == giver)
+    }
+
+    fn involves(&self, player_name: &str) -> bool {
+        self.giver == player_name || self.receiver == player_name
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Pairing {
+    pub giver: String,
+    pub receiver: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PastDraw {
+    pub year: u16,
+    pub pairings: Vec<Pairing>,
+}
+
+impl PastDraw {
+    // Names are compared by their key, so "ana" in an old draw is still Ana.
+    pub fn contains(&self, giver: &str, receiver: &str) -> bool {
+        let (giver, receiver) = (name_key(giver), name_key(receiver));
+        self.pairings
+            .iter()
+            .any(|p| name_key(&p.giver) == giver && name_key(&p.receiver) == receiver)
+    }
+}
+
+// Giver and receiver keys of every pairing in the draws.
+fn avoided_pairs(draws: &[&PastDraw]) -> HashSet<(String, String)> {
+    draws
+        .iter()
+        .flat_map(|d| &d.pairings)
+        .map(|p| (name_key(&p.giver), name_key(&p.receiver)))
+        .collect()
+}
+
+// Published when the game starts so the draw can be checked afterwards: the
+// commitment is shown right away, the seed only once the organizer reveals it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DrawAudit {
+    seed: String,
+    pub commitment: String,
+    pub seed_revealed: bool,
+    // players and exclusions as they were drawn, so the seed still reproduces
+    // the draw once people join or leave the running game
+    #[serde(default)]
+    players: Vec<Player>,
+    #[serde(default)]
+    exclusions: Vec<Exclusion>,
+    // the assignment changed after the draw, it no longer matches the commitment
+    #[serde(default)]
+    pub amended: bool,
+}
+
+// What withdrawing a player from a running game changed.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Repair {
+    // players whose recipients changed
+    pub reassigned: Vec<String>,
+    // those of them who had already revealed, they can reveal again
+    pub reveal_again: Vec<String>,
+}
+
+impl DrawAudit {
+    pub fn revealed_seed(&self) -> Option<&str> {
+        self.seed_revealed.then_some(self.seed.as_str())
+    }
+}
+
+// SHA-256 over the seed and every "giver -> receiver" line sorted by giver.
+// The secret seed keeps the assignment from being guessed from the hash.
+pub fn draw_commitment(seed: &str, pairings: &[Pairing]) -> String {
+    let mut lines: Vec<String> = pairings
+        .iter()
+        .map(|p| format!("{} -> {}", p.giver, p.receiver))
+        .collect();
+    lines.sort();
+    let mut hasher = Sha256::new();
+    hasher.update(seed.as_bytes());
+    for line in lines {
+        hasher.update(b"\n");
+        hasher.update(line.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// The same seed always gives the same sequence, whatever the platform.
+fn seeded_rng(seed: &str) -> ChaCha20Rng {
+    ChaCha20Rng::from_seed(Sha256::digest(seed.as_bytes()).into())
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum DrawStrategy {
+    // everyone in one gift chain that goes through the whole group
+    SingleCycle,
+    // any assignment without self gifts, small cycles and mutual pairs included
+    AnyDerangement,
+    // like AnyDerangement, but nobody gives to their own Secret Santa
+    NoMutualPairs,
+}
+
+// AvoidLastYears counts back from the newest draw in the history, so
+// AvoidLastYears(1) only forbids repeating the most recent exchange.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum HistoryPolicy {
+    AvoidLastYears(u16),
+    Prefer,
+}
+
+#[derive(Debug, Display, Error, PartialEq, Clone)]
+pub enum GameError {
+    #[display(fmt = "Game already started or finished")]
+    GameLocked,
+    #[display(fmt = "Game already started")]
+    AlreadyStarted,
+    #[display(fmt = "Game already finished")]
+    AlreadyFinished,
+    #[display(fmt = "Game not started")]
     NotStarted,
-    InProgress,
+    #[display(fmt = "Game finished")]
     Finished,
+    #[display(fmt = "Game not finished")]
+    NotFinished,
+    #[display(fmt = "Player {} already exists", name)]
+    PlayerAlreadyExists { name: String },
+    #[display(fmt = "Player name cannot be empty")]
+    EmptyPlayerName,
+    #[display(fmt = "Player name must have at least {} characters", min)]
+    NameTooShort { min: usize },
+    #[display(fmt = "Player name cannot be longer than {} characters", max)]
+    NameTooLong { max: usize },
+    #[display(fmt = "Name length limits must be at least 1 and in increasing order")]
+    InvalidNameRules,
+    #[display(fmt = "Player {} appears more than once", name)]
+    DuplicatePlayer { name: String },
+    #[display(fmt = "Player {} not found", name)]
+    PlayerNotFound { name: String },
+    #[display(fmt = "Player has already picked")]
+    AlreadyPicked,
+    #[display(fmt = "Invalid player token")]
+    InvalidToken,
+    #[display(fmt = "Player cannot be excluded from themselves")]
+    SelfExclusion,
+    #[display(fmt = "Exclusion between {} and {} already exists", giver, receiver)]
+    ExclusionAlreadyExists { giver: String, receiver: String },
+    #[display(fmt = "Exclusion not found")]
+    ExclusionNotFound,
+    #[display(fmt = "History must cover at least one year")]
+    EmptyHistoryWindow,
+    #[display(fmt = "Not enough players")]
+    NotEnoughPlayers,
+    #[display(
+        fmt = "Group {} has more than half of the players, no valid assignment exists",
+        group
+    )]
+    OversizedGroup { group: String },
+    #[display(fmt = "No valid assignment satisfies the exclusions")]
+    NoValidAssignment,
+    #[display(fmt = "No assignment was found in time, try again or loosen the exclusions")]
+    SearchGaveUp,
+    #[display(fmt = "The draw seed has not been revealed yet")]
+    SeedNotRevealed,
+    #[display(fmt = "Every player must give at least one gift")]
+    InvalidGiftsPerPerson,
+    #[display(fmt = "Cannot add {} to the running game: {}", name, reason)]
+    LateJoinImpossible { name: String, reason: String },
+    #[display(fmt = "Registration is closed")]
+    RegistrationClosed,
+    #[display(fmt = "Reveals are not open yet")]
+    RevealsNotOpen,
+    #[display(fmt = "A game cannot go from {} to {}", from, to)]
+    InvalidTransition { from: GameStatus, to: GameStatus },
+    #[display(fmt = "Reveals open on {}", at)]
+    RevealsScheduled { at: String },
+    #[display(fmt = "Reveals must open before the gift exchange")]
+    InvalidSchedule,
+    #[display(fmt = "Wishlist items need a title")]
+    EmptyWishlistTitle,
+    #[display(fmt = "A wishlist cannot have more than {} items", max)]
+    TooManyWishlistItems { max: usize },
+    #[display(fmt = "Reveal your recipient first")]
+    NotRevealed,
+    #[display(fmt = "The minimum budget cannot be above the maximum")]
+    InvalidBudget,
+    #[display(fmt = "Currency must be a three letter code such as EUR")]
+    InvalidCurrency,
+    #[display(fmt = "The event {} cannot be longer than {} characters", field, max)]
+    EventFieldTooLong { field: String, max: usize },
+    #[display(fmt = "Message cannot be empty")]
+    EmptyMessage,
+    #[display(fmt = "Message cannot be longer than {} characters", max)]
+    MessageTooLong { max: usize },
+    #[display(fmt = "Conversation not found")]
+    ThreadNotFound,
+    #[display(fmt = "{} is not a valid email address", email)]
+    InvalidEmail { email: String },
+    #[display(fmt = "{} is not a valid webhook URL", url)]
+    InvalidWebhookUrl { url: String },
+    #[display(fmt = "Unknown webhook event {}", event)]
+    UnknownWebhookEvent { event: String },
+    #[display(fmt = "A game cannot have more than {} webhooks", max)]
+    TooManyWebhooks { max: usize },
+    #[display(fmt = "Webhook not found")]
+    WebhookNotFound,
+}
+
+impl GameError {
+    // Stable identifier for clients, the display message may change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            GameError::GameLocked => "game_locked",
+            GameError::AlreadyStarted => "game_already_started",
+            GameError::AlreadyFinished => "game_already_finished",
+            GameError::NotStarted => "game_not_started",
+            GameError::Finished => "game_finished",
+            GameError::NotFinished => "game_not_finished",
+            GameError::PlayerAlreadyExists { .. } => "player_already_exists",
+            GameError::EmptyPlayerName => "empty_player_name",
+            GameError::NameTooShort { .. } => "name_too_short",
+            GameError::NameTooLong { .. } => "name_too_long",
+            GameError::InvalidNameRules => "invalid_name_rules",
+            GameError::DuplicatePlayer { .. } => "duplicate_player",
+            GameError::PlayerNotFound { .. } => "player_not_found",
+            GameError::AlreadyPicked => "already_picked",
+            GameError::InvalidToken => "invalid_token",
+            GameError::SelfExclusion => "self_exclusion",
+            GameError::ExclusionAlreadyExists { .. } => "exclusion_already_exists",
+            GameError::ExclusionNotFound => "exclusion_not_found",
+            GameError::EmptyHistoryWindow => "empty_history_window",
+            GameError::NotEnoughPlayers => "not_enough_players",
+            GameError::OversizedGroup { .. } => "oversized_group",
+            GameError::NoValidAssignment => "no_valid_assignment",
+            GameError::SearchGaveUp => "search_gave_up",
+            GameError::SeedNotRevealed => "seed_not_revealed",
+            GameError::InvalidGiftsPerPerson => "invalid_gifts_per_person",
+            GameError::LateJoinImpossible { .. } => "late_join_impossible",
+            GameError::RegistrationClosed => "registration_closed",
+            GameError::RevealsNotOpen => "reveals_not_open",
+            GameError::InvalidTransition { .. } => "invalid_transition",
+            GameError::RevealsScheduled { .. } => "reveals_scheduled",
+            GameError::InvalidSchedule => "invalid_schedule",
+            GameError::EmptyWishlistTitle => "empty_wishlist_title",
+            GameError::TooManyWishlistItems { .. } => "too_many_wishlist_items",
+            GameError::NotRevealed => "not_revealed",
+            GameError::InvalidBudget => "invalid_budget",
+            GameError::InvalidCurrency => "invalid_currency",
+            GameError::EventFieldTooLong { .. } => "event_field_too_long",
+            GameError::EmptyMessage => "empty_message",
+            GameError::MessageTooLong { .. } => "message_too_long",
+            GameError::ThreadNotFound => "thread_not_found",
+            GameError::InvalidEmail { .. } => "invalid_email",
+            GameError::InvalidWebhookUrl { .. } => "invalid_webhook_url",
+            GameError::UnknownWebhookEvent { .. } => "unknown_webhook_event",
+            GameError::TooManyWebhooks { .. } => "too_many_webhooks",
+            GameError::WebhookNotFound => "webhook_not_found",
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SecretSantaGame {
     pub status: GameStatus,
     pub players: Vec<Player>,
+    pub exclusions: Vec<Exclusion>,
+    pub history: Vec<PastDraw>,
+    pub history_policy: HistoryPolicy,
+    pub name_rules: NameRules,
+    pub audit: Option<DrawAudit>,
+    pub draw_strategy: DrawStrategy,
+    pub gifts_per_person: usize,
+    pub schedule: Schedule,
+    pub event: EventDetails,
+    pub messages: Vec<Message>,
+    pub webhooks: Vec<Webhook>,
 }
 
 impl Default for SecretSantaGame {
     fn default() -> Self {
         SecretSantaGame {
-            status: GameStatus::NotStarted,
+            status: GameStatus::Open,
             players: vec![],
+            exclusions: vec![],
+            history: vec![],
+            history_policy: HistoryPolicy::Prefer,
+            name_rules: NameRules::default(),
+            audit: None,
+            draw_strategy: DrawStrategy::SingleCycle,
+            gifts_per_person: 1,
+            schedule: Schedule::default(),
+            event: EventDetails::default(),
+            messages: vec![],
+            webhooks: vec![],
+        }
+    }
+}
+
+impl SecretSantaGame {
+    pub fn add_player(&mut self, player: Player) -> Result<(), GameError> {
+        self.add_players(vec![player])
+    }
+
+    // Adds every player or none of them, failing with the first problem found.
+    pub fn add_players(&mut self, players: Vec<Player>) -> Result<(), GameError> {
+        if let Some(Err(error)) = self
+            .check_players(&players)
+            .into_iter()
+            .find(Result::is_err)
+        {
+            return Err(error);
+        }
+        // the personal link opens the lobby before the draw
+        self.players
+            .extend(players.into_iter().map(|player| Player {
+                token: Some(generate_token()),
+                ..player
+            }));
+        Ok(())
+    }
+
+    // Validates a batch of new players as a whole, one result per player.
+    pub fn check_players(&self, players: &[Player]) -> Vec<Result<(), GameError>> {
+        players
+            .iter()
+            .enumerate()
+            .map(|(position, player)| {
+                self.check_registration()?;
+                self.check_new_player(player, &players[..position])
+            })
+            .collect()
+    }
+
+    fn check_registration(&self) -> Result<(), GameError> {
+        match self.status {
+            GameStatus::Open => Ok(()),
+            GameStatus::Draft => Err(GameError::RegistrationClosed),
+            _ => Err(GameError::GameLocked),
+        }
+    }
+
+    fn check_before_draw(&self) -> Result<(), GameError> {
+        if !self.status.before_draw() {
+            return Err(GameError::GameLocked);
+        }
+        Ok(())
+    }
+
+    fn check_new_player(&self, player: &Player, earlier: &[Player]) -> Result<(), GameError> {
+        let length = normalize_name(&player.name).chars().count();
+        if length == 0 {
+            return Err(GameError::EmptyPlayerName);
+        }
+        if length < self.name_rules.min_length {
+            return Err(GameError::NameTooShort {
+                min: self.name_rules.min_length,
+            });
+        }
+        if length > self.name_rules.max_length {
+            return Err(GameError::NameTooLong {
+                max: self.name_rules.max_length,
+            });
+        }
+        let key = name_key(&player.name);
+        if self.players.iter().any(|p| name_key(&p.name) == key) {
+            return Err(GameError::PlayerAlreadyExists {
+                name: player.name.clone(),
+            });
+        }
+        if earlier.iter().any(|p| name_key(&p.name) == key) {
+            return Err(GameError::DuplicatePlayer {
+                name: player.name.clone(),
+            });
+        }
+        if let Some(email) = &player.email {
+            if !looks_like_email(email) {
+                return Err(GameError::InvalidEmail {
+                    email: email.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Splices a newcomer into the running game. Players who have not revealed
+    // yet each hand one of their recipients over to the newcomer, who gives to
+    // those recipients instead, so every revealed assignment stays as it is.
+    // Returns the players whose recipients changed.
+    pub fn add_late_player(&mut self, mut player: Player) -> Result<Vec<String>, GameError> {
+        match self.status {
+            GameStatus::Open | GameStatus::Draft => return self.add_player(player).map(|_| vec![]),
+            GameStatus::Closed | GameStatus::Archived => return Err(GameError::Finished),
+            GameStatus::Drawn | GameStatus::Revealing => {}
+        }
+        self.check_new_player(&player, &[])?;
+
+        let gifts = self.gifts_per_person;
+        let avoided = avoided_pairs(&self.strictly_avoided_draws());
+        let allowed = |giver: &Player, receiver: &Player| self.allowed(giver, receiver, &avoided);
+        let givers: Vec<usize> = (0..self.players.len())
+            .filter(|&giver| !self.players[giver].has_picked)
+            .collect();
+        if givers.len() < gifts {
+            return Err(late_join_impossible(
+                &player,
+                format!(
+                    "only {} players have not revealed yet and {} are needed",
+                    givers.len(),
+                    gifts
+                ),
+            ));
+        }
+        let givers: Vec<usize> = givers
+            .into_iter()
+            .filter(|&giver| allowed(&self.players[giver], &player))
+            .collect();
+        if givers.len() < gifts {
+            return Err(late_join_impossible(
+                &player,
+                format!(
+                    "exclusions and groups only let {} of the players who have not revealed \
+                     yet give to them, {} are needed",
+                    givers.len(),
+                    gifts
+                ),
+            ));
+        }
+
+        // every giver -> receiver pair that could go through the newcomer
+        let mut reroutes: Vec<(usize, usize)> = givers
+            .iter()
+            .flat_map(|&giver| {
+                self.players[giver]
+                    .picked
+                    .iter()
+                    .filter_map(|name| self.find_player(name))
+                    .filter(|&receiver| allowed(&player, &self.players[receiver]))
+                    .map(move |receiver| (giver, receiver))
+            })
+            .collect();
+        reroutes.shuffle(&mut thread_rng());
+        let no_mutual_pairs = self.draw_strategy == DrawStrategy::NoMutualPairs;
+        let mut chosen = vec![];
+        if !choose_reroutes(&reroutes, gifts, no_mutual_pairs, 0, &mut chosen) {
+            return Err(late_join_impossible(
+                &player,
+                "exclusions, groups and history leave no recipient they could take over"
+                    .to_string(),
+            ));
+        }
+
+        let mut changed = vec![];
+        for (giver, receiver) in chosen.into_iter().map(|index| reroutes[index]) {
+            let receiver_name = self.players[receiver].name.clone();
+            let giver = &mut self.players[giver];
+            for picked in giver.picked.iter_mut().filter(|p| **p == receiver_name) {
+                *picked = player.name.clone();
+            }
+            changed.push(giver.name.clone());
+            player.picked.push(receiver_name);
+        }
+        player.token = Some(generate_token());
+        self.players.push(player);
+        if let Some(audit) = self.audit.as_mut() {
+            audit.amended = true;
+        }
+        Ok(changed)
+    }
+
+    // Names already in the game, or earlier in the batch, that look like each
+    // new player without being the same name. Worth a warning, not an error.
+    pub fn similar_names(&self, players: &[Player]) -> Vec<Vec<String>> {
+        players
+            .iter()
+            .enumerate()
+            .map(|(position, player)| {
+                let key = name_key(&player.name);
+                self.players
+                    .iter()
+                    .chain(&players[..position])
+                    .filter(|p| name_key(&p.name) != key && looks_alike(&p.name, &player.name))
+                    .map(|p| p.display_name().to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn set_name_rules(&mut self, rules: NameRules) -> Result<(), GameError> {
+        self.check_before_draw()?;
+        if rules.min_length == 0 || rules.min_length > rules.max_length {
+            return Err(GameError::InvalidNameRules);
+        }
+        self.name_rules = rules;
+        Ok(())
+    }
+
+    pub fn set_draw_strategy(&mut self, strategy: DrawStrategy) -> Result<(), GameError> {
+        self.check_before_draw()?;
+        self.draw_strategy = strategy;
+        Ok(())
+    }
+
+    pub fn set_gifts_per_person(&mut self, gifts: usize) -> Result<(), GameError> {
+        self.check_before_draw()?;
+        if gifts == 0 {
+            return Err(GameError::InvalidGiftsPerPerson);
+        }
+        self.gifts_per_person = gifts;
+        Ok(())
+    }
+
+    fn find_player(&self, player_name: &str) -> Option<usize> {
+        let key = name_key(player_name);
+        self.players.iter().position(|p| name_key(&p.name) == key)
+    }
+
+    pub fn remove_player(&mut self, player_name: &str) -> Result<(), GameError> {
+        self.check_registration()?;
+        self.take_out(player_name).map(|_| ())
+    }
+
+    // Removes a player with their exclusions and every message they sent or
+    // got, so a later player of the same name finds none of it.
+    fn take_out(&mut self, player_name: &str) -> Result<Player, GameError> {
+        let index = self
+            .find_player(player_name)
+            .ok_or_else(|| GameError::PlayerNotFound {
+                name: player_name.to_string(),
+            })?;
+        let player = self.players.remove(index);
+        self.exclusions.retain(|e| !e.involves(&player.name));
+        self.messages
+            .retain(|m| m.giver != player.name && m.receiver != player.name);
+        if let Some(audit) = self.audit.as_mut() {
+            for drawn in audit.players.iter_mut().filter(|p| p.name == player.name) {
+                drawn.wishlist.clear();
+            }
+        }
+        Ok(player)
+    }
+
+    // Takes a player out of the running game, optionally swapping in a
+    // replacement, and repairs the assignment around the gap. In order of
+    // preference: the replacement takes over the leaver's place, the leaver's
+    // givers take over the leaver's recipients, the players who have not
+    // revealed are drawn again, everybody is drawn again. Whoever already
+    // revealed and got a new recipient can reveal again.
+    pub fn withdraw_player(
+        &mut self,
+        player_name: &str,
+        replacement: Option<Player>,
+    ) -> Result<Repair, GameError> {
+        let mut game = self.clone();
+        match self.status {
+            // a closed registration keeps newcomers out, not leavers
+            GameStatus::Open | GameStatus::Draft => {
+                game.take_out(player_name)?;
+                if let Some(mut player) = replacement {
+                    game.check_new_player(&player, &[])?;
+                    player.token = Some(generate_token());
+                    game.players.push(player);
+                }
+                *self = game;
+                return Ok(Repair::default());
+            }
+            GameStatus::Closed | GameStatus::Archived => return Err(GameError::Finished),
+            GameStatus::Drawn | GameStatus::Revealing => {}
+        }
+        let index = self
+            .find_player(player_name)
+            .ok_or_else(|| GameError::PlayerNotFound {
+                name: player_name.to_string(),
+            })?;
+        let leaver = game.take_out(player_name)?;
+        if let Some(player) = &replacement {
+            game.check_new_player(player, &[])?;
+        }
+
+        match replacement {
+            Some(mut player) if game.can_take_over(&leaver, &player) => {
+                for giver in game.players.iter_mut() {
+                    for picked in giver.picked.iter_mut().filter(|p| **p == leaver.name) {
+                        *picked = player.name.clone();
+                    }
+                }
+                player.picked = leaver.picked.clone();
+                player.token = Some(generate_token());
+                game.players.insert(index, player);
+            }
+            replacement => {
+                if game.players.len() < 2 || game.players.len() <= game.gifts_per_person {
+                    return Err(GameError::NotEnoughPlayers);
+                }
+                game.repair_without(&leaver)?;
+                if let Some(player) = replacement {
+                    game.add_late_player(player)?;
+                }
+            }
+        }
+
+        let mut repair = Repair::default();
+        for player in game.players.iter_mut() {
+            let Some(before) = self.players.iter().find(|p| p.name == player.name) else {
+                continue;
+            };
+            let mut picked = player.picked.clone();
+            let mut picked_before = before.picked.clone();
+            picked.sort();
+            picked_before.sort();
+            if picked == picked_before {
+                continue;
+            }
+            repair.reassigned.push(player.name.clone());
+            if before.has_picked {
+                player.has_picked = false;
+                repair.reveal_again.push(player.name.clone());
+            }
+        }
+        if let Some(audit) = game.audit.as_mut() {
+            audit.amended = true;
+        }
+        *self = game;
+        Ok(repair)
+    }
+
+    fn can_take_over(&self, leaver: &Player, player: &Player) -> bool {
+        let avoided = avoided_pairs(&self.strictly_avoided_draws());
+        let gives_to_all = leaver
+            .picked
+            .iter()
+            .filter_map(|name| self.find_player(name))
+            .all(|receiver| self.allowed(player, &self.players[receiver], &avoided));
+        let receives_from_all = self
+            .players
+            .iter()
+            .filter(|giver| giver.picked.contains(&leaver.name))
+            .all(|giver| self.allowed(giver, player, &avoided));
+        gives_to_all && receives_from_all
+    }
+
+    // Fills the gap left by a player already taken out of `players`.
+    fn repair_without(&mut self, leaver: &Player) -> Result<(), GameError> {
+        let givers: Vec<usize> = (0..self.players.len())
+            .filter(|&giver| self.players[giver].picked.contains(&leaver.name))
+            .collect();
+        let receivers: Vec<usize> = leaver
+            .picked
+            .iter()
+            .filter_map(|name| self.find_player(name))
+            .collect();
+        for &giver in &givers {
+            self.players[giver].picked.retain(|p| *p != leaver.name);
+        }
+
+        let allowed = {
+            let avoided_draws = self.strictly_avoided_draws();
+            self.allowed_pairs(&avoided_draws)
+        };
+        // bypassing the leaver splits a single cycle once gifts go further
+        // than the next player
+        if self.draw_strategy != DrawStrategy::SingleCycle || self.gifts_per_person == 1 {
+            let mut chosen = vec![];
+            if self.bypass(&allowed, &givers, &receivers, &mut chosen) {
+                for (&giver, slot) in givers.iter().zip(chosen) {
+                    let receiver = self.players[receivers[slot]].name.clone();
+                    self.players[giver].picked.push(receiver);
+                }
+                return Ok(());
+            }
+        }
+
+        let revealed: Vec<bool> = (0..self.players.len())
+            .map(|player| self.players[player].has_picked && !givers.contains(&player))
+            .collect();
+        if self.redraw(allowed.clone(), &revealed).is_ok() {
+            return Ok(());
+        }
+        self.redraw(allowed, &vec![false; self.players.len()])
+    }
+
+    // Matches every giver left without a recipient to a different recipient
+    // left without a giver.
+    fn bypass(
+        &self,
+        allowed: &[Vec<bool>],
+        givers: &[usize],
+        receivers: &[usize],
+        chosen: &mut Vec<usize>,
+    ) -> bool {
+        let Some(&giver) = givers.get(chosen.len()) else {
+            return true;
+        };
+        let no_mutual_pairs = self.draw_strategy == DrawStrategy::NoMutualPairs;
+        for (slot, &receiver) in receivers.iter().enumerate() {
+            let gives_back = self.players[receiver]
+                .picked
+                .contains(&self.players[giver].name)
+                || givers
+                    .iter()
+                    .zip(chosen.iter())
+                    .any(|(&g, &s)| g == receiver && receivers[s] == giver);
+            if chosen.contains(&slot)
+                || !allowed[giver][receiver]
+                || self.players[giver]
+                    .picked
+                    .contains(&self.players[receiver].name)
+                || (no_mutual_pairs && gives_back)
+            {
+                continue;
+            }
+            chosen.push(slot);
+            if self.bypass(allowed, givers, receivers, chosen) {
+                return true;
+            }
+            chosen.pop();
+        }
+        false
+    }
+
+    // Draws again, except for the `fixed` players who keep their recipients.
+    fn redraw(&mut self, mut allowed: Vec<Vec<bool>>, fixed: &[bool]) -> Result<(), GameError> {
+        for (giver, row) in allowed.iter_mut().enumerate() {
+            if fixed[giver] {
+                for (receiver, cell) in row.iter_mut().enumerate() {
+                    *cell = self.players[giver]
+                        .picked
+                        .contains(&self.players[receiver].name);
+                }
+            }
+        }
+        let mut rng = seeded_rng(&generate_token());
+        let receivers = find_assignment(
+            &allowed,
+            self.draw_strategy,
+            self.gifts_per_person,
+            &mut rng,
+            &mut SEARCH_BUDGET.clone(),
+        )?;
+        for (giver, receivers) in receivers.into_iter().enumerate() {
+            self.players[giver].picked = receivers
+                .into_iter()
+                .map(|receiver| self.players[receiver].name.clone())
+                .collect();
+        }
+        Ok(())
+    }
+
+    pub fn add_exclusion(&mut self, mut exclusion: Exclusion) -> Result<(), GameError> {
+        self.check_before_draw()?;
+        for name in [&mut exclusion.giver, &mut exclusion.receiver] {
+            let index = self
+                .find_player(name)
+                .ok_or_else(|| GameError::PlayerNotFound { name: name.clone() })?;
+            *name = self.players[index].name.clone();
+        }
+        if exclusion.giver == exclusion.receiver {
+            return Err(GameError::SelfExclusion);
+        }
+        let already_covered = self
+            .exclusions
+            .iter()
+            .any(|e| e.forbids(&exclusion.giver, &exclusion.receiver))
+            && (!exclusion.symmetric
+                || self
+                    .exclusions
+                    .iter()
+                    .any(|e| e.forbids(&exclusion.receiver, &exclusion.giver)));
+        if already_covered {
+            return Err(GameError::ExclusionAlreadyExists {
+                giver: exclusion.giver,
+                receiver: exclusion.receiver,
+            });
+        }
+        self.exclusions.push(exclusion);
+        Ok(())
+    }
+
+    pub fn remove_exclusion(&mut self, giver: &str, receiver: &str) -> Result<(), GameError> {
+        self.check_before_draw()?;
+        let (giver, receiver) = (name_key(giver), name_key(receiver));
+        let before = self.exclusions.len();
+        self.exclusions
+            .retain(|e| !(name_key(&e.giver) == giver && name_key(&e.receiver) == receiver));
+        if self.exclusions.len() == before {
+            return Err(GameError::ExclusionNotFound);
+        }
+        Ok(())
+    }
+
+    pub fn set_history(
+        &mut self,
+        history: Vec<PastDraw>,
+        policy: HistoryPolicy,
+    ) -> Result<(), GameError> {
+        self.check_before_draw()?;
+        if policy == HistoryPolicy::AvoidLastYears(0) {
+            return Err(GameError::EmptyHistoryWindow);
+        }
+        self.history = history;
+        self.history_policy = policy;
+        Ok(())
+    }
+
+    pub fn export_draw(&self, year: u16) -> Result<PastDraw, GameError> {
+        self.check_exchange_done()?;
+        Ok(PastDraw {
+            year,
+            pairings: self.pairings(),
+        })
+    }
+
+    fn pairings(&self) -> Vec<Pairing> {
+        self.players
+            .iter()
+            .flat_map(|player| {
+                player.picked.iter().map(|picked| Pairing {
+                    giver: player.name.clone(),
+                    receiver: picked.clone(),
+                })
+            })
+            .collect()
+    }
+
+    // The organizer's seed is followed by a random token, as a chosen seed
+    // could be guessed and the players are public. The whole seed stays
+    // secret until `reveal_seed`. Player tokens never come from the seed.
+    pub fn start_game(&mut self, seed: Option<String>) -> Result<(), GameError> {
+        match self.status {
+            GameStatus::Open | GameStatus::Draft => {}
+            GameStatus::Drawn | GameStatus::Revealing => return Err(GameError::AlreadyStarted),
+            GameStatus::Closed | GameStatus::Archived => return Err(GameError::AlreadyFinished),
+        }
+
+        // nobody can give to themselves, so k gifts need k other players
+        if self.players.len() < 2 || self.players.len() <= self.gifts_per_person {
+            return Err(GameError::NotEnoughPlayers);
+        }
+
+        let seed = match seed {
+            Some(seed) => format!("{}:{}", seed, generate_token()),
+            None => generate_token(),
+        };
+        let (players, exclusions) = (self.players.clone(), self.exclusions.clone());
+        self.sort_players(&seed)?;
+        for player in self.players.iter_mut() {
+            player.token = Some(generate_token());
+        }
+        self.audit = Some(DrawAudit {
+            commitment: draw_commitment(&seed, &self.pairings()),
+            seed,
+            seed_revealed: false,
+            players,
+            exclusions,
+            amended: false,
+        });
+        self.status = GameStatus::Drawn;
+        Ok(())
+    }
+
+    pub fn close_registration(&mut self) -> Result<(), GameError> {
+        self.transition(GameStatus::Open, GameStatus::Draft)
+    }
+
+    pub fn reopen_registration(&mut self) -> Result<(), GameError> {
+        self.transition(GameStatus::Draft, GameStatus::Open)
+    }
+
+    pub fn open_reveals(&mut self) -> Result<(), GameError> {
+        self.transition(GameStatus::Drawn, GameStatus::Revealing)
+    }
+
+    pub fn close_exchange(&mut self) -> Result<(), GameError> {
+        self.transition(GameStatus::Revealing, GameStatus::Closed)
+    }
+
+    pub fn archive(&mut self) -> Result<(), GameError> {
+        self.transition(GameStatus::Closed, GameStatus::Archived)
+    }
+
+    // The details stay editable, and readable, until the game is archived.
+    pub fn set_event(&mut self, event: EventDetails) -> Result<(), GameError> {
+        if self.status == GameStatus::Archived {
+            return Err(GameError::AlreadyFinished);
+        }
+        self.event = event.cleaned()?;
+        Ok(())
+    }
+
+    // Webhooks outlive resets, only the organizer removes them.
+    pub fn add_webhook(
+        &mut self,
+        url: &str,
+        mut events: Vec<String>,
+    ) -> Result<Webhook, GameError> {
+        if self.webhooks.len() >= MAX_WEBHOOKS {
+            return Err(GameError::TooManyWebhooks { max: MAX_WEBHOOKS });
+        }
+        let url = url.trim();
+        let address = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"));
+        if address.is_none_or(str::is_empty)
+            || url.len() > MAX_WEBHOOK_URL_LENGTH
+            || url.contains(char::is_whitespace)
+        {
+            return Err(GameError::InvalidWebhookUrl {
+                url: url.to_string(),
+            });
+        }
+        if let Some(event) = events
+            .iter()
+            .find(|e| !WEBHOOK_EVENTS.contains(&e.as_str()))
+        {
+            return Err(GameError::UnknownWebhookEvent {
+                event: event.clone(),
+            });
+        }
+        events.sort();
+        events.dedup();
+        let webhook = Webhook {
+            id: generate_token()[..12].to_string(),
+            url: url.to_string(),
+            secret: generate_token(),
+            events,
+        };
+        self.webhooks.push(webhook.clone());
+        Ok(webhook)
+    }
+
+    pub fn remove_webhook(&mut self, id: &str) -> Result<(), GameError> {
+        let before = self.webhooks.len();
+        self.webhooks.retain(|webhook| webhook.id != id);
+        if self.webhooks.len() == before {
+            return Err(GameError::WebhookNotFound);
+        }
+        Ok(())
+    }
+
+    // Dates can move until the exchange is over.
+    pub fn set_schedule(&mut self, schedule: Schedule) -> Result<(), GameError> {
+        if matches!(self.status, GameStatus::Closed | GameStatus::Archived) {
+            return Err(GameError::AlreadyFinished);
+        }
+        schedule.validate()?;
+        self.schedule = schedule;
+        Ok(())
+    }
+
+    // The phase the schedule asks for at `now`: reveals open on their date
+    // and the exchange closes on its own once its date has passed.
+    pub fn scheduled_status(&self, now: u64) -> GameStatus {
+        let passed = |at: Option<u64>| at.is_some_and(|at| at <= now);
+        let mut status = self.status;
+        if status == GameStatus::Drawn && passed(self.schedule.reveals_at) {
+            status = GameStatus::Revealing;
+        }
+        if status == GameStatus::Revealing && passed(self.schedule.exchange_at) {
+            status = GameStatus::Closed;
+        }
+        status
+    }
+
+    // Returns whether the phase changed.
+    pub fn advance_schedule(&mut self, now: u64) -> bool {
+        let status = self.scheduled_status(now);
+        let changed = status != self.status;
+        self.status = status;
+        changed
+    }
+
+    fn transition(&mut self, from: GameStatus, to: GameStatus) -> Result<(), GameError> {
+        if self.status != from {
+            return Err(GameError::InvalidTransition {
+                from: self.status,
+                to,
+            });
+        }
+        self.status = to;
+        Ok(())
+    }
+
+    // Throws the draw away and opens registration again, players and
+    // exclusions stay.
+    pub fn restart_game(&mut self) -> Result<(), GameError> {
+        if self.status == GameStatus::Archived {
+            return Err(GameError::InvalidTransition {
+                from: self.status,
+                to: GameStatus::Open,
+            });
+        }
+        for player in self.players.iter_mut() {
+            player.picked = vec![];
+            player.token = Some(generate_token());
+            player.has_picked = false;
+        }
+        self.status = GameStatus::Open;
+        self.audit = None;
+        self.messages.clear();
+        Ok(())
+    }
+
+    pub fn clear_players(&mut self) -> Result<(), GameError> {
+        self.check_registration()?;
+        self.players = vec![];
+        self.exclusions = vec![];
+        Ok(())
+    }
+
+    fn check_exchange_done(&self) -> Result<(), GameError> {
+        if !matches!(self.status, GameStatus::Closed | GameStatus::Archived) {
+            return Err(GameError::NotFinished);
+        }
+        Ok(())
+    }
+
+    // Only once the exchange is done, earlier the seed would give the draw away.
+    pub fn reveal_seed(&mut self) -> Result<String, GameError> {
+        self.check_exchange_done()?;
+        let audit = self.audit.as_mut().ok_or(GameError::NotStarted)?;
+        audit.seed_revealed = true;
+        Ok(audit.seed.clone())
+    }
+
+    // Draws again from the revealed seed and checks that the fresh draw, and
+    // the stored assignment unless it was amended since, match the commitment.
+    pub fn verify_draw(&self) -> Result<bool, GameError> {
+        let audit = self.audit.as_ref().ok_or(GameError::NotStarted)?;
+        let seed = audit.revealed_seed().ok_or(GameError::SeedNotRevealed)?;
+        let mut redraw = self.clone();
+        if !audit.players.is_empty() {
+            redraw.players = audit.players.clone();
+            redraw.exclusions = audit.exclusions.clone();
+        }
+        for player in redraw.players.iter_mut() {
+            player.picked = vec![];
+        }
+        redraw.sort_players(seed)?;
+        Ok(
+            draw_commitment(seed, &redraw.pairings()) == audit.commitment
+                && (audit.amended || draw_commitment(seed, &self.pairings()) == audit.commitment),
+        )
+    }
+
+    fn sort_players(&mut self, seed: &str) -> Result<(), GameError> {
+        if let Some(group) = self.oversized_group() {
+            return Err(GameError::OversizedGroup { group });
+        }
+
+        let mut rng = seeded_rng(seed);
+        let original_order = self.players.clone();
+        self.suffle_players(&mut rng);
+        let mut budget = SEARCH_BUDGET;
+        let receivers = match self.find_assignment_avoiding_history(&mut rng, &mut budget) {
+            Ok(receivers) => receivers,
+            Err(err) => {
+                // a failed draw leaves the game as it was
+                self.players = original_order;
+                return Err(err);
+            }
+        };
+
+        for (giver, receivers) in receivers.into_iter().enumerate() {
+            self.players[giver].picked = receivers
+                .into_iter()
+                .map(|receiver| self.players[receiver].name.clone())
+                .collect();
+        }
+        Ok(())
+    }
+
+    // Everyone in a group needs a giver and a recipient from outside of it,
+    // which is impossible once a group holds more than half of the players.
+    fn oversized_group(&self) -> Option<String> {
+        let mut group_sizes: HashMap<&str, usize> = HashMap::new();
+        for group in self.players.iter().filter_map(|p| p.group.as_deref()) {
+            *group_sizes.entry(group).or_default() += 1;
+        }
+        group_sizes
+            .into_iter()
+            .find(|(_, size)| size * 2 > self.players.len())
+            .map(|(group, _)| group.to_string())
+    }
+
+    // Past draws that can never be repeated, only AvoidLastYears has any.
+    fn strictly_avoided_draws(&self) -> Vec<&PastDraw> {
+        let HistoryPolicy::AvoidLastYears(years) = self.history_policy else {
+            return vec![];
+        };
+        let newest_year = self
+            .history
+            .iter()
+            .map(|d| d.year)
+            .max()
+            .unwrap_or_default();
+        self.history
+            .iter()
+            .filter(|d| newest_year - d.year < years)
+            .collect()
+    }
+
+    // `budget` is the number of search steps the draw may take.
+    fn find_assignment_avoiding_history(
+        &self,
+        rng: &mut ChaCha20Rng,
+        budget: &mut usize,
+    ) -> Result<Vec<Vec<usize>>, GameError> {
+        let mut draws: Vec<&PastDraw> = self.history.iter().collect();
+        draws.sort_by_key(|d| std::cmp::Reverse(d.year));
+        let strategy = self.draw_strategy;
+        let gifts = self.gifts_per_person;
+
+        match self.history_policy {
+            HistoryPolicy::AvoidLastYears(_) => {
+                let draws = self.strictly_avoided_draws();
+                find_assignment(&self.allowed_pairs(&draws), strategy, gifts, rng, budget)
+            }
+            // forget the oldest draws one by one until an assignment exists,
+            // giving up at once when not even forgetting them all would help
+            HistoryPolicy::Prefer => {
+                if !assignment_possible(&self.allowed_pairs(&[]), gifts) {
+                    return Err(GameError::NoValidAssignment);
+                }
+                let mut error = GameError::NoValidAssignment;
+                for kept in (0..=draws.len()).rev() {
+                    match find_assignment(
+                        &self.allowed_pairs(&draws[..kept]),
+                        strategy,
+                        gifts,
+                        rng,
+                        budget,
+                    ) {
+                        Ok(receivers) => return Ok(receivers),
+                        Err(GameError::SearchGaveUp) => error = GameError::SearchGaveUp,
+                        Err(_) => {}
+                    }
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn allowed_pairs(&self, avoided_draws: &[&PastDraw]) -> Vec<Vec<bool>> {
+        let avoided = avoided_pairs(avoided_draws);
+        self.players
+            .iter()
+            .map(|giver| {
+                self.players
+                    .iter()
+                    .map(|receiver| self.allowed(giver, receiver, &avoided))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Past pairings are matched by name key, a player who was re-cased or
+    // re-spaced since is still the same player.
+    fn allowed(
+        &self,
+        giver: &Player,
+        receiver: &Player,
+        avoided: &HashSet<(String, String)>,
+    ) -> bool {
+        self.can_give(giver, receiver)
+            && !avoided.contains(&(name_key(&giver.name), name_key(&receiver.name)))
+    }
+
+    fn can_give(&self, giver: &Player, receiver: &Player) -> bool {
+        if giver.name == receiver.name {
+            return false;
+        }
+        if giver.group.is_some() && giver.group == receiver.group {
+            return false;
+        }
+        !self
+            .exclusions
+            .iter()
+            .any(|e| e.forbids(&giver.name, &receiver.name))
+    }
+
+    // Sorted first so the order players were added in does not matter.
+    fn suffle_players(&mut self, rng: &mut ChaCha20Rng) {
+        self.players.sort_by(|a, b| a.name.cmp(&b.name));
+        self.players.shuffle(rng);
+    }
+
+    // Whether recipients can be shown at `now`, by link or by email.
+    pub fn check_reveals_open(&self, now: u64) -> Result<(), GameError> {
+        let status = self.scheduled_status(now);
+        if let Some(reveals_at) = self.schedule.reveals_at {
+            if now < reveals_at && matches!(status, GameStatus::Drawn | GameStatus::Revealing) {
+                return Err(GameError::RevealsScheduled {
+                    at: format_timestamp(reveals_at),
+                });
+            }
+        }
+        match status {
+            GameStatus::Open | GameStatus::Draft => Err(GameError::NotStarted),
+            GameStatus::Drawn => Err(GameError::RevealsNotOpen),
+            GameStatus::Revealing => Ok(()),
+            GameStatus::Closed | GameStatus::Archived => Err(GameError::Finished),
+        }
+    }
+
+    pub fn player_pick(&mut self, player_name: &str) -> Result<Vec<String>, GameError> {
+        self.check_reveals_open(unix_now())?;
+
+        if let Some(index) = self.find_player(player_name) {
+            let player = &mut self.players[index];
+            if player.has_picked {
+                return Err(GameError::AlreadyPicked);
+            }
+            player.has_picked = true;
+            Ok(player.picked.clone())
+        } else {
+            Err(GameError::PlayerNotFound {
+                name: player_name.to_string(),
+            })
+        }
+    }
+
+    // The player behind a personal link.
+    pub fn player_by_token(&self, token: &str) -> Result<&Player, GameError> {
+        self.players
+            .iter()
+            .find(|p| p.token() == Some(token))
+            .ok_or(GameError::InvalidToken)
+    }
+
+    // Players edit their own wishlist through their personal link, the
+    // whole list is replaced at once.
+    pub fn set_wishlist(&mut self, token: &str, items: Vec<WishlistItem>) -> Result<(), GameError> {
+        if matches!(self.status, GameStatus::Closed | GameStatus::Archived) {
+            return Err(GameError::Finished);
+        }
+        if items.len() > MAX_WISHLIST_ITEMS {
+            return Err(GameError::TooManyWishlistItems {
+                max: MAX_WISHLIST_ITEMS,
+            });
+        }
+        let items = items
+            .into_iter()
+            .map(WishlistItem::cleaned)
+            .collect::<Result<Vec<_>, _>>()?;
+        let player = self
+            .players
+            .iter_mut()
+            .find(|p| p.token() == Some(token))
+            .ok_or(GameError::InvalidToken)?;
+        player.wishlist = items;
+        Ok(())
+    }
+
+    pub fn wishlist(&self, token: &str) -> Result<&[WishlistItem], GameError> {
+        Ok(&self.player_by_token(token)?.wishlist)
+    }
+
+    pub fn wishlist_of(&self, player_name: &str) -> &[WishlistItem] {
+        self.find_player(player_name)
+            .map(|index| self.players[index].wishlist.as_slice())
+            .unwrap_or_default()
+    }
+
+    // Lets a player come back to their recipients, and to the wishlists
+    // that may have changed since.
+    pub fn revealed_recipients(&self, token: &str) -> Result<Vec<String>, GameError> {
+        let player = self.player_by_token(token)?;
+        if !player.has_picked {
+            return Err(GameError::NotRevealed);
+        }
+        Ok(player.picked.clone())
+    }
+
+    // Threads stay readable after the exchange, for the thank-you notes.
+    fn check_messaging(&self) -> Result<(), GameError> {
+        match self.status {
+            GameStatus::Open | GameStatus::Draft => Err(GameError::NotStarted),
+            GameStatus::Drawn => Err(GameError::RevealsNotOpen),
+            GameStatus::Revealing | GameStatus::Closed => Ok(()),
+            GameStatus::Archived => Err(GameError::Finished),
+        }
+    }
+
+    fn thread(&self, giver: &str, receiver: &str, as_santa: bool) -> Vec<ThreadMessage> {
+        self.messages
+            .iter()
+            .filter(|m| m.giver == giver && m.receiver == receiver)
+            .map(|m| ThreadMessage {
+                mine: m.from_santa == as_santa,
+                text: m.text.clone(),
+                sent_at: m.sent_at,
+            })
+            .collect()
+    }
+
+    // A giver writes to one of the recipients they revealed.
+    pub fn message_recipient(
+        &mut self,
+        token: &str,
+        recipient: &str,
+        text: &str,
+        now: u64,
+    ) -> Result<(), GameError> {
+        self.check_messaging()?;
+        let text = clean_message(text)?;
+        let santa = self.player_by_token(token)?;
+        if !santa.has_picked {
+            return Err(GameError::NotRevealed);
+        }
+        let key = name_key(recipient);
+        let receiver = santa
+            .picked
+            .iter()
+            .find(|name| name_key(name) == key)
+            .ok_or(GameError::ThreadNotFound)?
+            .clone();
+        self.messages.push(Message {
+            giver: santa.name.clone(),
+            receiver,
+            from_santa: true,
+            text,
+            sent_at: now,
+        });
+        Ok(())
+    }
+
+    // A recipient answers one of their Santas, known only by its thread id.
+    pub fn message_santa(
+        &mut self,
+        token: &str,
+        thread: &str,
+        text: &str,
+        now: u64,
+    ) -> Result<(), GameError> {
+        self.check_messaging()?;
+        let text = clean_message(text)?;
+        let receiver = self.player_by_token(token)?.name.clone();
+        let giver = self
+            .players
+            .iter()
+            .find(|p| p.picked.contains(&receiver) && santa_id(p, &receiver) == thread)
+            .ok_or(GameError::ThreadNotFound)?
+            .name
+            .clone();
+        self.messages.push(Message {
+            giver,
+            receiver,
+            from_santa: false,
+            text,
+            sent_at: now,
+        });
+        Ok(())
+    }
+
+    // Threads with the player's recipients, once they revealed them.
+    pub fn threads_as_santa(&self, token: &str) -> Result<Vec<Thread>, GameError> {
+        self.check_messaging()?;
+        let santa = self.player_by_token(token)?;
+        if !santa.has_picked {
+            return Ok(vec![]);
+        }
+        Ok(santa
+            .picked
+            .iter()
+            .map(|receiver| Thread {
+                with: receiver.clone(),
+                messages: self.thread(&santa.name, receiver, true),
+            })
+            .collect())
+    }
+
+    // Threads with the player's Santas, sorted by id so the order gives
+    // nothing away either.
+    pub fn threads_as_recipient(&self, token: &str) -> Result<Vec<Thread>, GameError> {
+        self.check_messaging()?;
+        let receiver = &self.player_by_token(token)?.name;
+        let mut threads: Vec<Thread> = self
+            .players
+            .iter()
+            .filter(|p| p.picked.contains(receiver))
+            .map(|santa| Thread {
+                with: santa_id(santa, receiver),
+                messages: self.thread(&santa.name, receiver, false),
+            })
+            .collect();
+        threads.sort_by(|a, b| a.with.cmp(&b.with));
+        Ok(threads)
+    }
+
+    pub fn player_pick_by_token(&mut self, token: &str) -> Result<Vec<String>, GameError> {
+        self.check_reveals_open(unix_now())?;
+
+        let player_name = self
+            .players
+            .iter()
+            .find(|p| p.token() == Some(token))
+            .map(|p| p.name.clone())
+            .ok_or(GameError::InvalidToken)?;
+        self.player_pick(&player_name)
+    }
+}
+
+pub fn generate_token() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn late_join_impossible(player: &Player, reason: String) -> GameError {
+    GameError::LateJoinImpossible {
+        name: player.name.clone(),
+        reason,
+    }
+}
+
+// Picks `gifts` giver -> receiver pairs for a newcomer to go through, with
+// different givers and different receivers. Under NoMutualPairs the newcomer
+// cannot give to someone who now gives to them.
+fn choose_reroutes(
+    reroutes: &[(usize, usize)],
+    gifts: usize,
+    no_mutual_pairs: bool,
+    start: usize,
+    chosen: &mut Vec<usize>,
+) -> bool {
+    if chosen.len() == gifts {
+        return true;
+    }
+    for index in start..reroutes.len() {
+        let (giver, receiver) = reroutes[index];
+        let clashes = chosen.iter().map(|&c| reroutes[c]).any(|(g, r)| {
+            g == giver || r == receiver || (no_mutual_pairs && (g == receiver || r == giver))
+        });
+        if clashes {
+            continue;
+        }
+        chosen.push(index);
+        if choose_reroutes(reroutes, gifts, no_mutual_pairs, index + 1, chosen) {
+            return true;
+        }
+        chosen.pop();
+    }
+    false
+}
+
+// How many steps the searches below may take before giving up. They
+// backtrack, which takes exponential time when the constraints leave few
+// ways out, and they run with the game store locked.
+const SEARCH_BUDGET: usize = 200_000;
+
+// Whether each giver can get `gifts` receivers with every player receiving
+// `gifts` times, whatever the strategy. Grows one augmenting path at a time,
+// in polynomial time, so hopeless draws fail before any search.
+fn assignment_possible(allowed: &[Vec<bool>], gifts: usize) -> bool {
+    fn augment(
+        giver: usize,
+        allowed: &[Vec<bool>],
+        gifts: usize,
+        givers_of: &mut [Vec<usize>],
+        visited: &mut [bool],
+    ) -> bool {
+        for receiver in 0..allowed.len() {
+            if !allowed[giver][receiver]
+                || visited[receiver]
+                || givers_of[receiver].contains(&giver)
+            {
+                continue;
+            }
+            visited[receiver] = true;
+            if givers_of[receiver].len() < gifts {
+                givers_of[receiver].push(giver);
+                return true;
+            }
+            for index in 0..givers_of[receiver].len() {
+                let other = givers_of[receiver][index];
+                if augment(other, allowed, gifts, givers_of, visited) {
+                    givers_of[receiver][index] = giver;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // the quick test first, everyone needs `gifts` receivers and givers
+    let size = allowed.len();
+    let receivers = |giver: usize| (0..size).filter(|&r| allowed[giver][r]).count();
+    let givers = |receiver: usize| (0..size).filter(|&g| allowed[g][receiver]).count();
+    if (0..size).any(|player| receivers(player) < gifts || givers(player) < gifts) {
+        return false;
+    }
+
+    let mut givers_of = vec![vec![]; allowed.len()];
+    (0..allowed.len()).all(|giver| {
+        (0..gifts).all(|_| {
+            let mut visited = vec![false; allowed.len()];
+            augment(giver, allowed, gifts, &mut givers_of, &mut visited)
+        })
+    })
+}
+
+// Whether every player can reach every other one through allowed pairs,
+// which a single circle through everyone needs.
+fn strongly_connected(allowed: &[Vec<bool>]) -> bool {
+    let reaches_everyone = |linked: &dyn Fn(usize, usize) -> bool| {
+        let mut reached = vec![false; allowed.len()];
+        let mut pending = vec![0];
+        reached[0] = true;
+        while let Some(player) = pending.pop() {
+            for (other, reached) in reached.iter_mut().enumerate() {
+                if !*reached && linked(player, other) {
+                    *reached = true;
+                    pending.push(other);
+                }
+            }
+        }
+        reached.into_iter().all(|r| r)
+    };
+    reaches_everyone(&|giver, receiver| allowed[giver][receiver])
+        && reaches_everyone(&|receiver, giver| allowed[giver][receiver])
+}
+
+// A search that ran out of steps cannot tell whether an assignment exists.
+fn search_failed(budget: usize) -> GameError {
+    if budget == 0 {
+        GameError::SearchGaveUp
+    } else {
+        GameError::NoValidAssignment
+    }
+}
+
+// Indexes of the receivers of every giver, following the draw strategy.
+// Each giver gets `gifts` different receivers and each player receives
+// `gifts` times. `budget` is shared by the searches of one draw.
+fn find_assignment(
+    allowed: &[Vec<bool>],
+    strategy: DrawStrategy,
+    gifts: usize,
+    rng: &mut ChaCha20Rng,
+    budget: &mut usize,
+) -> Result<Vec<Vec<usize>>, GameError> {
+    if !assignment_possible(allowed, gifts) {
+        return Err(GameError::NoValidAssignment);
+    }
+    match strategy {
+        DrawStrategy::SingleCycle => {
+            if !strongly_connected(allowed) {
+                return Err(GameError::NoValidAssignment);
+            }
+            let cycle = find_cycle(allowed, gifts, budget).ok_or(search_failed(*budget))?;
+            let mut receivers = vec![vec![]; cycle.len()];
+            for (position, &giver) in cycle.iter().enumerate() {
+                receivers[giver] = (1..=gifts)
+                    .map(|step| cycle[(position + step) % cycle.len()])
+                    .collect();
+            }
+            Ok(receivers)
+        }
+        DrawStrategy::AnyDerangement | DrawStrategy::NoMutualPairs => {
+            // shuffled so the first assignment found is not biased towards
+            // low indexes, which would favour small cycles
+            let candidates: Vec<Vec<usize>> = allowed
+                .iter()
+                .map(|row| {
+                    let mut receivers: Vec<usize> = (0..row.len()).filter(|&r| row[r]).collect();
+                    receivers.shuffle(rng);
+                    receivers
+                })
+                .collect();
+            // the most constrained givers go first, so their dead ends are
+            // found before the search branches over everyone else
+            let mut order: Vec<usize> = (0..allowed.len()).collect();
+            order.sort_by_key(|&giver| candidates[giver].len());
+            let mut receivers = vec![vec![]; allowed.len()];
+            let mut received = vec![0; allowed.len()];
+            let no_mutual_pairs = strategy == DrawStrategy::NoMutualPairs;
+            if extend_derangement(
+                &candidates,
+                &order,
+                gifts,
+                no_mutual_pairs,
+                &mut receivers,
+                &mut received,
+                budget,
+            ) {
+                Ok(receivers)
+            } else {
+                Err(search_failed(*budget))
+            }
+        }
+    }
+}
+
+// Gives a receiver to the next giver in `order` still missing one,
+// backtracking on dead ends. A giver's receivers are taken in candidate order, so every set of
+// receivers is only tried once.
+fn extend_derangement(
+    candidates: &[Vec<usize>],
+    order: &[usize],
+    gifts: usize,
+    no_mutual_pairs: bool,
+    receivers: &mut [Vec<usize>],
+    received: &mut [usize],
+    budget: &mut usize,
+) -> bool {
+    if *budget == 0 {
+        return false;
+    }
+    *budget -= 1;
+    let Some(&giver) = order.iter().find(|&&g| receivers[g].len() < gifts) else {
+        return true;
+    };
+    let start = match receivers[giver].last() {
+        Some(last) => candidates[giver].iter().position(|c| c == last).unwrap() + 1,
+        None => 0,
+    };
+    for &receiver in candidates[giver][start..].iter() {
+        if received[receiver] == gifts || (no_mutual_pairs && receivers[receiver].contains(&giver))
+        {
+            continue;
+        }
+        received[receiver] += 1;
+        receivers[giver].push(receiver);
+        if extend_derangement(
+            candidates,
+            order,
+            gifts,
+            no_mutual_pairs,
+            receivers,
+            received,
+            budget,
+        ) {
+            return true;
+        }
+        receivers[giver].pop();
+        received[receiver] -= 1;
+    }
+    false
+}
+
+// Depth-first search for a single circle going through every player, where
+// each player gives to the `gifts` players following them. Players are
+// shuffled beforehand, so the first circle found is random. The circle starts
+// at the player with the fewest allowed givers or receivers.
+fn find_cycle(allowed: &[Vec<bool>], gifts: usize, budget: &mut usize) -> Option<Vec<usize>> {
+    let size = allowed.len();
+    let options = |player: usize| {
+        let givers = (0..size).filter(|&g| allowed[g][player]).count();
+        let receivers = (0..size).filter(|&r| allowed[player][r]).count();
+        givers.min(receivers)
+    };
+    let first = (0..size).min_by_key(|&player| options(player))?;
+    let mut path = vec![first];
+    let mut used = vec![false; size];
+    used[first] = true;
+    if extend_cycle(allowed, gifts, &mut path, &mut used, budget) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn extend_cycle(
+    allowed: &[Vec<bool>],
+    gifts: usize,
+    path: &mut Vec<usize>,
+    used: &mut [bool],
+    budget: &mut usize,
+) -> bool {
+    if *budget == 0 {
+        return false;
+    }
+    *budget -= 1;
+    let size = allowed.len();
+    if path.len() == size {
+        // the last players also give to the first ones, around the circle
+        return (0..size).all(|position| {
+            (1..=gifts).all(|step| {
+                position + step < size || allowed[path[position]][path[(position + step) % size]]
+            })
+        });
+    }
+    // every player left still needs a giver among the players left or the
+    // end of the path, and a receiver among them or its start
+    let (first, last) = (path[0], path[path.len() - 1]);
+    let left: Vec<usize> = (0..size).filter(|&player| !used[player]).collect();
+    let mut forced = None;
+    for &player in &left {
+        let givers = left
+            .iter()
+            .chain([&last])
+            .filter(|&&giver| giver != player && allowed[giver][player])
+            .count();
+        let receivers = left
+            .iter()
+            .chain([&first])
+            .filter(|&&receiver| receiver != player && allowed[player][receiver])
+            .count();
+        if givers == 0 || receivers == 0 {
+            return false;
+        }
+        if givers == 1 && allowed[last][player] {
+            // only the end of the path can give to them, they come next
+            if forced.is_some() {
+                return false;
+            }
+            forced = Some(player);
+        }
+    }
+
+    // the players with the fewest receivers left go first
+    let mut candidates: Vec<usize> = match forced {
+        Some(player) => vec![player],
+        None => left.clone(),
+    };
+    candidates.retain(|&next| {
+        path.iter()
+            .rev()
+            .take(gifts)
+            .all(|&giver| allowed[giver][next])
+    });
+    candidates.sort_by_key(|&next| left.iter().filter(|&&r| allowed[next][r]).count());
+    for next in candidates {
+        used[next] = true;
+        path.push(next);
+        if extend_cycle(allowed, gifts, path, used, budget) {
+            return true;
+        }
+        path.pop();
+        used[next] = false;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_player_in_game() {
+        let player = Player::new("Player 1");
+        let mut game = SecretSantaGame {
+            status: GameStatus::Open,
+            players: vec![],
+            ..SecretSantaGame::default()
+        };
+        assert_eq!(game.players.len(), 0);
+
+        let _ = game.add_player(player);
+        assert_eq!(game.players.len(), 1);
+        assert_eq!(game.players[0].name, "Player 1");
+        assert!(game.players[0].token().is_some());
+        assert_eq!(
+            game.players,
+            vec![Player {
+                token: game.players[0].token.clone(),
+                ..Player::new("Player 1")
+            }],
+        );
+    }
+
+    #[test]
+    fn start_game() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        assert_eq!(game.status, GameStatus::Open);
+        let head = game.players[0].clone();
+        assert!(head.picked.is_empty());
+
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        assert_eq!(game.status, GameStatus::Revealing);
+        let head_picked_name = game.players[0].clone().picked[0].clone();
+        assert!(head_picked_name.contains("Player"));
+    }
+
+    #[test]
+    fn restart_game() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        assert_eq!(game.status, GameStatus::Open);
+        let head = game.players[0].clone();
+        assert!(head.picked.is_empty());
+
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        assert_eq!(game.status, GameStatus::Revealing);
+
+        game.restart_game().unwrap();
+        assert_eq!(game.status, GameStatus::Open);
+        assert_eq!(game.players.len(), 10);
+        assert!(game
+            .players
+            .iter()
+            .all(|p| p.picked.is_empty() && p.token != head.token && !p.has_picked));
+        assert!(game.audit.is_none());
+
+        game.clear_players().unwrap();
+        assert_eq!(game.players.len(), 0);
+    }
+
+    #[test]
+    fn pick_player() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        // start picking
+
+        for i in 0..game.players.len() {
+            let player_name = format!("Player {}", i);
+            let picked_name = game.player_pick(&player_name).unwrap();
+            assert!(picked_name[0].contains("Player"));
+        }
+
+        game.close_exchange().unwrap();
+        assert_eq!(game.status, GameStatus::Closed);
+    }
+
+    #[test]
+    fn start_game_with_less_than_two_players() {
+        let mut game = SecretSantaGame::default();
+        let player = Player::new("Player 1");
+        let _ = game.add_player(player);
+
+        let result = game.start_game(None);
+        assert_eq!(result, Err(GameError::NotEnoughPlayers));
+    }
+
+    #[test]
+    fn start_game_twice() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        let result = game.start_game(None);
+        assert_eq!(result, Err(GameError::AlreadyStarted));
+    }
+
+    #[test]
+    fn start_game_that_already_finished() {
+        let mut game = SecretSantaGame::default();
+
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        // start picking
+
+        for i in 0..game.players.len() {
+            let player_name = format!("Player {}", i);
+            let _ = game.player_pick(&player_name).unwrap();
+        }
+
+        game.close_exchange().unwrap();
+        assert_eq!(game.status, GameStatus::Closed);
+
+        let result = game.start_game(None);
+        assert_eq!(result, Err(GameError::AlreadyFinished));
+    }
+
+    #[test]
+    fn pick_player_that_not_started() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+
+        let player_name = "Player 1";
+        let result = game.player_pick(player_name);
+        assert_eq!(result, Err(GameError::NotStarted));
+    }
+
+    #[test]
+    fn pick_player_that_already_finished() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        // start picking
+
+        for i in 0..game.players.len() {
+            let player_name = format!("Player {}", i);
+            let _ = game.player_pick(&player_name).unwrap();
+        }
+
+        game.close_exchange().unwrap();
+        assert_eq!(game.status, GameStatus::Closed);
+
+        let player_name = "Player 1";
+        let result = game.player_pick(player_name);
+        assert_eq!(result, Err(GameError::Finished));
+    }
+
+    #[test]
+    fn add_player_in_a_finished_game() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..10 {
+            let player = Player::new(&format!("Player {}", i));
+            let _ = game.add_player(player);
+        }
+        let _ = game.start_game(None);
+        game.open_reveals().unwrap();
+
+        // start picking
+
+        for i in 0..game.players.len() {
+            let player_name = format!("Player {}", i);
+            let _ = game.player_pick(&player_name).unwrap();
+        }
+
+        game.close_exchange().unwrap();
+        assert_eq!(game.status, GameStatus::Closed);
+
+        let player = Player::new("Player 19");
+        let result = game.add_player(player);
+        assert_eq!(result, Err(GameError::GameLocked));
+    }
+
+    #[test]
+    fn add_player_with_same_name_twice() {
+        let mut game = SecretSantaGame::default();
+        let player = Player::new("Player 1");
+        game.add_player(player.clone()).unwrap();
+        let second_add = game.add_player(player);
+        assert_eq!(
+            second_add,
+            Err(GameError::PlayerAlreadyExists {
+                name: "Player 1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn add_player_with_empty_name() {
+        let mut game = SecretSantaGame::default();
+        let player = Player::new("");
+        let result = game.add_player(player);
+        assert_eq!(result, Err(GameError::EmptyPlayerName));
+    }
+
+    #[test]
+    fn remove_player_in_game() {
+        let mut game = SecretSantaGame::default();
+        let player = Player::new("Player 1");
+        game.add_player(player.clone()).unwrap();
+        assert_eq!(game.players.len(), 1);
+
+        let _ = game.remove_player(&player.name);
+        assert_eq!(game.players.len(), 0);
+    }
+
+    #[test]
+    fn start_game_respects_exclusions() {
+        for _ in 0..20 {
+            let mut game = SecretSantaGame::default();
+            for i in 0..4 {
+                let player = Player::new(&format!("Player {}", i));
+                game.add_player(player).unwrap();
+            }
+            game.add_exclusion(Exclusion::symmetric("Player 0", "Player 1"))
+                .unwrap();
+            game.add_exclusion(Exclusion::new("Player 2", "Player 3"))
+                .unwrap();
+            game.start_game(None).unwrap();
+            game.open_reveals().unwrap();
+
+            for player in game.players.iter() {
+                let picked = player.picked[0].clone();
+                assert!(!game
+                    .exclusions
+                    .iter()
+                    .any(|e| e.forbids(&player.name, &picked)));
+            }
+        }
+    }
+
+    #[test]
+    fn start_game_with_impossible_exclusions() {
+        let mut game = SecretSantaGame::default();
+        for i in 0..3 {
+            let player = Player::new(&format!("Player {}", i));
+            game.add_player(player).unwrap();
+        }
+        game.add_exclusion(Exclusion::new("Player 0", "Player 1"))
+            .unwrap();
+        game.add_exclusion(Exclusion::new("Player 0", "Player 2"))
+            .unwrap();
+
+        let result = game.start_game(None);
+        assert_eq!(result, Err(GameError::NoValidAssignment));
+        assert_eq!(game.status, GameStatus::Open);
+    }
+
+    #[test]
+    fn add_exclusion_with_unknown_player() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        let result = game.add_exclusion(Exclusion::new("Player 1", "Player 2"));
+        assert_eq!(
+            result,
+            Err(GameError::PlayerNotFound {
+                name: "Player 2".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn add_exclusion_twice() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        game.add_player(Player::new("Player 2")).unwrap();
+        game.add_exclusion(Exclusion::symmetric("Player 1", "Player 2"))
+            .unwrap();
+        let result = game.add_exclusion(Exclusion::new("Player 2", "Player 1"));
+        assert_eq!(
+            result,
+            Err(GameError::ExclusionAlreadyExists {
+                giver: "Player 2".to_string(),
+                receiver: "Player 1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn remove_player_drops_its_exclusions() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        game.add_player(Player::new("Player 2")).unwrap();
+        game.add_exclusion(Exclusion::new("Player 1", "Player 2"))
+            .unwrap();
+
+        game.remove_player("Player 2").unwrap();
+        assert!(game.exclusions.is_empty());
+    }
+
+    #[test]
+    fn start_game_with_groups() {
+        for _ in 0..20 {
+            let mut game = SecretSantaGame::default();
+            for (name, group) in [
+                ("Ana", "Silva"),
+                ("Bruno", "Silva"),
+                ("Carla", "Souza"),
+                ("Diego", "Souza"),
+                ("Eva", "Lima"),
+            ] {
+                game.add_player(Player::with_group(name, group)).unwrap();
+            }
+            game.add_player(Player::new("Fabio")).unwrap();
+            game.start_game(None).unwrap();
+            game.open_reveals().unwrap();
+
+            for player in game.players.iter() {
+                let picked = player.picked[0].clone();
+                let receiver = game.players.iter().find(|p| p.name == picked).unwrap();
+                assert!(player.group.is_none() || player.group != receiver.group);
+            }
         }
     }
-}
 
-impl SecretSantaGame {
-    pub fn add_player(&mut self, player: Player) -> Result<(), String> {
-        if self.status != GameStatus::NotStarted {
-            return Err("Game already started or finished".into());
-        }
-        if let Some(p) = self.players.iter().find(|p| p.name == player.name) {
-            return Err(format!("Player {} already exists", p.name));
-        }
-        if player.name.is_empty() {
-            return Err("Player name cannot be empty".into());
-        }
-        self.players.push(player);
-        Ok(())
+    #[test]
+    fn start_game_with_oversized_group() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::with_group("Ana", "Silva")).unwrap();
+        game.add_player(Player::with_group("Bruno", "Silva"))
+            .unwrap();
+        game.add_player(Player::new("Carla")).unwrap();
+
+        let result = game.start_game(None);
+        assert_eq!(
+            result,
+            Err(GameError::OversizedGroup {
+                group: "Silva".to_string()
+            })
+        );
     }
 
-    pub fn remove_player(&mut self, player_name: &str) -> Result<(), String> {
-        if self.status != GameStatus::NotStarted {
-            return Err("Game already started or finished".into());
-        }
-        if let Some(index) = self.players.iter().position(|p| p.name == player_name) {
-            self.players.remove(index);
-            Ok(())
-        } else {
-            Err("Player not found".into())
+    fn past_draw(year: u16, pairs: &[(&str, &str)]) -> PastDraw {
+        PastDraw {
+            year,
+            pairings: pairs
+                .iter()
+                .map(|(giver, receiver)| Pairing {
+                    giver: giver.to_string(),
+                    receiver: receiver.to_string(),
+                })
+                .collect(),
         }
     }
 
-    pub fn start_game(&mut self) -> Result<(), String> {
-        if self.status == GameStatus::InProgress {
-            return Err("Game already started".into());
+    #[test]
+    fn start_game_avoids_last_years_pairings() {
+        for _ in 0..20 {
+            let mut game = SecretSantaGame::default();
+            for i in 0..4 {
+                game.add_player(Player::new(&format!("Player {}", i)))
+                    .unwrap();
+            }
+            let history = vec![
+                past_draw(2022, &[("Player 0", "Player 1"), ("Player 1", "Player 0")]),
+                past_draw(2023, &[("Player 2", "Player 3"), ("Player 3", "Player 0")]),
+            ];
+            game.set_history(history.clone(), HistoryPolicy::AvoidLastYears(2))
+                .unwrap();
+            game.start_game(None).unwrap();
+            game.open_reveals().unwrap();
+
+            for player in game.players.iter() {
+                let picked = player.picked[0].clone();
+                assert!(!history.iter().any(|d| d.contains(&player.name, &picked)));
+            }
         }
+    }
 
-        if self.status == GameStatus::Finished {
-            return Err("Game already finished".into());
+    #[test]
+    fn history_matches_names_by_key() {
+        // last year's spelling differs, the people are the same
+        let history = vec![past_draw(
+            2023,
+            &[("ana", "BRUNO"), ("  Bruno ", "Carla"), ("CARLA", "ana")],
+        )];
+        for _ in 0..20 {
+            let mut game = players_named(&["Ana", "Bruno", "Carla"]);
+            game.set_history(history.clone(), HistoryPolicy::AvoidLastYears(1))
+                .unwrap();
+            game.start_game(None).unwrap();
+            for player in game.players.iter() {
+                assert!(!history[0].contains(&player.name, &player.picked[0]));
+            }
+            let ana = game.find_player("Ana").unwrap();
+            assert_eq!(game.players[ana].picked, vec!["Carla".to_string()]);
         }
+    }
 
-        if self.players.len() < 2 {
-            return Err("Not enough players".into());
+    // Twenty players where Player 0 and Player 1 may only give to Player 2.
+    fn over_constrained_game() -> SecretSantaGame {
+        let names: Vec<String> = (0..20).map(|i| format!("Player {}", i)).collect();
+        let mut game = players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        for giver in &names[..2] {
+            for receiver in names.iter().filter(|n| *n != giver && *n != "Player 2") {
+                game.add_exclusion(Exclusion::new(giver, receiver)).unwrap();
+            }
         }
+        game
+    }
 
-        self.sort_players();
-        self.status = GameStatus::InProgress;
-        Ok(())
+    // How a draw of `game` given `budget` search steps fails, and the steps it
+    // spent, `None` when it succeeds.
+    fn failed_search(game: &SecretSantaGame, budget: usize) -> (Option<GameError>, usize) {
+        let mut left = budget;
+        let result = game.find_assignment_avoiding_history(&mut seeded_rng("steps"), &mut left);
+        (result.err(), budget - left)
     }
 
-    pub fn restart_game(&mut self) {
-        self.status = GameStatus::NotStarted;
-        self.players = vec![];
+    #[test]
+    fn search_gives_up_when_out_of_steps() {
+        let game = players_named(&["Ana", "Bob", "Carol", "Dan", "Eve"]);
+        for strategy in [
+            DrawStrategy::SingleCycle,
+            DrawStrategy::AnyDerangement,
+            DrawStrategy::NoMutualPairs,
+        ] {
+            let mut game = game.clone();
+            game.set_draw_strategy(strategy).unwrap();
+            assert_eq!(failed_search(&game, 1), (Some(GameError::SearchGaveUp), 1));
+            assert_eq!(failed_search(&game, SEARCH_BUDGET).0, None);
+        }
     }
-    fn sort_players(&mut self) {
-        self.suffle_players();
-        if !self.players.is_empty() {
-            for i in 0..self.players.len() - 1 {
-                let next_index = i + 1;
-                let next_name = self.players[next_index].name.clone();
-                self.players[i].picked = next_name.into();
+
+    #[test]
+    fn hopeless_single_cycle_fails_before_searching() {
+        let game = over_constrained_game();
+        assert_eq!(
+            failed_search(&game, SEARCH_BUDGET),
+            (Some(GameError::NoValidAssignment), 0)
+        );
+
+        // everyone has a recipient in their own half, but no circle joins them
+        let names: Vec<String> = (0..20).map(|i| format!("Player {}", i)).collect();
+        let mut game = players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        for first in &names[..10] {
+            for second in &names[10..] {
+                game.add_exclusion(Exclusion::symmetric(first, second))
+                    .unwrap();
             }
         }
-
-        let head_name = self.players[0].name.clone();
-        let last_index = self.players.len() - 1;
-        self.players[last_index].picked = head_name.into();
+        assert_eq!(
+            failed_search(&game, SEARCH_BUDGET),
+            (Some(GameError::NoValidAssignment), 0)
+        );
     }
 
-    fn suffle_players(&mut self) {
-        let mut rng = thread_rng();
-        self.players.shuffle(&mut rng);
+    #[test]
+    fn hopeless_derangement_fails_before_searching() {
+        for strategy in [DrawStrategy::AnyDerangement, DrawStrategy::NoMutualPairs] {
+            let mut game = over_constrained_game();
+            game.set_draw_strategy(strategy).unwrap();
+            assert_eq!(
+                failed_search(&game, SEARCH_BUDGET),
+                (Some(GameError::NoValidAssignment), 0)
+            );
+        }
     }
 
-    fn check_game_status(&mut self) {
-        if self.players.iter().all(|p| p.has_picked) {
-            self.status = GameStatus::Finished;
+    #[test]
+    fn hopeless_game_with_long_history_fails_before_searching() {
+        let history: Vec<PastDraw> = (2000..2030)
+            .map(|year| past_draw(year, &[("Player 3", "Player 4"), ("Player 4", "Player 5")]))
+            .collect();
+        for strategy in [
+            DrawStrategy::SingleCycle,
+            DrawStrategy::AnyDerangement,
+            DrawStrategy::NoMutualPairs,
+        ] {
+            let mut game = over_constrained_game();
+            game.set_draw_strategy(strategy).unwrap();
+            game.set_history(history.clone(), HistoryPolicy::Prefer)
+                .unwrap();
+            assert_eq!(
+                failed_search(&game, SEARCH_BUDGET),
+                (Some(GameError::NoValidAssignment), 0)
+            );
         }
     }
 
-    pub fn player_pick(&mut self, player_name: &str) -> Result<String, String> {
-        if self.status == GameStatus::NotStarted {
-            return Err("Game not started".into());
+    #[test]
+    fn failed_draw_keeps_player_order() {
+        let mut game = over_constrained_game();
+        let names: Vec<String> = game.players.iter().map(|p| p.name.clone()).collect();
+        assert_eq!(game.start_game(None), Err(GameError::NoValidAssignment));
+        assert_eq!(game.status, GameStatus::Open);
+        let after: Vec<String> = game.players.iter().map(|p| p.name.clone()).collect();
+        assert_eq!(after, names);
+    }
+
+    #[test]
+    fn single_cycle_follows_a_forced_chain() {
+        let names: Vec<String> = (0..20).map(|i| format!("Player {}", i)).collect();
+        for seed in 0..30 {
+            // Player 0 gives to Player 1, who gives to Player 2
+            let mut game = players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+            for (giver, only) in [(0, 1), (1, 2)] {
+                for (receiver, name) in names.iter().enumerate() {
+                    if receiver != giver && receiver != only {
+                        game.add_exclusion(Exclusion::new(&names[giver], name))
+                            .unwrap();
+                    }
+                }
+            }
+            game.start_game(Some(seed.to_string())).unwrap();
+            game.open_reveals().unwrap();
+            let picked = |name: &str| {
+                let player = game.players.iter().find(|p| p.name == name).unwrap();
+                player.picked().to_vec()
+            };
+            assert_eq!(picked("Player 0"), ["Player 1"]);
+            assert_eq!(picked("Player 1"), ["Player 2"]);
+            assert_eq!(cycle_count(&drawn_receivers(&game)), 1);
         }
+    }
 
-        if self.status == GameStatus::Finished {
-            return Err("Game finished".into());
+    #[test]
+    fn derangement_with_a_forced_giver() {
+        let names: Vec<String> = (0..20).map(|i| format!("Player {}", i)).collect();
+        for (seed, strategy) in (0..10).flat_map(|seed| {
+            [DrawStrategy::AnyDerangement, DrawStrategy::NoMutualPairs].map(|s| (seed, s))
+        }) {
+            let mut game = players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+            for receiver in &names[2..] {
+                game.add_exclusion(Exclusion::new("Player 0", receiver))
+                    .unwrap();
+            }
+            game.set_draw_strategy(strategy).unwrap();
+            game.start_game(Some(seed.to_string())).unwrap();
+            game.open_reveals().unwrap();
+            let giver = game.players.iter().find(|p| p.name == "Player 0").unwrap();
+            assert_eq!(giver.picked(), ["Player 1"]);
         }
+    }
 
-        if let Some(player) = self.players.iter_mut().find(|p| p.name == player_name) {
-            if player.has_picked {
-                return Err("Player has already picked".into());
+    #[test]
+    fn several_gifts_need_enough_allowed_pairs() {
+        let names: Vec<String> = (0..20).map(|i| format!("Player {}", i)).collect();
+        let everyone_else: Vec<Vec<bool>> = (0..4)
+            .map(|giver| (0..4).map(|receiver| giver != receiver).collect())
+            .collect();
+        assert!(assignment_possible(&everyone_else, 3));
+        assert!(!assignment_possible(&everyone_else, 4));
+
+        for strategy in [
+            DrawStrategy::SingleCycle,
+            DrawStrategy::AnyDerangement,
+            DrawStrategy::NoMutualPairs,
+        ] {
+            // Player 0 is only paired with Player 1, so two gifts each are impossible
+            let mut game = players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+            for receiver in &names[2..] {
+                game.add_exclusion(Exclusion::new("Player 0", receiver))
+                    .unwrap();
             }
-            player.has_picked = true;
-            let picked_name = player.picked.clone();
-            self.check_game_status();
-            return Ok(picked_name.unwrap());
-        } else {
-            return Err("Player not found".into());
+            game.set_draw_strategy(strategy).unwrap();
+            game.set_gifts_per_person(2).unwrap();
+            assert_eq!(
+                failed_search(&game, SEARCH_BUDGET),
+                (Some(GameError::NoValidAssignment), 0)
+            );
+            assert_eq!(game.start_game(None), Err(GameError::NoValidAssignment));
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn start_game_with_unavoidable_history() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        game.add_player(Player::new("Player 2")).unwrap();
+        let history = vec![past_draw(
+            2023,
+            &[("Player 1", "Player 2"), ("Player 2", "Player 1")],
+        )];
+
+        game.set_history(history.clone(), HistoryPolicy::AvoidLastYears(1))
+            .unwrap();
+        assert_eq!(game.start_game(None), Err(GameError::NoValidAssignment));
+
+        game.set_history(history, HistoryPolicy::Prefer).unwrap();
+        assert_eq!(game.start_game(None), Ok(()));
+    }
 
     #[test]
-    fn add_player_in_game() {
-        let player = Player::new("Player 1");
-        let mut game = SecretSantaGame {
-            status: GameStatus::NotStarted,
-            players: vec![],
-        };
-        assert_eq!(game.players.len(), 0);
+    fn export_finished_draw() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        game.add_player(Player::new("Player 2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert_eq!(game.export_draw(2024), Err(GameError::NotFinished));
 
-        let _ = game.add_player(player);
-        assert_eq!(game.players.len(), 1);
-        assert_eq!(game.players[0].name, "Player 1");
-        assert_eq!(game.players, vec![Player::new("Player 1")],);
+        game.player_pick("Player 1").unwrap();
+        game.player_pick("Player 2").unwrap();
+        game.close_exchange().unwrap();
+        let draw = game.export_draw(2024).unwrap();
+        assert_eq!(draw.year, 2024);
+        assert!(draw.contains("Player 1", "Player 2"));
+        assert!(draw.contains("Player 2", "Player 1"));
     }
 
     #[test]
-    fn start_game() {
+    fn start_game_assigns_unique_tokens() {
         let mut game = SecretSantaGame::default();
         for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+            game.add_player(Player::new(&format!("Player {}", i)))
+                .unwrap();
         }
-        assert_eq!(game.status, GameStatus::NotStarted);
-        let head = game.players[0].clone();
-        assert_eq!(head.picked, None);
+        // joining hands out a link for the lobby, the draw replaces it
+        let joined = game.players[0].token().unwrap().to_string();
+        assert!(game.players.iter().all(|p| p.token().is_some()));
 
-        let _ = game.start_game();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert_eq!(
+            game.player_by_token(&joined).unwrap_err(),
+            GameError::InvalidToken
+        );
+        let mut tokens: Vec<&str> = game.players.iter().filter_map(|p| p.token()).collect();
+        tokens.sort();
+        tokens.dedup();
+        assert_eq!(tokens.len(), 10);
+    }
 
-        assert_eq!(game.status, GameStatus::InProgress);
-        let head_picked_name = game.players[0].clone().picked.unwrap();
-        assert!(head_picked_name.contains("Player"));
+    #[test]
+    fn pick_player_by_token() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        game.add_player(Player::new("Player 2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+
+        let token = game.players[0].token().unwrap().to_string();
+        let player_name = game.players[0].name.clone();
+        let picked_name = game.player_pick_by_token(&token).unwrap();
+        assert_ne!(picked_name, vec![player_name]);
+
+        let result = game.player_pick_by_token("Player 2");
+        assert_eq!(result, Err(GameError::InvalidToken));
     }
 
     #[test]
-    fn restart_game() {
+    fn serialize_game_in_progress() {
         let mut game = SecretSantaGame::default();
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
-        }
-        assert_eq!(game.status, GameStatus::NotStarted);
-        let head = game.players[0].clone();
-        assert_eq!(head.picked, None);
+        game.add_player(Player::with_group("Player 1", "Family"))
+            .unwrap();
+        game.add_player(Player::new("Player 2")).unwrap();
+        game.add_player(Player::new("Player 3")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let token = game.players[0].token().unwrap().to_string();
+        let expected_pick = game.players[0].picked.clone();
 
-        let _ = game.start_game();
+        let snapshot = serde_json::to_string(&game).unwrap();
+        let mut restored: SecretSantaGame = serde_json::from_str(&snapshot).unwrap();
+        assert_eq!(restored.status, GameStatus::Revealing);
+        assert_eq!(restored.players, game.players);
+        assert_eq!(restored.player_pick_by_token(&token), Ok(expected_pick));
+    }
 
-        assert_eq!(game.status, GameStatus::InProgress);
+    #[test]
+    fn add_players_is_all_or_nothing() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player 1")).unwrap();
+        let batch = vec![
+            Player::new("Player 2"),
+            Player::new("Player 1"),
+            Player::new(" "),
+            Player::new("Player 2"),
+        ];
+        assert_eq!(
+            game.check_players(&batch),
+            vec![
+                Ok(()),
+                Err(GameError::PlayerAlreadyExists {
+                    name: "Player 1".to_string()
+                }),
+                Err(GameError::EmptyPlayerName),
+                Err(GameError::DuplicatePlayer {
+                    name: "Player 2".to_string()
+                }),
+            ]
+        );
+        assert!(game.add_players(batch).is_err());
+        assert_eq!(game.players.len(), 1);
 
-        game.restart_game();
-        assert_eq!(game.status, GameStatus::NotStarted);
-        assert_eq!(game.players.len(), 0);
+        game.add_players(vec![Player::new("Player 2"), Player::new("Player 3")])
+            .unwrap();
+        assert_eq!(game.players.len(), 3);
     }
 
     #[test]
-    fn pick_player() {
+    fn names_are_normalized_and_case_insensitive() {
         let mut game = SecretSantaGame::default();
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+        // "A" followed by a combining acute accent
+        game.add_player(Player::new("  A\u{301}na   Maria "))
+            .unwrap();
+        assert_eq!(game.players[0].name, "\u{c1}na Maria");
+        assert_eq!(game.players[0].display_name(), "A\u{301}na   Maria");
+        assert_eq!(
+            game.add_player(Player::new("ána maria")),
+            Err(GameError::PlayerAlreadyExists {
+                name: "ána maria".to_string()
+            })
+        );
+        assert_eq!(
+            game.check_players(&[Player::new("Bob"), Player::new("BOB ")]),
+            vec![
+                Ok(()),
+                Err(GameError::DuplicatePlayer {
+                    name: "BOB".to_string()
+                })
+            ]
+        );
+        game.add_player(Player::new("Bob")).unwrap();
+        game.add_exclusion(Exclusion::new("bob", "ÁNA MARIA"))
+            .unwrap();
+        assert_eq!(
+            game.exclusions,
+            vec![Exclusion::new("Bob", "\u{c1}na Maria")]
+        );
+        game.remove_player("BOB").unwrap();
+        assert!(game.exclusions.is_empty());
+    }
+
+    #[test]
+    fn names_respect_length_limits() {
+        let mut game = SecretSantaGame::default();
+        assert_eq!(
+            game.set_name_rules(NameRules {
+                min_length: 5,
+                max_length: 3
+            }),
+            Err(GameError::InvalidNameRules)
+        );
+        game.set_name_rules(NameRules {
+            min_length: 3,
+            max_length: 6,
+        })
+        .unwrap();
+        assert_eq!(
+            game.add_player(Player::new(" Al ")),
+            Err(GameError::NameTooShort { min: 3 })
+        );
+        assert_eq!(
+            game.add_player(Player::new("Maximilian")),
+            Err(GameError::NameTooLong { max: 6 })
+        );
+        // accents count as one character
+        game.add_player(Player::new("Zoë")).unwrap();
+    }
+
+    fn players_named(names: &[&str]) -> SecretSantaGame {
+        let mut game = SecretSantaGame::default();
+        for name in names {
+            game.add_player(Player::new(name)).unwrap();
         }
-        let _ = game.start_game();
+        game
+    }
 
-        // start picking
+    #[test]
+    fn seeded_draw_is_reproducible() {
+        let organizer_seed = "7c2e9a41f0d3b865";
+        let mut first = players_named(&["Ana", "Bob", "Carol", "Dan", "Eve"]);
+        let mut other = players_named(&["Ana", "Bob", "Carol", "Dan", "Eve"]);
+        first.start_game(Some(organizer_seed.to_string())).unwrap();
+        first.open_reveals().unwrap();
+        other.start_game(Some(organizer_seed.to_string())).unwrap();
+        // the organizer's seed cannot be used alone to predict the draw
+        let seed = first.audit.as_ref().unwrap().seed.clone();
+        assert!(seed.starts_with(&format!("{}:", organizer_seed)));
+        assert_ne!(other.audit.as_ref().unwrap().seed, seed);
 
-        for i in 0..game.players.len() {
-            let player_name = format!("Player {}", i);
-            let picked_name = game.player_pick(&player_name).unwrap();
-            assert!(picked_name.contains("Player"));
+        // the whole seed draws the same, whatever order players were added in
+        let mut second = players_named(&["Eve", "Dan", "Carol", "Bob", "Ana"]);
+        second.sort_players(&seed).unwrap();
+        let mut first_pairings = first.pairings();
+        let mut second_pairings = second.pairings();
+        first_pairings.sort_by(|a, b| a.giver.cmp(&b.giver));
+        second_pairings.sort_by(|a, b| a.giver.cmp(&b.giver));
+        assert_eq!(first_pairings, second_pairings);
+
+        let commitment = &first.audit.as_ref().unwrap().commitment;
+        assert_eq!(commitment, &draw_commitment(&seed, &first_pairings));
+        assert_eq!(first.audit.as_ref().unwrap().revealed_seed(), None);
+        // players keep their own secret tokens
+        assert_ne!(first.players[0].token, other.players[0].token);
+    }
+
+    #[test]
+    fn seed_is_revealed_after_the_game_and_verifies() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert_eq!(game.verify_draw(), Err(GameError::SeedNotRevealed));
+        assert_eq!(game.reveal_seed(), Err(GameError::NotFinished));
+
+        for name in ["Ana", "Bob", "Carol"] {
+            game.player_pick(name).unwrap();
+        }
+        game.close_exchange().unwrap();
+        let seed = game.reveal_seed().unwrap();
+        assert_eq!(
+            game.audit.as_ref().unwrap().revealed_seed(),
+            Some(seed.as_str())
+        );
+        assert_eq!(game.verify_draw(), Ok(true));
+
+        // swapping two recipients breaks the commitment
+        let picked = game.players[0].picked.clone();
+        game.players[0].picked = game.players[1].picked.clone();
+        game.players[1].picked = picked;
+        assert_eq!(game.verify_draw(), Ok(false));
+    }
+
+    // receiver index for every giver index
+    fn drawn_receivers(game: &SecretSantaGame) -> Vec<usize> {
+        game.players
+            .iter()
+            .map(|giver| {
+                let picked = &giver.picked()[0];
+                game.players.iter().position(|p| &p.name == picked).unwrap()
+            })
+            .collect()
+    }
+
+    fn cycle_count(receivers: &[usize]) -> usize {
+        let mut seen = vec![false; receivers.len()];
+        let mut cycles = 0;
+        for start in 0..receivers.len() {
+            if seen[start] {
+                continue;
+            }
+            cycles += 1;
+            let mut current = start;
+            while !seen[current] {
+                seen[current] = true;
+                current = receivers[current];
+            }
         }
+        cycles
+    }
 
-        assert_eq!(game.status, GameStatus::Finished);
+    fn draw_with(strategy: DrawStrategy, size: usize, seed: usize) -> Vec<usize> {
+        let names: Vec<String> = (0..size).map(|i| format!("Player {}", i)).collect();
+        let mut game = players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        game.set_draw_strategy(strategy).unwrap();
+        game.start_game(Some(seed.to_string())).unwrap();
+        game.open_reveals().unwrap();
+        let receivers = drawn_receivers(&game);
+        for (giver, &receiver) in receivers.iter().enumerate() {
+            assert_ne!(giver, receiver, "{:?} drew themselves", strategy);
+        }
+        let mut received = receivers.clone();
+        received.sort();
+        assert_eq!(received, (0..size).collect::<Vec<_>>(), "{:?}", strategy);
+        receivers
     }
 
     #[test]
-    fn start_game_with_less_than_two_players() {
-        let mut game = SecretSantaGame::default();
-        let player = Player::new("Player 1");
-        let _ = game.add_player(player);
+    fn single_cycle_strategy_chains_everyone() {
+        for size in 2..8 {
+            for seed in 0..20 {
+                let receivers = draw_with(DrawStrategy::SingleCycle, size, seed);
+                assert_eq!(cycle_count(&receivers), 1);
+            }
+        }
+    }
 
-        let result = game.start_game();
-        assert_eq!(result, Err("Not enough players".into()));
+    #[test]
+    fn any_derangement_strategy_allows_small_cycles() {
+        let mut small_cycles = false;
+        for size in 2..8 {
+            for seed in 0..20 {
+                let receivers = draw_with(DrawStrategy::AnyDerangement, size, seed);
+                small_cycles |= cycle_count(&receivers) > 1;
+            }
+        }
+        assert!(small_cycles);
     }
 
     #[test]
-    fn start_game_twice() {
-        let mut game = SecretSantaGame::default();
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+    fn no_mutual_pairs_strategy() {
+        for size in 3..8 {
+            for seed in 0..20 {
+                let receivers = draw_with(DrawStrategy::NoMutualPairs, size, seed);
+                for (giver, &receiver) in receivers.iter().enumerate() {
+                    assert_ne!(receivers[receiver], giver);
+                }
+            }
         }
-        let _ = game.start_game();
 
-        let result = game.start_game();
-        assert_eq!(result, Err("Game already started".into()));
+        let mut game = players_named(&["Ana", "Bob"]);
+        game.set_draw_strategy(DrawStrategy::NoMutualPairs).unwrap();
+        assert_eq!(game.start_game(None), Err(GameError::NoValidAssignment));
     }
 
     #[test]
-    fn start_game_that_already_finished() {
-        let mut game = SecretSantaGame::default();
+    fn several_gifts_per_person() {
+        let strategies = [
+            DrawStrategy::SingleCycle,
+            DrawStrategy::AnyDerangement,
+            DrawStrategy::NoMutualPairs,
+        ];
+        for strategy in strategies {
+            for (size, gifts) in [(3, 2), (5, 2), (7, 3)] {
+                for seed in 0..10 {
+                    let names: Vec<String> = (0..size).map(|i| format!("Player {}", i)).collect();
+                    let mut game =
+                        players_named(&names.iter().map(String::as_str).collect::<Vec<_>>());
+                    game.set_draw_strategy(strategy).unwrap();
+                    game.set_gifts_per_person(gifts).unwrap();
+                    if strategy == DrawStrategy::NoMutualPairs && size < 2 * gifts + 1 {
+                        // everyone would have to give to one of their own Santas
+                        assert_eq!(
+                            game.start_game(Some(seed.to_string())),
+                            Err(GameError::NoValidAssignment)
+                        );
+                        continue;
+                    }
+                    game.start_game(Some(seed.to_string())).unwrap();
+                    game.open_reveals().unwrap();
 
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+                    let mut received: HashMap<&str, usize> = HashMap::new();
+                    for player in game.players.iter() {
+                        let mut picked = player.picked().to_vec();
+                        assert_eq!(picked.len(), gifts);
+                        assert!(!picked.contains(&player.name));
+                        picked.sort();
+                        picked.dedup();
+                        assert_eq!(picked.len(), gifts, "{:?} gave twice", strategy);
+                        for receiver in player.picked() {
+                            *received.entry(receiver).or_default() += 1;
+                        }
+                    }
+                    assert_eq!(received.len(), size);
+                    assert!(received.values().all(|&count| count == gifts));
+                }
+            }
         }
-        let _ = game.start_game();
+    }
 
-        // start picking
+    #[test]
+    fn gifts_per_person_needs_enough_players() {
+        let mut game = players_named(&["Ana", "Bob"]);
+        assert_eq!(
+            game.set_gifts_per_person(0),
+            Err(GameError::InvalidGiftsPerPerson)
+        );
+        game.set_gifts_per_person(2).unwrap();
+        assert_eq!(game.start_game(None), Err(GameError::NotEnoughPlayers));
+        game.add_player(Player::new("Carol")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert_eq!(game.player_pick("Ana").unwrap().len(), 2);
+    }
 
-        for i in 0..game.players.len() {
-            let player_name = format!("Player {}", i);
-            let _ = game.player_pick(&player_name).unwrap();
+    #[test]
+    fn read_single_recipient_snapshots() {
+        let player: Player = serde_json::from_str(
+            r#"{"name": "Ana", "group": null, "picked": "Bob", "token": null, "has_picked": false}"#,
+        )
+        .unwrap();
+        assert_eq!(player.picked(), ["Bob".to_string()]);
+        let player: Player = serde_json::from_str(
+            r#"{"name": "Ana", "group": null, "picked": null, "token": null, "has_picked": false}"#,
+        )
+        .unwrap();
+        assert!(player.picked().is_empty());
+    }
+
+    #[test]
+    fn phases_follow_the_lifecycle() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.close_registration().unwrap();
+        assert_eq!(
+            game.add_player(Player::new("Dan")),
+            Err(GameError::RegistrationClosed)
+        );
+        assert_eq!(game.clear_players(), Err(GameError::RegistrationClosed));
+        game.set_draw_strategy(DrawStrategy::AnyDerangement)
+            .unwrap();
+        game.reopen_registration().unwrap();
+        game.add_player(Player::new("Dan")).unwrap();
+
+        game.start_game(None).unwrap();
+        assert_eq!(game.status, GameStatus::Drawn);
+        assert_eq!(game.player_pick("Ana"), Err(GameError::RevealsNotOpen));
+        assert_eq!(
+            game.close_exchange(),
+            Err(GameError::InvalidTransition {
+                from: GameStatus::Drawn,
+                to: GameStatus::Closed
+            })
+        );
+        game.open_reveals().unwrap();
+        for name in ["Ana", "Bob", "Carol", "Dan"] {
+            game.player_pick(name).unwrap();
         }
+        // everyone revealed, but the exchange itself is still to come
+        assert_eq!(game.status, GameStatus::Revealing);
+        game.close_exchange().unwrap();
+        game.archive().unwrap();
+        assert_eq!(
+            game.restart_game(),
+            Err(GameError::InvalidTransition {
+                from: GameStatus::Archived,
+                to: GameStatus::Open
+            })
+        );
+        assert_eq!(game.players.len(), 4);
+    }
 
-        assert_eq!(game.status, GameStatus::Finished);
+    #[test]
+    fn schedule_moves_the_game_along() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        assert_eq!(
+            game.set_schedule(Schedule {
+                reveals_at: Some(2_000),
+                exchange_at: Some(1_000),
+            }),
+            Err(GameError::InvalidSchedule)
+        );
+        let now = unix_now();
+        game.set_schedule(Schedule {
+            reveals_at: Some(now + 3_600),
+            exchange_at: Some(now + 7_200),
+        })
+        .unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert!(matches!(
+            game.player_pick("Ana"),
+            Err(GameError::RevealsScheduled { .. })
+        ));
 
-        let result = game.start_game();
-        assert_eq!(result, Err("Game already finished".into()));
+        game.status = GameStatus::Drawn;
+        assert!(!game.advance_schedule(now));
+        assert!(game.advance_schedule(now + 3_600));
+        assert_eq!(game.status, GameStatus::Revealing);
+        assert!(game.advance_schedule(now + 7_200));
+        assert_eq!(game.status, GameStatus::Closed);
+        assert_eq!(
+            game.set_schedule(Schedule::default()),
+            Err(GameError::AlreadyFinished)
+        );
     }
 
     #[test]
-    fn pick_player_that_not_started() {
+    fn reveals_open_once_their_date_has_passed() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.set_schedule(Schedule {
+            reveals_at: Some(unix_now() - 60),
+            exchange_at: None,
+        })
+        .unwrap();
+        game.start_game(None).unwrap();
+        // the background task may not have opened reveals yet
+        assert_eq!(game.player_pick("Ana").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn format_timestamps_in_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_timestamp(951_827_696), "2000-02-29 12:34 UTC");
+        assert_eq!(format_timestamp(1_796_083_200), "2026-12-01 00:00 UTC");
+    }
+
+    #[test]
+    fn wishlists_follow_the_personal_link() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let token = game.players[game.find_player("Ana").unwrap()]
+            .token()
+            .unwrap()
+            .to_string();
+        let santa = giver_of(&game, "Ana");
+        let santa_token = game.players[game.find_player(santa).unwrap()]
+            .token()
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            game.set_wishlist(&token, vec![WishlistItem::new("  ")]),
+            Err(GameError::EmptyWishlistTitle)
+        );
+        assert_eq!(
+            game.set_wishlist(
+                &token,
+                vec![WishlistItem::new("Book"); MAX_WISHLIST_ITEMS + 1]
+            ),
+            Err(GameError::TooManyWishlistItems {
+                max: MAX_WISHLIST_ITEMS
+            })
+        );
+        assert_eq!(
+            game.set_wishlist("unknown", vec![]),
+            Err(GameError::InvalidToken)
+        );
+        game.set_wishlist(
+            &token,
+            vec![WishlistItem {
+                link: Some(" ".to_string()),
+                price_hint: Some("20 EUR".to_string()),
+                ..WishlistItem::new(" Book ")
+            }],
+        )
+        .unwrap();
+        assert_eq!(
+            game.wishlist(&token).unwrap(),
+            [WishlistItem {
+                price_hint: Some("20 EUR".to_string()),
+                ..WishlistItem::new("Book")
+            }]
+        );
+
+        assert_eq!(
+            game.revealed_recipients(&santa_token),
+            Err(GameError::NotRevealed)
+        );
+        let picked = game.player_pick_by_token(&santa_token).unwrap();
+        assert_eq!(picked, vec!["Ana".to_string()]);
+        assert_eq!(game.revealed_recipients(&santa_token).unwrap(), picked);
+        assert_eq!(game.wishlist_of("Ana")[0].title, "Book");
+    }
+
+    #[test]
+    fn event_details_are_cleaned_up() {
         let mut game = SecretSantaGame::default();
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+        assert_eq!(
+            game.set_event(EventDetails {
+                budget_min: Some(50),
+                budget_max: Some(20),
+                ..Default::default()
+            }),
+            Err(GameError::InvalidBudget)
+        );
+        assert_eq!(
+            game.set_event(EventDetails {
+                currency: Some("euro".to_string()),
+                ..Default::default()
+            }),
+            Err(GameError::InvalidCurrency)
+        );
+        assert_eq!(
+            game.set_event(EventDetails {
+                theme: Some("x".repeat(101)),
+                ..Default::default()
+            }),
+            Err(GameError::EventFieldTooLong {
+                field: "theme".to_string(),
+                max: 100
+            })
+        );
+
+        game.set_event(EventDetails {
+            title: Some(" Office party ".to_string()),
+            budget_min: Some(20),
+            budget_max: Some(50),
+            currency: Some("eur".to_string()),
+            location: Some("  ".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            game.event,
+            EventDetails {
+                title: Some("Office party".to_string()),
+                budget_min: Some(20),
+                budget_max: Some(50),
+                currency: Some("EUR".to_string()),
+                ..Default::default()
+            }
+        );
+        // the budget survives a restart
+        game.restart_game().unwrap();
+        assert_eq!(game.event.budget_max, Some(50));
+    }
+
+    #[test]
+    fn santa_and_recipient_talk_anonymously() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.start_game(None).unwrap();
+        let token_of = |game: &SecretSantaGame, name: &str| {
+            game.players[game.find_player(name).unwrap()]
+                .token()
+                .unwrap()
+                .to_string()
+        };
+        let santa = giver_of(&game, "Ana").to_string();
+        let (santa_token, ana_token) = (token_of(&game, &santa), token_of(&game, "Ana"));
+        assert_eq!(
+            game.message_recipient(&santa_token, "Ana", "Hi", 1),
+            Err(GameError::RevealsNotOpen)
+        );
+        game.open_reveals().unwrap();
+        assert_eq!(
+            game.message_recipient(&santa_token, "Ana", "Hi", 1),
+            Err(GameError::NotRevealed)
+        );
+        game.player_pick(&santa).unwrap();
+        assert_eq!(
+            game.message_recipient(&santa_token, "Ana", "  ", 1),
+            Err(GameError::EmptyMessage)
+        );
+        assert_eq!(
+            game.message_recipient(&ana_token, "Ana", "Hi", 1),
+            Err(GameError::NotRevealed)
+        );
+        game.message_recipient(&santa_token, "Ana", "What size are you?", 1)
+            .unwrap();
+
+        let threads = game.threads_as_recipient(&ana_token).unwrap();
+        assert_eq!(threads.len(), 1);
+        assert!(!threads[0].with.contains(&santa));
+        assert!(!threads[0].messages[0].mine);
+        game.message_santa(&ana_token, &threads[0].with, "M, thanks!", 2)
+            .unwrap();
+        assert_eq!(
+            game.message_santa(&ana_token, "someone", "Hi", 2),
+            Err(GameError::ThreadNotFound)
+        );
+
+        let threads = game.threads_as_santa(&santa_token).unwrap();
+        assert_eq!(threads[0].with, "Ana");
+        assert_eq!(
+            threads[0].messages,
+            vec![
+                ThreadMessage {
+                    mine: true,
+                    text: "What size are you?".to_string(),
+                    sent_at: 1
+                },
+                ThreadMessage {
+                    mine: false,
+                    text: "M, thanks!".to_string(),
+                    sent_at: 2
+                }
+            ]
+        );
+        // a recipient who has not revealed does not see their own recipient yet
+        assert!(game.threads_as_santa(&ana_token).unwrap().is_empty());
+
+        game.restart_game().unwrap();
+        assert!(game.messages.is_empty());
+    }
+
+    #[test]
+    fn webhooks_are_checked() {
+        let mut game = SecretSantaGame::default();
+        for url in ["", "ftp://example.com", "https://", "http://exa mple.com"] {
+            assert_eq!(
+                game.add_webhook(url, vec![]),
+                Err(GameError::InvalidWebhookUrl {
+                    url: url.trim().to_string()
+                })
+            );
         }
+        assert_eq!(
+            game.add_webhook(
+                "https://example.com/hook",
+                vec!["assignment_made".to_string()]
+            ),
+            Err(GameError::UnknownWebhookEvent {
+                event: "assignment_made".to_string()
+            })
+        );
 
-        let player_name = "Player 1";
-        let result = game.player_pick(&player_name);
-        assert_eq!(result, Err("Game not started".into()));
+        let events = vec!["player_revealed".to_string(), "game_started".to_string()];
+        let webhook = game
+            .add_webhook(" https://example.com/hook ", events)
+            .unwrap();
+        assert_eq!(webhook.url, "https://example.com/hook");
+        assert!(webhook.wants("game_started"));
+        assert!(!webhook.wants("player_added"));
+        assert_eq!(game.webhooks, vec![webhook.clone()]);
+
+        game.restart_game().unwrap();
+        assert_eq!(game.webhooks.len(), 1);
+        assert_eq!(
+            game.remove_webhook("unknown"),
+            Err(GameError::WebhookNotFound)
+        );
+        game.remove_webhook(&webhook.id).unwrap();
+        assert!(game.webhooks.is_empty());
+
+        for _ in 0..MAX_WEBHOOKS {
+            game.add_webhook("http://localhost:9000", vec![]).unwrap();
+        }
+        assert_eq!(
+            game.add_webhook("http://localhost:9000", vec![]),
+            Err(GameError::TooManyWebhooks { max: MAX_WEBHOOKS })
+        );
     }
 
     #[test]
-    fn pick_player_that_already_finished() {
+    fn player_emails_are_checked() {
         let mut game = SecretSantaGame::default();
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+        for email in [
+            "ana",
+            "ana@",
+            "@example.com",
+            "ana@example",
+            "ana @example.com",
+        ] {
+            assert_eq!(
+                game.add_player(Player::new("Ana").with_email(email)),
+                Err(GameError::InvalidEmail {
+                    email: email.to_string()
+                })
+            );
         }
-        let _ = game.start_game();
+        game.add_player(Player::new("Ana").with_email(" ana@example.com "))
+            .unwrap();
+        assert_eq!(game.players[0].email.as_deref(), Some("ana@example.com"));
+    }
 
-        // start picking
+    #[test]
+    fn read_old_status_names() {
+        for (old, status) in [
+            ("NotStarted", GameStatus::Open),
+            ("InProgress", GameStatus::Revealing),
+            ("Finished", GameStatus::Closed),
+        ] {
+            let read: GameStatus = serde_json::from_str(&format!("\"{}\"", old)).unwrap();
+            assert_eq!(read, status);
+        }
+    }
 
-        for i in 0..game.players.len() {
-            let player_name = format!("Player {}", i);
-            let _ = game.player_pick(&player_name).unwrap();
+    #[test]
+    fn strategies_respect_exclusions() {
+        for strategy in [DrawStrategy::AnyDerangement, DrawStrategy::NoMutualPairs] {
+            for seed in 0..20 {
+                let mut game = players_named(&["Ana", "Bob", "Carol", "Dan"]);
+                game.add_exclusion(Exclusion::symmetric("Ana", "Bob"))
+                    .unwrap();
+                game.add_exclusion(Exclusion::new("Carol", "Dan")).unwrap();
+                game.set_draw_strategy(strategy).unwrap();
+                game.start_game(Some(seed.to_string())).unwrap();
+                game.open_reveals().unwrap();
+                for player in game.players.iter() {
+                    let picked = &player.picked()[0];
+                    assert!(!game
+                        .exclusions
+                        .iter()
+                        .any(|e| e.forbids(&player.name, picked)));
+                }
+            }
         }
+    }
 
-        assert_eq!(game.status, GameStatus::Finished);
+    #[test]
+    fn late_player_keeps_revealed_assignments() {
+        for seed in 0..20 {
+            let mut game = players_named(&["Ana", "Bob", "Carol", "Dan"]);
+            game.start_game(Some(seed.to_string())).unwrap();
+            game.open_reveals().unwrap();
+            let ana = game.player_pick("Ana").unwrap();
+            let bob = game.player_pick("Bob").unwrap();
 
-        let player_name = "Player 1";
-        let result = game.player_pick(&player_name);
-        assert_eq!(result, Err("Game finished".into()));
+            let changed = game.add_late_player(Player::new("Eve")).unwrap();
+            assert_eq!(changed.len(), 1);
+            assert!(changed[0] == "Carol" || changed[0] == "Dan");
+            assert_eq!(game.players.iter().filter(|p| p.has_picked).count(), 2);
+            let find = |name: &str| game.players.iter().find(|p| p.name == name).unwrap();
+            assert_eq!(find("Ana").picked(), ana);
+            assert_eq!(find("Bob").picked(), bob);
+            assert!(find("Eve").token().is_some());
+
+            let mut received: Vec<String> = game
+                .players
+                .iter()
+                .flat_map(|p| p.picked().to_vec())
+                .collect();
+            received.sort();
+            assert_eq!(received, vec!["Ana", "Bob", "Carol", "Dan", "Eve"]);
+            assert!(game.players.iter().all(|p| !p.picked().contains(&p.name)));
+
+            // the original draw still checks out against the commitment
+            for name in ["Carol", "Dan", "Eve"] {
+                game.player_pick(name).unwrap();
+            }
+            game.close_exchange().unwrap();
+            game.reveal_seed().unwrap();
+            assert_eq!(game.verify_draw(), Ok(true));
+            assert!(game.audit.as_ref().unwrap().amended);
+        }
     }
 
     #[test]
-    fn add_player_in_a_finished_game() {
+    fn late_player_explains_why_it_cannot_join() {
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.set_gifts_per_person(2).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        game.player_pick("Ana").unwrap();
+        game.player_pick("Bob").unwrap();
+        assert!(matches!(
+            game.add_late_player(Player::new("Dan")),
+            Err(GameError::LateJoinImpossible { .. })
+        ));
+
         let mut game = SecretSantaGame::default();
-        for i in 0..10 {
-            let player = Player::new(&format!("Player {}", i));
-            let _ = game.add_player(player);
+        game.add_player(Player::with_group("Ana", "Home")).unwrap();
+        game.add_player(Player::with_group("Bob", "Home")).unwrap();
+        game.add_player(Player::new("Carol")).unwrap();
+        game.add_player(Player::new("Dan")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        game.player_pick("Carol").unwrap();
+        game.player_pick("Dan").unwrap();
+        let result = game.add_late_player(Player::with_group("Eve", "Home"));
+        match result {
+            Err(GameError::LateJoinImpossible { name, reason }) => {
+                assert_eq!(name, "Eve");
+                assert!(reason.contains("groups"));
+            }
+            other => panic!("unexpected {:?}", other),
         }
-        let _ = game.start_game();
+        assert_eq!(game.players.len(), 4);
 
-        // start picking
+        assert_eq!(
+            game.add_late_player(Player::new("ana")),
+            Err(GameError::PlayerAlreadyExists {
+                name: "ana".to_string()
+            })
+        );
+    }
 
-        for i in 0..game.players.len() {
-            let player_name = format!("Player {}", i);
-            let _ = game.player_pick(&player_name).unwrap();
+    fn giver_of<'a>(game: &'a SecretSantaGame, name: &str) -> &'a str {
+        let giver = game
+            .players
+            .iter()
+            .find(|p| p.picked().iter().any(|r| r == name));
+        &giver.unwrap().name
+    }
+
+    #[test]
+    fn withdrawn_player_is_bypassed() {
+        for seed in 0..20 {
+            let mut game = players_named(&["Ana", "Bob", "Carol", "Dan", "Eve"]);
+            game.start_game(Some(seed.to_string())).unwrap();
+            game.open_reveals().unwrap();
+            let giver = giver_of(&game, "Carol").to_string();
+            let receiver = game.players[game.find_player("Carol").unwrap()].picked()[0].clone();
+            let revealed: Vec<(String, Vec<String>)> = ["Ana", "Bob"]
+                .iter()
+                .map(|&name| (name.to_string(), game.player_pick(name).unwrap()))
+                .collect();
+
+            let repair = game.withdraw_player("carol", None).unwrap();
+            assert_eq!(repair.reassigned, vec![giver.clone()]);
+            let giver_revealed = revealed.iter().any(|(name, _)| *name == giver);
+            assert_eq!(repair.reveal_again.contains(&giver), giver_revealed);
+            assert_eq!(cycle_count(&drawn_receivers(&game)), 1);
+            assert!(game.find_player("Carol").is_none());
+            assert!(game
+                .players
+                .iter()
+                .all(|p| !p.picked().contains(&"Carol".to_string())));
+            let find = |name: &str| game.players.iter().find(|p| p.name == name).unwrap();
+            assert_eq!(find(&giver).picked(), vec![receiver]);
+            assert!(!find(&giver).has_picked);
+            for (name, picked) in revealed.iter().filter(|(name, _)| *name != giver) {
+                assert_eq!(find(name).picked(), picked);
+                assert!(find(name).has_picked);
+            }
+            assert!(game.audit.as_ref().unwrap().amended);
         }
+    }
 
-        assert_eq!(game.status, GameStatus::Finished);
+    #[test]
+    fn replacement_takes_over_the_withdrawn_player() {
+        let mut game = players_named(&["Ana", "Bob", "Carol", "Dan"]);
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let giver = giver_of(&game, "Carol").to_string();
+        let carol = game.players[game.find_player("Carol").unwrap()]
+            .picked()
+            .to_vec();
+        game.player_pick(&giver).unwrap();
 
-        let player = Player::new("Player 19");
-        let result = game.add_player(player);
-        assert_eq!(result, Err("Game already started or finished".into()));
+        let repair = game
+            .withdraw_player("Carol", Some(Player::new("Zoe")))
+            .unwrap();
+        assert_eq!(repair.reassigned, vec![giver.clone()]);
+        assert_eq!(repair.reveal_again, vec![giver.clone()]);
+        let find = |name: &str| game.players.iter().find(|p| p.name == name).unwrap();
+        assert_eq!(find("Zoe").picked(), carol);
+        assert!(find("Zoe").token().is_some());
+        assert_eq!(find(&giver).picked(), vec!["Zoe".to_string()]);
+        assert!(game.find_player("Carol").is_none());
+        assert_eq!(game.player_pick(&giver), Ok(vec!["Zoe".to_string()]));
     }
 
     #[test]
-    fn add_player_with_same_name_twice() {
-        let mut game = SecretSantaGame::default();
-        let player = Player::new("Player 1");
-        game.add_player(player.clone()).unwrap();
-        let second_add = game.add_player(player);
-        assert_eq!(second_add, Err("Player Player 1 already exists".into()));
+    fn withdraw_before_the_draw() {
+        let mut game = players_named(&["Ana", "Bob", "Carol", "Dan"]);
+        game.close_registration().unwrap();
+        assert_eq!(
+            game.remove_player("Carol"),
+            Err(GameError::RegistrationClosed)
+        );
+        assert_eq!(game.withdraw_player("Carol", None), Ok(Repair::default()));
+        game.withdraw_player("Dan", Some(Player::new("Zoe")))
+            .unwrap();
+        let names: Vec<&str> = game.players.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Ana", "Bob", "Zoe"]);
+        assert_eq!(
+            game.withdraw_player("Zoe", Some(Player::new("ana"))),
+            Err(GameError::PlayerAlreadyExists {
+                name: "ana".to_string()
+            })
+        );
+        assert_eq!(
+            game.add_player(Player::new("Eve")),
+            Err(GameError::RegistrationClosed)
+        );
     }
 
     #[test]
-    fn add_player_with_empty_name() {
-        let mut game = SecretSantaGame::default();
-        let player = Player::new("");
-        let result = game.add_player(player);
-        assert_eq!(result, Err("Player name cannot be empty".into()));
+    fn withdrawn_player_leaves_no_messages_or_wishlist() {
+        let mut game = players_named(&["Ana", "Bob", "Carol", "Dan"]);
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let token = |game: &SecretSantaGame, name: &str| {
+            let player = game.players.iter().find(|p| p.name == name).unwrap();
+            player.token().unwrap().to_string()
+        };
+        let carol = token(&game, "Carol");
+        let santa = token(&game, giver_of(&game, "Carol"));
+        game.player_pick_by_token(&santa).unwrap();
+        game.player_pick_by_token(&carol).unwrap();
+        game.set_wishlist(&carol, vec![WishlistItem::new("Book")])
+            .unwrap();
+        game.message_recipient(&santa, "Carol", "Any size?", 1)
+            .unwrap();
+        let recipient = game.revealed_recipients(&carol).unwrap()[0].clone();
+        game.message_recipient(&carol, &recipient, "Hello!", 2)
+            .unwrap();
+
+        game.withdraw_player("Carol", None).unwrap();
+        assert!(game.messages.is_empty());
+        assert!(game.wishlist_of("Carol").is_empty());
+        let drawn = &game.audit.as_ref().unwrap().players;
+        assert!(drawn.iter().all(|p| p.wishlist.is_empty()));
+
+        // someone else called Carol starts from nothing
+        game.add_late_player(Player::new("Carol")).unwrap();
+        let carol = token(&game, "Carol");
+        assert!(game.wishlist_of("Carol").is_empty());
+        let threads = game.threads_as_recipient(&carol).unwrap();
+        assert!(threads.iter().all(|t| t.messages.is_empty()));
     }
 
     #[test]
-    fn remove_player_in_game() {
-        let mut game = SecretSantaGame::default();
-        let player = Player::new("Player 1");
-        game.add_player(player.clone()).unwrap();
-        assert_eq!(game.players.len(), 1);
+    fn withdrawal_reports_who_must_reveal_again() {
+        let mut game = players_named(&["Ana", "Bob", "Carol", "Dan"]);
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let giver = giver_of(&game, "Carol").to_string();
+        let receiver = game.players[game.find_player("Carol").unwrap()].picked()[0].clone();
+        let other = giver_of(&game, &giver).to_string();
+        // the leaver's giver can no longer skip over them, the player who
+        // gives to that giver has to change too
+        game.exclusions.push(Exclusion::new(&giver, &receiver));
+        game.player_pick(&giver).unwrap();
+        game.player_pick(&other).unwrap();
 
-        let _ = game.remove_player(&player.name);
-        assert_eq!(game.players.len(), 0);
+        let mut repair = game.withdraw_player("Carol", None).unwrap();
+        repair.reveal_again.sort();
+        let mut expected = vec![giver.clone(), other.clone()];
+        expected.sort();
+        assert_eq!(repair.reveal_again, expected);
+        let find = |name: &str| game.players.iter().find(|p| p.name == name).unwrap();
+        assert_eq!(find(&giver).picked(), vec![other.clone()]);
+        assert_eq!(find(&other).picked(), vec![receiver]);
+        assert!(game.players.iter().all(|p| !p.has_picked));
+
+        let mut game = players_named(&["Ana", "Bob", "Carol"]);
+        game.set_gifts_per_person(2).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert_eq!(
+            game.withdraw_player("Carol", None),
+            Err(GameError::NotEnoughPlayers)
+        );
+        assert_eq!(
+            game.withdraw_player("Zoe", None),
+            Err(GameError::PlayerNotFound {
+                name: "Zoe".to_string()
+            })
+        );
+        assert_eq!(game.players.len(), 3);
+    }
+
+    #[test]
+    fn similar_names_are_reported() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Ana")).unwrap();
+        game.add_player(Player::new("Jonathan")).unwrap();
+        let batch = vec![
+            Player::new("Ána"),
+            Player::new("Jonathon"),
+            Player::new("Ann"),
+            Player::new("Anne-Marie"),
+            Player::new("Anne Marie"),
+        ];
+        assert!(game.check_players(&batch).iter().all(Result::is_ok));
+        assert_eq!(
+            game.similar_names(&batch),
+            vec![
+                vec!["Ana".to_string()],
+                vec!["Jonathan".to_string()],
+                vec![],
+                vec![],
+                vec!["Anne-Marie".to_string()],
+            ]
+        );
     }
 }
@@ -1,20 +1,24 @@
 use actix_files::Files;
 use actix_web::{http::header, web, App, HttpServer};
-use tokio;
 
 use bore_cli::client::Client;
 use dotenv::dotenv;
-use std::sync::Arc;
-use std::sync::Mutex;
 
 mod server;
 use actix_cors::Cors;
 
-use secret_santa::SecretSantaGame;
+use secret_santa::{generate_token, unix_now};
+use server::lobby::Lobby;
+use server::mailer::Mailer;
+use server::repository::{GameStore, InMemoryRepository, SqliteRepository};
 use server::routes::routes;
+use server::storage::StateFile;
 use server::utils::open_browser;
+use server::webhooks::WebhookDispatcher;
 
 const LOCAL_PORT: u16 = 8080;
+// how often scheduled reveals and exchanges are checked
+const SCHEDULE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
 use actix_web_static_files::ResourceFiles;
 
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
@@ -28,10 +32,88 @@ async fn main() -> std::io::Result<()> {
     // random port between 1000 and 2000
     let bore_port = rand::random::<u16>() % 1000 + 1000;
 
-    // create game logic
-    let game = SecretSantaGame::default();
-    let game_data = Arc::new(Mutex::new(game));
-    let secret_santa_game = web::Data::new(game_data);
+    // create game logic, backed by SQLite when a database path is given,
+    // otherwise kept in memory and snapshotted to the state file
+    let secret_santa_game = match std::env::var("SECRET_SANTA_DATABASE") {
+        Ok(database_path) => {
+            let repository =
+                SqliteRepository::open(&database_path).map_err(std::io::Error::other)?;
+            log::info!("Using SQLite database {}", database_path);
+            GameStore::new(repository)
+        }
+        Err(_) => {
+            let state_path = std::env::var("SECRET_SANTA_STATE_PATH")
+                .unwrap_or_else(|_| "secret-santa-state.json".to_string());
+            let state_file = StateFile::new(&state_path);
+            let rooms = state_file.restore()?;
+            GameStore::new(InMemoryRepository::with_state_file(rooms, state_file))
+        }
+    };
+    // anyone can create a game, so the number of rooms is capped
+    let secret_santa_game = match std::env::var("SECRET_SANTA_MAX_GAMES") {
+        Ok(max_games) => {
+            let max_games = max_games.parse().map_err(std::io::Error::other)?;
+            secret_santa_game.with_max_games(max_games)
+        }
+        Err(_) => secret_santa_game,
+    };
+
+    // every game has its own organizer secret, the first one is created here
+    let mut game_ids = secret_santa_game
+        .game_ids()
+        .map_err(|err| std::io::Error::other(err.to_string()))?;
+    if game_ids.is_empty() {
+        let admin_token =
+            std::env::var("SECRET_SANTA_ADMIN_TOKEN").unwrap_or_else(|_| generate_token());
+        let game_id = secret_santa_game
+            .create_game(&admin_token)
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+        game_ids.push(game_id);
+    }
+    let mut organizer_links = Vec::new();
+    for game_id in game_ids {
+        let admin_token = secret_santa_game
+            .admin_token(&game_id)
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+        organizer_links.push(format!("?game={}&admin={}", game_id, admin_token));
+    }
+    let secret_santa_game = web::Data::new(secret_santa_game);
+    let lobby = web::Data::new(Lobby::default());
+
+    // givers are emailed their recipients only when SMTP is configured
+    let mailer = Mailer::from_env().map_err(std::io::Error::other)?.map(web::Data::new);
+    if mailer.is_none() {
+        log::info!("No SMTP_HOST or SMTP_DRY_RUN_DIR set, recipients will not be emailed");
+    }
+
+    // posts game events to the webhooks organizers registered
+    // internal addresses only for the comma separated hosts allowed here
+    let allowed_hosts: Vec<String> = std::env::var("SECRET_SANTA_WEBHOOK_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(String::from)
+        .collect();
+    let webhooks = web::Data::new(WebhookDispatcher::default().with_allowed_hosts(allowed_hosts));
+    WebhookDispatcher::listen(webhooks.clone(), &secret_santa_game);
+
+    // opens reveals and closes exchanges when their dates come
+    let scheduled_games = secret_santa_game.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match scheduled_games.advance_schedules(unix_now()) {
+                Ok(game_ids) => {
+                    for game_id in game_ids {
+                        log::info!("Game {} moved on to its next phase as scheduled", game_id);
+                    }
+                }
+                Err(err) => log::error!("Failed to advance scheduled games: {}", err),
+            }
+        }
+    });
 
     let tunnel_url = format!("http://tunnel.guibeira.com:{}", bore_port);
 
@@ -41,6 +123,11 @@ async fn main() -> std::io::Result<()> {
     #[cfg(debug_assertions)]
     log::info!("Starting server on {}", localhost_url);
 
+    #[cfg(debug_assertions)]
+    for link in organizer_links.iter() {
+        log::info!("Organizer link: {}/{}", localhost_url, link);
+    }
+
     #[cfg(not(debug_assertions))]
     log::info!("Starting server on {}", tunnel_url);
 
@@ -61,8 +148,11 @@ async fn main() -> std::io::Result<()> {
 
     if cfg!(not(debug_assertions)) {
         log::info!("Opening browser");
-        let url = format!("https://tunnel.guibeira.com/{}", bore_port);
-        open_browser(&url).await;
+        let base_url = format!("https://tunnel.guibeira.com/{}/", bore_port);
+        for link in organizer_links.iter() {
+            log::info!("Organizer link: {}{}", base_url, link);
+        }
+        open_browser(&format!("{}{}", base_url, organizer_links[0])).await;
     }
 
     HttpServer::new(move || {
@@ -84,6 +174,13 @@ async fn main() -> std::io::Result<()> {
         let mut app = App::new()
             .wrap(actix_web::middleware::Logger::default())
             .app_data(secret_santa_game.clone())
+            .app_data(lobby.clone())
+            .app_data(webhooks.clone())
+            .configure(|cfg| {
+                if let Some(mailer) = &mailer {
+                    cfg.app_data(mailer.clone());
+                }
+            })
             .service(web::scope("/secret-santa").configure(routes))
             .wrap(cors);
 
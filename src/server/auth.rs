@@ -0,0 +1,61 @@
+use std::future::{ready, Ready};
+
+use super::error::CustomError;
+use super::repository::GameStore;
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpRequest};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// Handlers taking an `Organizer` only run when the request carries the
+// admin secret of the game in the path as a bearer token.
+pub struct Organizer;
+
+impl FromRequest for Organizer {
+    type Error = CustomError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let store = req.app_data::<web::Data<GameStore>>();
+        let game_id = req.match_info().get("game_id");
+        let provided = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        ready(match (store, game_id, provided) {
+            (Some(store), Some(game_id), Some(provided)) => match store.admin_token(game_id) {
+                Ok(secret) if same_secret(&secret, provided) => Ok(Organizer),
+                Ok(_) => Err(CustomError::Unauthorized),
+                Err(err) => Err(err),
+            },
+            _ => Err(CustomError::Unauthorized),
+        })
+    }
+}
+
+// Compares HMACs of both values in constant time, so response times tell
+// nothing about how much of a guessed secret was right, nor its length.
+fn same_secret(secret: &str, provided: &str) -> bool {
+    let mac = |value: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(value.as_bytes());
+        mac
+    };
+    mac(provided)
+        .verify_slice(&mac(secret).finalize().into_bytes())
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_secrets() {
+        assert!(same_secret("organizer-secret", "organizer-secret"));
+        assert!(!same_secret("organizer-secret", "organizer-secreT"));
+        assert!(!same_secret("organizer-secret", "organizer"));
+        assert!(!same_secret("organizer-secret", ""));
+    }
+}
@@ -1,23 +1,58 @@
 use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 
 use derive_more::{Display, Error};
+use secret_santa::GameError;
 use serde::Serialize;
 
 #[derive(Debug, Display, Error, PartialEq)]
 pub enum CustomError {
     #[display(fmt = "{}", error)]
-    ValidationError { error: String },
+    GameError { error: GameError },
+    #[display(fmt = "Organizer token missing or invalid")]
+    Unauthorized,
+    #[display(fmt = "Game not found")]
+    GameNotFound,
+    #[display(fmt = "{}", error)]
+    StorageError { error: String },
+    #[display(fmt = "Email is not configured on this server")]
+    EmailDisabled,
+    #[display(fmt = "This server already holds {} games", max)]
+    TooManyGames { max: usize },
+    #[display(fmt = "Webhooks cannot call internal addresses: {}", url)]
+    WebhookTargetForbidden { url: String },
+}
+
+impl From<GameError> for CustomError {
+    fn from(error: GameError) -> Self {
+        CustomError::GameError { error }
+    }
+}
+
+impl CustomError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CustomError::GameError { error } => error.code(),
+            CustomError::Unauthorized => "unauthorized",
+            CustomError::GameNotFound => "game_not_found",
+            CustomError::StorageError { .. } => "storage_error",
+            CustomError::EmailDisabled => "email_disabled",
+            CustomError::TooManyGames { .. } => "too_many_games",
+            CustomError::WebhookTargetForbidden { .. } => "webhook_target_forbidden",
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
+    code: &'static str,
 }
 
 impl ResponseError for CustomError {
     fn error_response(&self) -> HttpResponse {
         let body = serde_json::to_string(&ErrorResponse {
             error: self.to_string(),
+            code: self.code(),
         });
 
         HttpResponse::build(self.status_code())
@@ -26,8 +61,59 @@ impl ResponseError for CustomError {
     }
 
     fn status_code(&self) -> StatusCode {
-        match *self {
-            CustomError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+        match self {
+            CustomError::GameError { error } => match error {
+                GameError::PlayerNotFound { .. }
+                | GameError::InvalidToken
+                | GameError::ExclusionNotFound
+                | GameError::ThreadNotFound
+                | GameError::WebhookNotFound => StatusCode::NOT_FOUND,
+                GameError::GameLocked
+                | GameError::AlreadyStarted
+                | GameError::AlreadyFinished
+                | GameError::NotStarted
+                | GameError::Finished
+                | GameError::NotFinished
+                | GameError::PlayerAlreadyExists { .. }
+                | GameError::AlreadyPicked
+                | GameError::ExclusionAlreadyExists { .. }
+                | GameError::SeedNotRevealed
+                | GameError::LateJoinImpossible { .. }
+                | GameError::RegistrationClosed
+                | GameError::RevealsNotOpen
+                | GameError::InvalidTransition { .. }
+                | GameError::RevealsScheduled { .. }
+                | GameError::NotRevealed => StatusCode::CONFLICT,
+                GameError::EmptyPlayerName
+                | GameError::NameTooShort { .. }
+                | GameError::NameTooLong { .. }
+                | GameError::InvalidNameRules
+                | GameError::InvalidGiftsPerPerson
+                | GameError::InvalidSchedule
+                | GameError::EmptyWishlistTitle
+                | GameError::TooManyWishlistItems { .. }
+                | GameError::InvalidBudget
+                | GameError::InvalidCurrency
+                | GameError::EventFieldTooLong { .. }
+                | GameError::EmptyMessage
+                | GameError::MessageTooLong { .. }
+                | GameError::InvalidEmail { .. }
+                | GameError::InvalidWebhookUrl { .. }
+                | GameError::UnknownWebhookEvent { .. }
+                | GameError::TooManyWebhooks { .. }
+                | GameError::DuplicatePlayer { .. }
+                | GameError::SelfExclusion
+                | GameError::EmptyHistoryWindow
+                | GameError::NotEnoughPlayers
+                | GameError::OversizedGroup { .. }
+                | GameError::NoValidAssignment
+                | GameError::SearchGaveUp => StatusCode::UNPROCESSABLE_ENTITY,
+            },
+            CustomError::Unauthorized => StatusCode::UNAUTHORIZED,
+            CustomError::GameNotFound => StatusCode::NOT_FOUND,
+            CustomError::StorageError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            CustomError::WebhookTargetForbidden { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            CustomError::EmailDisabled | CustomError::TooManyGames { .. } => StatusCode::CONFLICT,
         }
     }
 }
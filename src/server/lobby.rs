@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::models::SantaGameInfo;
+use super::repository::GameStore;
+use actix_web::web;
+use actix_ws::{Message, MessageStream, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// Lobby protocol, JSON text frames tagged by `type`.
+//
+// Client to server:
+//   {"type": "hello", "token": "..."} the personal token of the player behind
+//                                      this connection, who is shown by the name
+//                                      the game knows; sending it again switches
+//                                      the connection to that player
+//
+// Server to client:
+//   {"type": "game", "game": {...}}    game info, on connect and after every change
+//   {"type": "presence", "online": ["Ana"], "anonymous": 2}
+//                                      everyone connected to the game, sent on
+//                                      connect and whenever someone comes or goes
+//   {"type": "joined", "name": "Ana"}  first connection for a name
+//   {"type": "left", "name": "Ana"}    last connection for a name closed
+//   {"type": "error", "message": "..."}
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello { token: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Game {
+        game: Box<SantaGameInfo>,
+    },
+    Presence {
+        online: Vec<String>,
+        anonymous: usize,
+    },
+    Joined {
+        name: String,
+    },
+    Left {
+        name: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct LobbyEvent {
+    game_id: String,
+    message: ServerMessage,
+}
+
+// Who is connected to each game. Connections without a name are visitors
+// that have not said hello yet.
+pub struct Lobby {
+    connections: Mutex<HashMap<String, HashMap<u64, Option<String>>>>,
+    next_connection: AtomicU64,
+    events: broadcast::Sender<LobbyEvent>,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(64);
+        Lobby {
+            connections: Mutex::new(HashMap::new()),
+            next_connection: AtomicU64::new(0),
+            events,
+        }
+    }
+}
+
+impl Lobby {
+    fn connect(&self, game_id: &str) -> u64 {
+        let connection = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let mut connections = self.connections.lock().unwrap();
+        let game = connections.entry(game_id.to_string()).or_default();
+        game.insert(connection, None);
+        self.publish(game_id, presence(game));
+        connection
+    }
+
+    fn set_name(&self, game_id: &str, connection: u64, name: &str) {
+        let mut connections = self.connections.lock().unwrap();
+        let game = connections.entry(game_id.to_string()).or_default();
+        let previous = game.insert(connection, Some(name.to_string())).flatten();
+        if previous.as_deref() == Some(name) {
+            return;
+        }
+        if let Some(previous) = previous {
+            if !is_online(game, &previous) {
+                self.publish(game_id, ServerMessage::Left { name: previous });
+            }
+        }
+        if game
+            .values()
+            .flatten()
+            .filter(|n| n.as_str() == name)
+            .count()
+            == 1
+        {
+            self.publish(
+                game_id,
+                ServerMessage::Joined {
+                    name: name.to_string(),
+                },
+            );
+        }
+        self.publish(game_id, presence(game));
+    }
+
+    // Names the connection after the player the token belongs to, a client
+    // cannot speak for anybody else.
+    fn hello(
+        &self,
+        store: &GameStore,
+        game_id: &str,
+        connection: u64,
+        token: &str,
+    ) -> Result<(), String> {
+        let name = store
+            .read(game_id, |game| {
+                game.player_by_token(token)
+                    .map(|player| player.display_name().to_string())
+            })
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        self.set_name(game_id, connection, &name);
+        Ok(())
+    }
+
+    fn disconnect(&self, game_id: &str, connection: u64) {
+        let mut connections = self.connections.lock().unwrap();
+        let Some(game) = connections.get_mut(game_id) else {
+            return;
+        };
+        if let Some(Some(name)) = game.remove(&connection) {
+            if !is_online(game, &name) {
+                self.publish(game_id, ServerMessage::Left { name });
+            }
+        }
+        self.publish(game_id, presence(game));
+        if game.is_empty() {
+            connections.remove(game_id);
+        }
+    }
+
+    // Handles a text frame from a client, returning the reply if there is one.
+    fn receive(
+        &self,
+        store: &GameStore,
+        game_id: &str,
+        connection: u64,
+        text: &str,
+    ) -> Option<ServerMessage> {
+        let result = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(ClientMessage::Hello { token }) => self.hello(store, game_id, connection, &token),
+            Err(err) => Err(format!("Invalid message: {}", err)),
+        };
+        result.err().map(|message| ServerMessage::Error { message })
+    }
+
+    fn presence(&self, game_id: &str) -> ServerMessage {
+        let connections = self.connections.lock().unwrap();
+        match connections.get(game_id) {
+            Some(game) => presence(game),
+            None => presence(&HashMap::new()),
+        }
+    }
+
+    fn publish(&self, game_id: &str, message: ServerMessage) {
+        // nobody listening is not an error
+        let _ = self.events.send(LobbyEvent {
+            game_id: game_id.to_string(),
+            message,
+        });
+    }
+}
+
+fn is_online(game: &HashMap<u64, Option<String>>, name: &str) -> bool {
+    game.values().flatten().any(|n| n == name)
+}
+
+fn presence(game: &HashMap<u64, Option<String>>) -> ServerMessage {
+    let mut online: Vec<String> = game.values().flatten().cloned().collect();
+    online.sort();
+    online.dedup();
+    ServerMessage::Presence {
+        online,
+        anonymous: game.values().filter(|name| name.is_none()).count(),
+    }
+}
+
+fn game_message(store: &GameStore, game_id: &str) -> Option<ServerMessage> {
+    let game = store.read(game_id, SantaGameInfo::new).ok()?;
+    Some(ServerMessage::Game {
+        game: Box::new(game),
+    })
+}
+
+async fn send(session: &mut Session, message: &ServerMessage) -> Result<(), actix_ws::Closed> {
+    session.text(serde_json::to_string(message).unwrap()).await
+}
+
+// Runs one lobby connection until the client goes away.
+pub async fn run(
+    mut session: Session,
+    mut stream: MessageStream,
+    game_id: String,
+    store: web::Data<GameStore>,
+    lobby: web::Data<Lobby>,
+) {
+    let mut game_changes = store.subscribe();
+    let mut lobby_events = lobby.events.subscribe();
+    let connection = lobby.connect(&game_id);
+
+    let greeting = match game_message(&store, &game_id) {
+        Some(game) => send(&mut session, &game).await,
+        None => Err(actix_ws::Closed),
+    };
+    if greeting.is_ok() && send(&mut session, &lobby.presence(&game_id)).await.is_ok() {
+        loop {
+            let reply = tokio::select! {
+                message = stream.recv() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        lobby.receive(&store, &game_id, connection, &text)
+                    }
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                        None
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => None,
+                },
+                changed = game_changes.recv() => match changed {
+                    Ok(changed) if changed != game_id => None,
+                    Ok(_) | Err(RecvError::Lagged(_)) => match game_message(&store, &game_id) {
+                        Some(game) => Some(game),
+                        None => break,
+                    },
+                    Err(RecvError::Closed) => break,
+                },
+                event = lobby_events.recv() => match event {
+                    Ok(event) if event.game_id == game_id => Some(event.message),
+                    Ok(_) => None,
+                    Err(RecvError::Lagged(_)) => Some(lobby.presence(&game_id)),
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            if let Some(reply) = reply {
+                if send(&mut session, &reply).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    lobby.disconnect(&game_id, connection);
+    let _ = session.close(None).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::repository::{GameRepository, InMemoryRepository};
+    use super::*;
+    use secret_santa::{Player, SecretSantaGame};
+
+    fn store() -> GameStore {
+        let mut game = SecretSantaGame::default();
+        game.add_players(vec![Player::new(" Ana "), Player::new("Bruno")])
+            .unwrap();
+        let repository = InMemoryRepository::default();
+        repository.create("family", "secret").unwrap();
+        repository.save("family", &game).unwrap();
+        GameStore::new(repository)
+    }
+
+    fn hello(store: &GameStore, name: &str) -> String {
+        let token = store
+            .read("family", |game| {
+                let player = game.players.iter().find(|p| p.display_name() == name);
+                player.unwrap().token().unwrap().to_string()
+            })
+            .unwrap();
+        serde_json::json!({ "type": "hello", "token": token }).to_string()
+    }
+
+    fn online(lobby: &Lobby, game_id: &str) -> (Vec<String>, usize) {
+        match lobby.presence(game_id) {
+            ServerMessage::Presence { online, anonymous } => (online, anonymous),
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn lobby_tracks_presence_per_game() {
+        let store = store();
+        let lobby = Lobby::default();
+        let mut events = lobby.events.subscribe();
+        let first = lobby.connect("family");
+        let second = lobby.connect("family");
+        lobby.connect("office");
+        assert!(lobby
+            .receive(&store, "family", first, &hello(&store, "Ana"))
+            .is_none());
+        assert!(lobby
+            .receive(&store, "family", second, &hello(&store, "Ana"))
+            .is_none());
+        assert_eq!(online(&lobby, "family"), (vec!["Ana".to_string()], 0));
+        assert_eq!(online(&lobby, "office"), (vec![], 1));
+        assert!(matches!(
+            lobby.receive(&store, "family", first, r#"{"type": "shout"}"#),
+            Some(ServerMessage::Error { .. })
+        ));
+
+        // Ana is still connected from another tab
+        lobby.disconnect("family", first);
+        assert_eq!(online(&lobby, "family"), (vec!["Ana".to_string()], 0));
+        lobby.disconnect("family", second);
+        assert_eq!(online(&lobby, "family"), (vec![], 0));
+
+        let mut joined = 0;
+        let mut left = 0;
+        while let Ok(event) = events.try_recv() {
+            match event.message {
+                ServerMessage::Joined { name } => {
+                    assert_eq!((event.game_id.as_str(), name.as_str()), ("family", "Ana"));
+                    joined += 1;
+                }
+                ServerMessage::Left { .. } => left += 1,
+                _ => {}
+            }
+        }
+        assert_eq!((joined, left), (1, 1));
+    }
+
+    #[test]
+    fn hello_needs_a_personal_token() {
+        let store = store();
+        let lobby = Lobby::default();
+        let connection = lobby.connect("family");
+        for text in [
+            r#"{"type": "hello", "name": "Bruno"}"#,
+            r#"{"type": "hello", "token": "not-a-token"}"#,
+        ] {
+            assert!(matches!(
+                lobby.receive(&store, "family", connection, text),
+                Some(ServerMessage::Error { .. })
+            ));
+        }
+        assert_eq!(online(&lobby, "family"), (vec![], 1));
+
+        lobby.receive(&store, "family", connection, &hello(&store, "Bruno"));
+        assert_eq!(online(&lobby, "family"), (vec!["Bruno".to_string()], 0));
+    }
+}
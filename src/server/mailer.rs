@@ -0,0 +1,541 @@
+use std::env;
+use std::path::PathBuf;
+
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secret_santa::{EventDetails, SecretSantaGame, WishlistItem};
+
+use super::models::{EmailFailure, EmailReport};
+
+// The texts of one language, `body` fills in `{giver}`, `{title}`,
+// `{recipients}`, `{wishlists}`, `{event}` and `{link}`.
+struct Template {
+    subject: &'static str,
+    body: &'static str,
+    and: &'static str,
+    wishlist_of: &'static str,
+    empty_wishlist: &'static str,
+    event: &'static str,
+    budget: &'static str,
+    location: &'static str,
+    theme: &'static str,
+    rules: &'static str,
+    link: &'static str,
+}
+
+const ENGLISH: Template = Template {
+    subject: "Your Secret Santa",
+    body: include_str!("templates/recipient.en.txt"),
+    and: "and",
+    wishlist_of: "Wishlist of",
+    empty_wishlist: "nothing yet",
+    event: "The event",
+    budget: "Budget",
+    location: "Where",
+    theme: "Theme",
+    rules: "Rules",
+    link: "Your personal link",
+};
+
+const PORTUGUESE: Template = Template {
+    subject: "Seu amigo secreto",
+    body: include_str!("templates/recipient.pt_BR.txt"),
+    and: "e",
+    wishlist_of: "Lista de desejos de",
+    empty_wishlist: "nada ainda",
+    event: "O evento",
+    budget: "Orçamento",
+    location: "Onde",
+    theme: "Tema",
+    rules: "Regras",
+    link: "Seu link pessoal",
+};
+
+fn template(language: &str) -> &'static Template {
+    match language {
+        "pt_BR" => &PORTUGUESE,
+        _ => &ENGLISH,
+    }
+}
+
+pub struct Email {
+    pub game_id: String,
+    // position of the giver in the game, keeps dry run file names apart
+    pub index: usize,
+    pub player: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// Fills every `{key}` of the template in a single pass, so placeholders
+// inside the inserted values (a player named "{recipients}") stay as typed.
+fn fill<'a>(template: &str, value: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let placeholder = rest[start..]
+            .find('}')
+            .and_then(|end| Some((end, value(&rest[start + 1..start + end])?)));
+        match placeholder {
+            Some((end, value)) => {
+                filled.push_str(value);
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn names(names: &[&str], and: &str) -> String {
+    match names {
+        [] => String::new(),
+        [name] => name.to_string(),
+        [rest @ .., last] => format!("{} {} {}", rest.join(", "), and, last),
+    }
+}
+
+fn wishlist(template: &Template, name: &str, items: &[WishlistItem]) -> String {
+    let mut text = format!("\n{} {}:\n", template.wishlist_of, name);
+    if items.is_empty() {
+        text.push_str(&format!("- {}\n", template.empty_wishlist));
+    }
+    for item in items {
+        let mut line = format!("- {}", item.title);
+        for extra in [&item.price_hint, &item.link, &item.notes]
+            .into_iter()
+            .flatten()
+        {
+            line.push_str(&format!(" | {}", extra));
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+fn budget(event: &EventDetails) -> Option<String> {
+    let currency = event
+        .currency
+        .as_deref()
+        .map(|code| format!(" {}", code))
+        .unwrap_or_default();
+    match (event.budget_min, event.budget_max) {
+        (Some(min), Some(max)) if min == max => Some(format!("{}{}", max, currency)),
+        (Some(min), Some(max)) => Some(format!("{} - {}{}", min, max, currency)),
+        (Some(min), None) => Some(format!("{}+{}", min, currency)),
+        (None, Some(max)) => Some(format!("<= {}{}", max, currency)),
+        (None, None) => None,
+    }
+}
+
+fn event(template: &Template, event: &EventDetails) -> String {
+    let lines: Vec<String> = [
+        (template.budget, budget(event)),
+        (template.location, event.location.clone()),
+        (template.theme, event.theme.clone()),
+        (template.rules, event.rules.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| value.map(|value| format!("{}: {}\n", label, value)))
+    .collect();
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("\n{}:\n{}", template.event, lines.concat())
+}
+
+// One email per giver with an address, `link` is where the frontend is
+// served and gets the personal link appended.
+pub fn recipient_emails(
+    game_id: &str,
+    game: &SecretSantaGame,
+    language: &str,
+    link: Option<&str>,
+) -> Vec<Email> {
+    let template = template(language);
+    let event_section = event(template, &game.event);
+    let title = game
+        .event
+        .title
+        .as_deref()
+        .map(|title| format!(" \"{}\"", title))
+        .unwrap_or_default();
+    game.players
+        .iter()
+        .enumerate()
+        .filter_map(|(index, giver)| {
+            let to = giver.email.clone()?;
+            let recipients: Vec<&str> = giver
+                .picked()
+                .iter()
+                .map(|name| match game.players.iter().find(|p| p.name == *name) {
+                    Some(recipient) => recipient.display_name(),
+                    None => name.as_str(),
+                })
+                .collect();
+            let wishlists: String = giver
+                .picked()
+                .iter()
+                .zip(&recipients)
+                .map(|(name, display)| wishlist(template, display, game.wishlist_of(name)))
+                .collect();
+            let link = match (link, giver.token()) {
+                (Some(url), Some(token)) => format!(
+                    "\n{}: {}?game={}&token={}\n",
+                    template.link,
+                    url.trim_end_matches('/'),
+                    game_id,
+                    token
+                ),
+                _ => String::new(),
+            };
+            let recipients = names(&recipients, template.and);
+            let body = fill(template.body, |key| match key {
+                "giver" => Some(giver.display_name()),
+                "title" => Some(&title),
+                "recipients" => Some(&recipients),
+                "wishlists" => Some(&wishlists),
+                "event" => Some(&event_section),
+                "link" => Some(&link),
+                _ => None,
+            });
+            Some(Email {
+                game_id: game_id.to_string(),
+                index,
+                player: giver.display_name().to_string(),
+                to,
+                subject: match &game.event.title {
+                    Some(title) => format!("{}: {}", template.subject, title),
+                    None => template.subject.to_string(),
+                },
+                body,
+            })
+        })
+        .collect()
+}
+
+enum Delivery {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    // writes each email to an .eml file in the directory instead
+    DryRun(PathBuf),
+}
+
+pub struct Mailer {
+    from: Mailbox,
+    delivery: Delivery,
+    pub link: Option<String>,
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+impl Mailer {
+    // Nothing is sent unless SMTP_HOST or SMTP_DRY_RUN_DIR is set.
+    // SMTP_SECURITY is "starttls" (default), "tls" or "none", the latter for
+    // local catchers such as MailHog.
+    pub fn from_env() -> Result<Option<Mailer>, String> {
+        let from = var("SMTP_FROM")
+            .unwrap_or_else(|| "Secret Santa <santa@localhost>".to_string())
+            .parse()
+            .map_err(|e| format!("Invalid SMTP_FROM: {}", e))?;
+        let link = var("SECRET_SANTA_PUBLIC_URL");
+        if let Some(dir) = var("SMTP_DRY_RUN_DIR") {
+            return Ok(Some(Mailer::dry_run(dir.into(), from, link)));
+        }
+        let Some(host) = var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let security = var("SMTP_SECURITY").unwrap_or_else(|| "starttls".to_string());
+        let mut builder = match security.as_str() {
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                .map_err(|e| e.to_string())?,
+            "tls" => {
+                AsyncSmtpTransport::<Tokio1Executor>::relay(&host).map_err(|e| e.to_string())?
+            }
+            other => return Err(format!("Unknown SMTP_SECURITY {}", other)),
+        };
+        if let Some(port) = var("SMTP_PORT") {
+            builder = builder.port(
+                port.parse()
+                    .map_err(|_| format!("Invalid SMTP_PORT {}", port))?,
+            );
+        }
+        if let (Some(username), Some(password)) = (var("SMTP_USERNAME"), var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(Some(Mailer {
+            from,
+            delivery: Delivery::Smtp(builder.build()),
+            link,
+        }))
+    }
+
+    pub fn dry_run(dir: PathBuf, from: Mailbox, link: Option<String>) -> Mailer {
+        Mailer {
+            from,
+            delivery: Delivery::DryRun(dir),
+            link,
+        }
+    }
+
+    async fn send(&self, email: &Email) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse().map_err(|e| format!("{}", e))?)
+            .subject(email.subject.as_str())
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())
+            .map_err(|e| e.to_string())?;
+        match &self.delivery {
+            Delivery::Smtp(transport) => transport
+                .send(message)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Delivery::DryRun(dir) => {
+                let file: String = format!("{}-{}-{}", email.game_id, email.index, email.player)
+                    .chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || c == '-' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                tokio::fs::write(dir.join(format!("{}.eml", file)), message.formatted())
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    pub async fn send_all(&self, emails: Vec<Email>, without_email: Vec<String>) -> EmailReport {
+        let mut report = EmailReport {
+            without_email,
+            ..Default::default()
+        };
+        for email in emails {
+            match self.send(&email).await {
+                Ok(()) => report.sent.push(email.player),
+                Err(error) => {
+                    log::warn!("Could not email {}: {}", email.player, error);
+                    report.failed.push(EmailFailure {
+                        name: email.player,
+                        error,
+                    })
+                }
+            }
+        }
+        report
+    }
+}
+
+pub fn players_without_email(game: &SecretSantaGame) -> Vec<String> {
+    game.players
+        .iter()
+        .filter(|player| player.email.is_none())
+        .map(|player| player.display_name().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secret_santa::Player;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn drawn_game() -> SecretSantaGame {
+        let mut game = SecretSantaGame::default();
+        game.add_players(vec![
+            Player::new("Ana").with_email("ana@example.com"),
+            Player::new("Bruno").with_email("bruno@example.com"),
+            Player::new("Carla"),
+        ])
+        .unwrap();
+        game.start_game(Some("seed".to_string())).unwrap();
+        game
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("secret-santa-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn emails_carry_recipient_wishlist_and_event() {
+        let mut game = drawn_game();
+        let position = |game: &SecretSantaGame, name: &str| {
+            game.players.iter().position(|p| p.name == name).unwrap()
+        };
+        let ana = position(&game, "Ana");
+        let index = position(&game, &game.players[ana].picked()[0]);
+        game.players[index].wishlist = vec![WishlistItem::new("Livro")];
+        game.event.budget_max = Some(50);
+        game.event.currency = Some("BRL".to_string());
+
+        let emails = recipient_emails("g1", &game, "pt_BR", Some("https://santa.example.com/"));
+        assert_eq!(emails.len(), 2);
+        let email = emails.iter().find(|e| e.player == "Ana").unwrap();
+        assert_eq!(email.to, "ana@example.com");
+        assert_eq!(email.subject, "Seu amigo secreto");
+        let display = game.players[index].display_name();
+        assert!(email
+            .body
+            .contains(&format!("você vai presentear {}.", display)));
+        assert!(email
+            .body
+            .contains(&format!("Lista de desejos de {}:\n- Livro", display)));
+        assert!(email.body.contains("Orçamento: <= 50 BRL"));
+        assert!(email.body.contains(&format!(
+            "https://santa.example.com?game=g1&token={}",
+            game.players[ana].token().unwrap()
+        )));
+
+        let emails = recipient_emails("g1", &game, "de", None);
+        assert!(emails[0].body.starts_with("Hi "));
+        assert!(!emails[0].body.contains("token="));
+        assert_eq!(players_without_email(&game), vec!["Carla".to_string()]);
+    }
+
+    #[test]
+    fn placeholders_in_names_are_kept_as_typed() {
+        let mut game = SecretSantaGame::default();
+        game.add_players(vec![
+            Player::new("{recipients}").with_email("a@example.com"),
+            Player::new("{link}").with_email("b@example.com"),
+        ])
+        .unwrap();
+        game.start_game(None).unwrap();
+        let emails = recipient_emails("g1", &game, "en", Some("https://santa.example.com"));
+        let body = |name: &str| &emails.iter().find(|e| e.player == name).unwrap().body;
+        assert!(body("{recipients}").starts_with("Hi {recipients},"));
+        assert!(body("{recipients}").contains("giving a gift to {link}."));
+        assert!(body("{link}").starts_with("Hi {link},"));
+        assert!(body("{link}").contains("giving a gift to {recipients}."));
+        assert_eq!(body("{link}").matches("token=").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn dry_run_writes_emails_to_files() {
+        let dir = temp_dir("dry-run");
+        let mailer = Mailer::dry_run(dir.clone(), "santa@example.com".parse().unwrap(), None);
+        let game = drawn_game();
+        let report = mailer
+            .send_all(recipient_emails("g1", &game, "en", None), vec![])
+            .await;
+        assert_eq!(report.sent.len(), 2);
+        assert!(report.failed.is_empty());
+        let index = game.players.iter().position(|p| p.name == "Ana").unwrap();
+        let ana = std::fs::read_to_string(dir.join(format!("g1-{}-Ana.eml", index))).unwrap();
+        assert!(ana.contains("To: ana@example.com"));
+        assert!(ana.contains("Subject: Your Secret Santa"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run_keeps_similar_names_apart() {
+        let dir = temp_dir("dry-run-names");
+        let mailer = Mailer::dry_run(dir.clone(), "santa@example.com".parse().unwrap(), None);
+        let mut game = SecretSantaGame::default();
+        game.add_players(vec![
+            Player::new("Ana B").with_email("ana.b@example.com"),
+            Player::new("Ana_B").with_email("ana_b@example.com"),
+        ])
+        .unwrap();
+        game.start_game(None).unwrap();
+        let report = mailer
+            .send_all(recipient_emails("g1", &game, "en", None), vec![])
+            .await;
+        assert_eq!(report.sent.len(), 2);
+        for (index, player) in game.players.iter().enumerate() {
+            let email =
+                std::fs::read_to_string(dir.join(format!("g1-{}-Ana_B.eml", index))).unwrap();
+            assert!(email.contains(&format!("To: {}", player.email.as_deref().unwrap())));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // Just enough SMTP to accept every message, standing in for MailHog.
+    async fn smtp_catcher(listener: TcpListener, expected: usize) -> Vec<String> {
+        let mut messages = vec![];
+        while messages.len() < expected {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 catcher\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = match line.to_uppercase().split(' ').next().unwrap() {
+                    "EHLO" | "HELO" => b"250 catcher\r\n",
+                    "DATA" => {
+                        writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                        let mut data = String::new();
+                        while let Some(line) = lines.next_line().await.unwrap() {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push('\n');
+                        }
+                        messages.push(data);
+                        b"250 queued\r\n"
+                    }
+                    "QUIT" => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn emails_go_through_the_smtp_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let catcher = tokio::spawn(smtp_catcher(listener, 2));
+        let mailer = Mailer {
+            from: "santa@example.com".parse().unwrap(),
+            delivery: Delivery::Smtp(
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+                    .port(port)
+                    .build(),
+            ),
+            link: None,
+        };
+        let game = drawn_game();
+        let mut emails = recipient_emails("g1", &game, "en", None);
+        emails.push(Email {
+            game_id: "g1".to_string(),
+            index: 3,
+            player: "Dora".to_string(),
+            to: "not an address".to_string(),
+            subject: String::new(),
+            body: String::new(),
+        });
+        let report = mailer.send_all(emails, vec!["Carla".to_string()]).await;
+        assert_eq!(report.sent.len(), 2);
+        assert_eq!(report.failed[0].name, "Dora");
+        assert_eq!(report.without_email, vec!["Carla".to_string()]);
+
+        let messages = catcher.await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().any(|m| m.contains("To: bruno@example.com")));
+    }
+}
@@ -1,4 +1,10 @@
+pub mod auth;
 mod error;
+pub mod lobby;
+pub mod mailer;
 mod models;
-pub mod utils;
+pub mod repository;
 pub mod routes;
+pub mod storage;
+pub mod utils;
+pub mod webhooks;
@@ -1,27 +1,315 @@
 use actix_web::Responder;
-use secret_santa::GameStatus;
+use secret_santa::{
+    DrawStrategy, EventDetails, Exclusion, GameStatus, HistoryPolicy, PastDraw, Schedule,
+    SecretSantaGame, Thread, Webhook, WishlistItem,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PlayerInfo {
     pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub group: Option<String>,
     pub has_picked: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SantaGameInfo {
     pub status: GameStatus,
     pub players: Vec<PlayerInfo>,
+    #[serde(default)]
+    pub commitment: Option<String>,
+    #[serde(default)]
+    pub seed: Option<String>,
+    pub draw_strategy: DrawStrategy,
+    pub gifts_per_person: usize,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub event: EventDetails,
+}
+
+impl SantaGameInfo {
+    pub fn new(game: &SecretSantaGame) -> Self {
+        SantaGameInfo {
+            status: game.status,
+            players: game
+                .players
+                .iter()
+                .map(|player| PlayerInfo {
+                    name: player.name.clone(),
+                    display_name: player.display_name().to_string(),
+                    group: player.group.clone(),
+                    has_picked: player.has_picked,
+                })
+                .collect(),
+            commitment: game.audit.as_ref().map(|audit| audit.commitment.clone()),
+            seed: game
+                .audit
+                .as_ref()
+                .and_then(|audit| audit.revealed_seed().map(str::to_string)),
+            draw_strategy: game.draw_strategy,
+            gifts_per_person: game.gifts_per_person,
+            schedule: game.schedule,
+            event: game.event.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Players {
     pub names: Vec<String>,
+    #[serde(default)]
+    pub groups: HashMap<String, String>,
+    #[serde(default)]
+    pub emails: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LatePlayer {
+    pub name: String,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+// `reassigned` lists the players who now give to the newcomer instead of one
+// of their recipients, none of them had revealed yet.
+#[derive(Serialize, Deserialize)]
+pub struct LateJoin {
+    pub name: String,
+    pub token: String,
+    pub reassigned: Vec<String>,
+}
+
+// Takes `name` out of a running game, `replacement` joins in their place.
+#[derive(Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub name: String,
+    #[serde(default)]
+    pub replacement: Option<LatePlayer>,
+}
+
+// `reveal_again` lists the players who had revealed a recipient that changed,
+// their personal link works once more.
+#[derive(Serialize, Deserialize)]
+pub struct WithdrawalReport {
+    pub reassigned: Vec<String>,
+    pub reveal_again: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerReport {
+    pub name: String,
+    pub accepted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    // near-duplicates of this name, they do not stop the batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub similar_to: Vec<String>,
+    // the personal link of an added player, also good for the lobby
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+// Either every player of the batch was added, or none was and the
+// rejected ones say why. `error` and `code` mirror the usual error body.
+#[derive(Serialize, Deserialize)]
+pub struct AddPlayersReport {
+    pub applied: bool,
+    pub players: Vec<PlayerReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Exclusions {
+    pub exclusions: Vec<Exclusion>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct History {
+    pub draws: Vec<PastDraw>,
+    pub policy: HistoryPolicy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerTokens {
+    pub tokens: HashMap<String, String>,
+    // only when the recipients were emailed right away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emails: Option<EmailReport>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EmailFailure {
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct EmailReport {
+    pub sent: Vec<String>,
+    pub failed: Vec<EmailFailure>,
+    pub without_email: Vec<String>,
+}
+
+// The secret is only shown when the webhook is added.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookInfo {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl WebhookInfo {
+    pub fn new(webhook: &Webhook) -> Self {
+        WebhookInfo {
+            id: webhook.id.clone(),
+            url: webhook.url.clone(),
+            events: webhook.events.clone(),
+            secret: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebhookList {
+    pub webhooks: Vec<WebhookInfo>,
+}
+
+impl WebhookList {
+    pub fn new(game: &SecretSantaGame) -> Self {
+        WebhookList {
+            webhooks: game.webhooks.iter().map(WebhookInfo::new).collect(),
+        }
+    }
+}
+
+// An empty `events` subscribes to every event.
+#[derive(Serialize, Deserialize)]
+pub struct NewWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+// One event sent to one webhook, `status` is the last HTTP answer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub sent_at: u64,
+    pub attempts: u32,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebhookDeliveries {
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct NotifyPlayers {
+    // "en" or "pt_BR", English otherwise
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewGame {
+    pub id: String,
+    pub admin_token: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct StartGame {
+    #[serde(default)]
+    pub seed: Option<String>,
+    // leave the game drawn, reveals open later with `open-reveals`
+    #[serde(default)]
+    pub hold_reveals: bool,
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DrawSettings {
+    pub strategy: DrawStrategy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GiftSettings {
+    pub gifts_per_person: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DrawVerification {
+    pub seed: String,
+    pub commitment: String,
+    pub valid: bool,
+    // players joined or left after the draw, so today's assignment differs
+    pub amended: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PickedResponse {
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub recipients: Vec<Recipient>,
+}
+
+impl PickedResponse {
+    pub fn new(game: &SecretSantaGame, names: Vec<String>) -> Self {
+        let recipients = names
+            .iter()
+            .map(|name| Recipient {
+                name: name.clone(),
+                wishlist: game.wishlist_of(name).to_vec(),
+            })
+            .collect();
+        PickedResponse { names, recipients }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recipient {
     pub name: String,
+    pub wishlist: Vec<WishlistItem>,
+}
+
+// Both sides of a player's anonymous threads.
+#[derive(Serialize, Deserialize)]
+pub struct Conversations {
+    pub recipients: Vec<Thread>,
+    pub santas: Vec<Thread>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewMessage {
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Wishlist {
+    pub items: Vec<WishlistItem>,
 }
 
 impl Responder for PickedResponse {
@@ -34,6 +322,76 @@ impl Responder for PickedResponse {
     }
 }
 
+impl Responder for Conversations {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for Wishlist {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for DrawSettings {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for GiftSettings {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for WithdrawalReport {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for LateJoin {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for DrawVerification {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
 impl Responder for SantaGameInfo {
     type Body = actix_web::body::BoxBody;
     fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
@@ -52,3 +410,91 @@ impl Responder for Players {
             .body(body)
     }
 }
+impl Responder for Exclusions {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+impl Responder for History {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+impl Responder for WebhookInfo {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for WebhookList {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for WebhookDeliveries {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for EmailReport {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+
+impl Responder for PlayerTokens {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+impl Responder for NewGame {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+    }
+}
+impl Responder for AddPlayersReport {
+    type Body = actix_web::body::BoxBody;
+    fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        let body = serde_json::to_string(&self).unwrap();
+        let mut response = if self.applied {
+            actix_web::HttpResponse::Ok()
+        } else {
+            actix_web::HttpResponse::UnprocessableEntity()
+        };
+        response.content_type("application/json").body(body)
+    }
+}
@@ -0,0 +1,893 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::error::CustomError;
+use super::storage::StateFile;
+use super::webhooks::{self, GameEvent};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rusqlite::{params, Connection, OptionalExtension};
+use secret_santa::{Exclusion, Message, Player, SecretSantaGame, Webhook, WishlistItem};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+// Anyone can open a room, so a server only holds this many unless configured.
+pub const MAX_GAMES: usize = 1_000;
+
+pub trait GameRepository: Send + Sync {
+    fn create(&self, game_id: &str, admin_token: &str) -> Result<(), String>;
+    fn game_ids(&self) -> Result<Vec<String>, String>;
+    fn admin_token(&self, game_id: &str) -> Result<Option<String>, String>;
+    fn load(&self, game_id: &str) -> Result<Option<SecretSantaGame>, String>;
+    fn save(&self, game_id: &str, game: &SecretSantaGame) -> Result<(), String>;
+}
+
+// Route handlers go through the store, which serializes load/modify/save
+// cycles so two requests never overwrite each other's changes.
+pub struct GameStore {
+    repository: Box<dyn GameRepository>,
+    lock: Mutex<()>,
+    changes: broadcast::Sender<String>,
+    events: broadcast::Sender<GameEvent>,
+    max_games: usize,
+}
+
+impl GameStore {
+    pub fn new(repository: impl GameRepository + 'static) -> Self {
+        let (changes, _) = broadcast::channel(64);
+        let (events, _) = broadcast::channel(256);
+        GameStore {
+            repository: Box::new(repository),
+            lock: Mutex::new(()),
+            changes,
+            events,
+            max_games: MAX_GAMES,
+        }
+    }
+
+    pub fn with_max_games(self, max_games: usize) -> Self {
+        GameStore { max_games, ..self }
+    }
+
+    // Receives the id of every game right after it is saved.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.changes.subscribe()
+    }
+
+    // Receives what happened in games that have webhooks, once it is saved.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<GameEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn create_game(&self, admin_token: &str) -> Result<String, CustomError> {
+        let _guard = self.lock.lock().unwrap();
+        let games = self.repository.game_ids().map_err(storage_error)?.len();
+        if games >= self.max_games {
+            return Err(CustomError::TooManyGames {
+                max: self.max_games,
+            });
+        }
+        let game_id = generate_game_id();
+        self.repository
+            .create(&game_id, admin_token)
+            .map_err(storage_error)?;
+        Ok(game_id)
+    }
+
+    pub fn game_ids(&self) -> Result<Vec<String>, CustomError> {
+        let _guard = self.lock.lock().unwrap();
+        self.repository.game_ids().map_err(storage_error)
+    }
+
+    pub fn admin_token(&self, game_id: &str) -> Result<String, CustomError> {
+        let _guard = self.lock.lock().unwrap();
+        self.repository
+            .admin_token(game_id)
+            .map_err(storage_error)?
+            .ok_or(CustomError::GameNotFound)
+    }
+
+    pub fn read<T>(
+        &self,
+        game_id: &str,
+        f: impl FnOnce(&SecretSantaGame) -> T,
+    ) -> Result<T, CustomError> {
+        let _guard = self.lock.lock().unwrap();
+        let game = self.load(game_id)?;
+        Ok(f(&game))
+    }
+
+    // A change that fails is dropped, the game is neither saved nor announced.
+    pub fn update<T, E>(
+        &self,
+        game_id: &str,
+        f: impl FnOnce(&mut SecretSantaGame) -> Result<T, E>,
+    ) -> Result<Result<T, E>, CustomError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut game = self.load(game_id)?;
+        let before = (!game.webhooks.is_empty()).then(|| game.clone());
+        let result = f(&mut game);
+        if result.is_err() {
+            return Ok(result);
+        }
+        self.repository
+            .save(game_id, &game)
+            .map_err(storage_error)?;
+        // nobody listening is not an error
+        let _ = self.changes.send(game_id.to_string());
+        for (event, data) in before.map_or(vec![], |before| webhooks::game_events(&before, &game)) {
+            let _ = self.events.send(GameEvent {
+                game_id: game_id.to_string(),
+                event,
+                data,
+                webhooks: game
+                    .webhooks
+                    .iter()
+                    .filter(|webhook| webhook.wants(event))
+                    .cloned()
+                    .collect(),
+            });
+        }
+        Ok(result)
+    }
+
+    // Moves every game whose dates have come, returns the ids of those that changed.
+    pub fn advance_schedules(&self, now: u64) -> Result<Vec<String>, CustomError> {
+        let mut advanced = vec![];
+        for game_id in self.game_ids()? {
+            let due = self.read(&game_id, |game| game.scheduled_status(now) != game.status)?;
+            // a game the schedule leaves as it was is not saved again
+            let advance =
+                |game: &mut SecretSantaGame| game.advance_schedule(now).then_some(()).ok_or(());
+            if due && self.update(&game_id, advance)?.is_ok() {
+                advanced.push(game_id);
+            }
+        }
+        Ok(advanced)
+    }
+
+    fn load(&self, game_id: &str) -> Result<SecretSantaGame, CustomError> {
+        self.repository
+            .load(game_id)
+            .map_err(storage_error)?
+            .ok_or(CustomError::GameNotFound)
+    }
+}
+
+fn generate_game_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .collect()
+}
+
+fn storage_error(error: String) -> CustomError {
+    log::error!("Storage error: {}", error);
+    CustomError::StorageError { error }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Room {
+    pub admin_token: String,
+    pub game: SecretSantaGame,
+}
+
+#[derive(Default)]
+pub struct InMemoryRepository {
+    rooms: Mutex<HashMap<String, Room>>,
+    state_file: Option<StateFile>,
+}
+
+impl InMemoryRepository {
+    pub fn with_state_file(rooms: HashMap<String, Room>, state_file: StateFile) -> Self {
+        InMemoryRepository {
+            rooms: Mutex::new(rooms),
+            state_file: Some(state_file),
+        }
+    }
+
+    // a failed snapshot should not fail the request, the game keeps running in memory
+    fn snapshot(&self, rooms: &HashMap<String, Room>) {
+        if let Some(state_file) = &self.state_file {
+            if let Err(err) = state_file.save(rooms) {
+                log::error!("Failed to save game state: {}", err);
+            }
+        }
+    }
+}
+
+impl GameRepository for InMemoryRepository {
+    fn create(&self, game_id: &str, admin_token: &str) -> Result<(), String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        if rooms.contains_key(game_id) {
+            return Err(format!("Game {} already exists", game_id));
+        }
+        rooms.insert(
+            game_id.to_string(),
+            Room {
+                admin_token: admin_token.to_string(),
+                game: SecretSantaGame::default(),
+            },
+        );
+        self.snapshot(&rooms);
+        Ok(())
+    }
+
+    fn game_ids(&self) -> Result<Vec<String>, String> {
+        let mut game_ids: Vec<String> = self.rooms.lock().unwrap().keys().cloned().collect();
+        game_ids.sort();
+        Ok(game_ids)
+    }
+
+    fn admin_token(&self, game_id: &str) -> Result<Option<String>, String> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(rooms.get(game_id).map(|room| room.admin_token.clone()))
+    }
+
+    fn load(&self, game_id: &str) -> Result<Option<SecretSantaGame>, String> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(rooms.get(game_id).map(|room| room.game.clone()))
+    }
+
+    fn save(&self, game_id: &str, game: &SecretSantaGame) -> Result<(), String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        match rooms.get_mut(game_id) {
+            Some(room) => room.game = game.clone(),
+            None => return Err(format!("Game {} not found", game_id)),
+        }
+        self.snapshot(&rooms);
+        Ok(())
+    }
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id TEXT PRIMARY KEY,
+        admin_token TEXT NOT NULL,
+        status TEXT NOT NULL,
+        history_policy TEXT NOT NULL,
+        history TEXT NOT NULL,
+        name_rules TEXT NOT NULL,
+        audit TEXT NOT NULL,
+        draw_strategy TEXT NOT NULL,
+        gifts_per_person INTEGER NOT NULL,
+        schedule TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS players (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        name TEXT NOT NULL,
+        display_name TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        group_name TEXT,
+        token TEXT,
+        has_picked INTEGER NOT NULL,
+        revealed_at TEXT,
+        email TEXT,
+        PRIMARY KEY (game_id, name)
+    );
+    CREATE TABLE IF NOT EXISTS assignments (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        giver TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        receiver TEXT NOT NULL,
+        PRIMARY KEY (game_id, giver, position)
+    );
+    CREATE TABLE IF NOT EXISTS wishlist_items (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        player TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        link TEXT,
+        price_hint TEXT,
+        notes TEXT,
+        PRIMARY KEY (game_id, player, position)
+    );
+    CREATE TABLE IF NOT EXISTS messages (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        position INTEGER NOT NULL,
+        giver TEXT NOT NULL,
+        receiver TEXT NOT NULL,
+        from_santa INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        sent_at INTEGER NOT NULL,
+        PRIMARY KEY (game_id, position)
+    );
+    CREATE TABLE IF NOT EXISTS webhooks (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        position INTEGER NOT NULL,
+        id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        secret TEXT NOT NULL,
+        events TEXT NOT NULL,
+        PRIMARY KEY (game_id, position)
+    );
+    CREATE TABLE IF NOT EXISTS exclusions (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        position INTEGER NOT NULL,
+        giver TEXT NOT NULL,
+        receiver TEXT NOT NULL,
+        symmetric INTEGER NOT NULL,
+        PRIMARY KEY (game_id, position)
+    );
+";
+
+pub struct SqliteRepository {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteRepository {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let connection = Connection::open(path).map_err(|e| e.to_string())?;
+        Self::with_connection(connection)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self, String> {
+        let connection = Connection::open_in_memory().map_err(|e| e.to_string())?;
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, String> {
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| e.to_string())?;
+        Ok(SqliteRepository {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn create_game(
+        connection: &Connection,
+        game_id: &str,
+        admin_token: &str,
+    ) -> rusqlite::Result<()> {
+        let game = SecretSantaGame::default();
+        connection.execute(
+            "INSERT INTO games
+             (id, admin_token, status, history_policy, history, name_rules, audit, draw_strategy,
+              gifts_per_person, schedule, event)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                game_id,
+                admin_token,
+                to_json(&game.status)?,
+                to_json(&game.history_policy)?,
+                to_json(&game.history)?,
+                to_json(&game.name_rules)?,
+                to_json(&game.audit)?,
+                to_json(&game.draw_strategy)?,
+                game.gifts_per_person,
+                to_json(&game.schedule)?,
+                to_json(&game.event)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn load_game(
+        connection: &Connection,
+        game_id: &str,
+    ) -> rusqlite::Result<Option<SecretSantaGame>> {
+        type Stored = (
+            String,
+            String,
+            String,
+            String,
+            String,
+            String,
+            usize,
+            String,
+            String,
+        );
+        let stored: Option<Stored> = connection
+            .query_row(
+                "SELECT status, history_policy, history, name_rules, audit, draw_strategy,
+                 gifts_per_person, schedule, event FROM games WHERE id = ?1",
+                [game_id],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                        row.get(7)?,
+                        row.get(8)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((
+            status,
+            history_policy,
+            history,
+            name_rules,
+            audit,
+            draw_strategy,
+            gifts_per_person,
+            schedule,
+            event,
+        )) = stored
+        else {
+            return Ok(None);
+        };
+        let mut game = SecretSantaGame {
+            status: from_json(&status)?,
+            history_policy: from_json(&history_policy)?,
+            history: from_json(&history)?,
+            name_rules: from_json(&name_rules)?,
+            audit: from_json(&audit)?,
+            draw_strategy: from_json(&draw_strategy)?,
+            gifts_per_person,
+            schedule: from_json(&schedule)?,
+            event: from_json(&event)?,
+            ..Default::default()
+        };
+
+        let mut assignments: HashMap<String, Vec<String>> = HashMap::new();
+        let mut statement = connection.prepare(
+            "SELECT giver, receiver FROM assignments WHERE game_id = ?1 ORDER BY giver, position",
+        )?;
+        let rows = statement.query_map([game_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (giver, receiver) = row?;
+            assignments.entry(giver).or_default().push(receiver);
+        }
+
+        let mut wishlists: HashMap<String, Vec<WishlistItem>> = HashMap::new();
+        let mut statement = connection.prepare(
+            "SELECT player, title, link, price_hint, notes FROM wishlist_items
+             WHERE game_id = ?1 ORDER BY player, position",
+        )?;
+        let rows = statement.query_map([game_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                WishlistItem {
+                    title: row.get(1)?,
+                    link: row.get(2)?,
+                    price_hint: row.get(3)?,
+                    notes: row.get(4)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (player, item) = row?;
+            wishlists.entry(player).or_default().push(item);
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT name, display_name, group_name, token, has_picked, email FROM players
+             WHERE game_id = ?1 ORDER BY position",
+        )?;
+        let rows = statement.query_map([game_id], |row| {
+            let name: String = row.get(0)?;
+            let display_name: String = row.get(1)?;
+            let mut player = Player::restore(
+                &name,
+                &display_name,
+                row.get(2)?,
+                assignments.remove(&name).unwrap_or_default(),
+                row.get(3)?,
+                row.get(4)?,
+            );
+            player.wishlist = wishlists.remove(&name).unwrap_or_default();
+            player.email = row.get(5)?;
+            Ok(player)
+        })?;
+        game.players = rows.collect::<rusqlite::Result<_>>()?;
+
+        let mut statement = connection.prepare(
+            "SELECT giver, receiver, symmetric FROM exclusions
+             WHERE game_id = ?1 ORDER BY position",
+        )?;
+        let rows = statement.query_map([game_id], |row| {
+            Ok(Exclusion {
+                giver: row.get(0)?,
+                receiver: row.get(1)?,
+                symmetric: row.get(2)?,
+            })
+        })?;
+        game.exclusions = rows.collect::<rusqlite::Result<_>>()?;
+
+        let mut statement = connection.prepare(
+            "SELECT giver, receiver, from_santa, text, sent_at FROM messages
+             WHERE game_id = ?1 ORDER BY position",
+        )?;
+        let rows = statement.query_map([game_id], |row| {
+            Ok(Message {
+                giver: row.get(0)?,
+                receiver: row.get(1)?,
+                from_santa: row.get(2)?,
+                text: row.get(3)?,
+                sent_at: row.get(4)?,
+            })
+        })?;
+        game.messages = rows.collect::<rusqlite::Result<_>>()?;
+
+        let mut statement = connection.prepare(
+            "SELECT id, url, secret, events FROM webhooks WHERE game_id = ?1 ORDER BY position",
+        )?;
+        let rows = statement.query_map([game_id], |row| {
+            Ok(Webhook {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                secret: row.get(2)?,
+                events: from_json(&row.get::<_, String>(3)?)?,
+            })
+        })?;
+        game.webhooks = rows.collect::<rusqlite::Result<_>>()?;
+        Ok(Some(game))
+    }
+
+    fn save_game(
+        connection: &mut Connection,
+        game_id: &str,
+        game: &SecretSantaGame,
+    ) -> rusqlite::Result<()> {
+        let transaction = connection.transaction()?;
+        let updated = transaction.execute(
+            "UPDATE games SET status = ?2, history_policy = ?3, history = ?4, name_rules = ?5,
+             audit = ?6, draw_strategy = ?7, gifts_per_person = ?8, schedule = ?9,
+             event = ?10 WHERE id = ?1",
+            params![
+                game_id,
+                to_json(&game.status)?,
+                to_json(&game.history_policy)?,
+                to_json(&game.history)?,
+                to_json(&game.name_rules)?,
+                to_json(&game.audit)?,
+                to_json(&game.draw_strategy)?,
+                game.gifts_per_person,
+                to_json(&game.schedule)?,
+                to_json(&game.event)?
+            ],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        // keep the moment each player revealed their recipient across saves
+        let mut revealed_at: HashMap<String, String> = HashMap::new();
+        {
+            let mut statement = transaction.prepare(
+                "SELECT name, revealed_at FROM players
+                 WHERE game_id = ?1 AND revealed_at IS NOT NULL",
+            )?;
+            let rows = statement.query_map([game_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            for row in rows {
+                let (name, timestamp) = row?;
+                revealed_at.insert(name, timestamp);
+            }
+        }
+
+        transaction.execute("DELETE FROM players WHERE game_id = ?1", [game_id])?;
+        transaction.execute("DELETE FROM assignments WHERE game_id = ?1", [game_id])?;
+        transaction.execute("DELETE FROM wishlist_items WHERE game_id = ?1", [game_id])?;
+        transaction.execute("DELETE FROM exclusions WHERE game_id = ?1", [game_id])?;
+        transaction.execute("DELETE FROM messages WHERE game_id = ?1", [game_id])?;
+        transaction.execute("DELETE FROM webhooks WHERE game_id = ?1", [game_id])?;
+        for (position, player) in game.players.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO players
+                 (game_id, name, display_name, position, group_name, token, has_picked, revealed_at, email)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, CASE WHEN ?7 THEN COALESCE(?8, CURRENT_TIMESTAMP) END, ?9)",
+                params![
+                    game_id,
+                    player.name,
+                    player.display_name(),
+                    position,
+                    player.group,
+                    player.token(),
+                    player.has_picked,
+                    revealed_at.get(&player.name),
+                    player.email
+                ],
+            )?;
+            for (position, picked) in player.picked().iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO assignments (game_id, giver, position, receiver)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![game_id, player.name, position, picked],
+                )?;
+            }
+            for (position, item) in player.wishlist.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO wishlist_items
+                     (game_id, player, position, title, link, price_hint, notes)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        game_id,
+                        player.name,
+                        position,
+                        item.title,
+                        item.link,
+                        item.price_hint,
+                        item.notes
+                    ],
+                )?;
+            }
+        }
+        for (position, exclusion) in game.exclusions.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO exclusions (game_id, position, giver, receiver, symmetric)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    game_id,
+                    position,
+                    exclusion.giver,
+                    exclusion.receiver,
+                    exclusion.symmetric
+                ],
+            )?;
+        }
+        for (position, message) in game.messages.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO messages (game_id, position, giver, receiver, from_santa, text, sent_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    game_id,
+                    position,
+                    message.giver,
+                    message.receiver,
+                    message.from_santa,
+                    message.text,
+                    message.sent_at
+                ],
+            )?;
+        }
+        for (position, webhook) in game.webhooks.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO webhooks (game_id, position, id, url, secret, events)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    game_id,
+                    position,
+                    webhook.id,
+                    webhook.url,
+                    webhook.secret,
+                    to_json(&webhook.events)?
+                ],
+            )?;
+        }
+        transaction.commit()
+    }
+}
+
+impl GameRepository for SqliteRepository {
+    fn create(&self, game_id: &str, admin_token: &str) -> Result<(), String> {
+        let connection = self.connection.lock().unwrap();
+        Self::create_game(&connection, game_id, admin_token).map_err(|e| e.to_string())
+    }
+
+    fn game_ids(&self) -> Result<Vec<String>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id FROM games ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())
+    }
+
+    fn admin_token(&self, game_id: &str) -> Result<Option<String>, String> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT admin_token FROM games WHERE id = ?1",
+                [game_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    fn load(&self, game_id: &str) -> Result<Option<SecretSantaGame>, String> {
+        let connection = self.connection.lock().unwrap();
+        Self::load_game(&connection, game_id).map_err(|e| e.to_string())
+    }
+
+    fn save(&self, game_id: &str, game: &SecretSantaGame) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        Self::save_game(&mut connection, game_id, game).map_err(|e| e.to_string())
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secret_santa::{DrawStrategy, EventDetails, GameStatus, HistoryPolicy, Schedule};
+
+    #[test]
+    fn sqlite_round_trip() {
+        let repository = SqliteRepository::in_memory().unwrap();
+        assert!(repository.load("family").unwrap().is_none());
+        repository.create("family", "organizer-secret").unwrap();
+        assert_eq!(repository.load("family").unwrap().unwrap().players.len(), 0);
+
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::with_group("Player1", "Family"))
+            .unwrap();
+        game.add_player(Player::new("Player2").with_email("player2@example.com"))
+            .unwrap();
+        game.add_player(Player::new("Player3")).unwrap();
+        game.add_player(Player::new("Player4")).unwrap();
+        game.add_exclusion(Exclusion::symmetric("Player2", "Player3"))
+            .unwrap();
+        game.set_history(vec![], HistoryPolicy::AvoidLastYears(2))
+            .unwrap();
+        game.set_draw_strategy(DrawStrategy::AnyDerangement)
+            .unwrap();
+        game.set_gifts_per_person(2).unwrap();
+        game.set_event(EventDetails {
+            title: Some("Family".to_string()),
+            budget_max: Some(40),
+            ..Default::default()
+        })
+        .unwrap();
+        game.set_schedule(Schedule {
+            reveals_at: Some(1_000),
+            exchange_at: Some(4_102_444_800),
+        })
+        .unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let token = game.players[0].token().unwrap().to_string();
+        game.player_pick_by_token(&token).unwrap();
+        game.set_wishlist(
+            &token,
+            vec![
+                WishlistItem {
+                    notes: Some("size M".to_string()),
+                    ..WishlistItem::new("Sweater")
+                },
+                WishlistItem::new("Book"),
+            ],
+        )
+        .unwrap();
+        let recipient = game.players[0].picked()[0].clone();
+        game.message_recipient(&token, &recipient, "Any allergies?", 1_000)
+            .unwrap();
+        game.add_webhook("https://example.com/hook", vec!["game_started".to_string()])
+            .unwrap();
+        repository.save("family", &game).unwrap();
+
+        let restored = repository.load("family").unwrap().unwrap();
+        assert_eq!(restored.status, GameStatus::Revealing);
+        assert_eq!(restored.players, game.players);
+        assert_eq!(restored.exclusions, game.exclusions);
+        assert_eq!(restored.history_policy, HistoryPolicy::AvoidLastYears(2));
+        assert_eq!(restored.draw_strategy, DrawStrategy::AnyDerangement);
+        assert_eq!(restored.gifts_per_person, 2);
+        assert_eq!(restored.schedule, game.schedule);
+        assert_eq!(restored.event, game.event);
+        assert_eq!(restored.messages, game.messages);
+        assert_eq!(restored.webhooks, game.webhooks);
+        assert_eq!(restored.audit, game.audit);
+        assert_eq!(
+            repository.admin_token("family").unwrap(),
+            Some("organizer-secret".to_string())
+        );
+    }
+
+    #[test]
+    fn sqlite_keeps_rooms_isolated() {
+        let repository = SqliteRepository::in_memory().unwrap();
+        repository.create("family", "family-secret").unwrap();
+        repository.create("office", "office-secret").unwrap();
+
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        repository.save("family", &game).unwrap();
+
+        assert_eq!(repository.game_ids().unwrap(), vec!["family", "office"]);
+        assert_eq!(repository.load("family").unwrap().unwrap().players.len(), 1);
+        assert!(repository
+            .load("office")
+            .unwrap()
+            .unwrap()
+            .players
+            .is_empty());
+        assert!(repository.save("unknown", &game).is_err());
+    }
+
+    #[test]
+    fn sqlite_records_reveal_timestamps() {
+        let repository = SqliteRepository::in_memory().unwrap();
+        repository.create("family", "organizer-secret").unwrap();
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        game.player_pick("Player1").unwrap();
+        repository.save("family", &game).unwrap();
+        repository.save("family", &game).unwrap();
+
+        let connection = repository.connection.lock().unwrap();
+        let revealed: Vec<(String, Option<String>)> = connection
+            .prepare("SELECT name, revealed_at FROM players ORDER BY name")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(revealed[0].1.is_some());
+        assert!(revealed[1].1.is_none());
+    }
+
+    #[test]
+    fn failed_update_is_not_saved() {
+        let store = GameStore::new(SqliteRepository::in_memory().unwrap());
+        let game_id = store.create_game("family-secret").unwrap();
+        let mut changes = store.subscribe();
+        let result = store
+            .update(&game_id, |game| {
+                game.add_player(Player::new("Player1"))?;
+                game.add_player(Player::new("player1"))
+            })
+            .unwrap();
+        assert!(result.is_err());
+        assert!(store
+            .read(&game_id, |game| game.players.is_empty())
+            .unwrap());
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn store_advances_scheduled_games() {
+        let store = GameStore::new(SqliteRepository::in_memory().unwrap());
+        let family = store.create_game("family-secret").unwrap();
+        let office = store.create_game("office-secret").unwrap();
+        for game_id in [&family, &office] {
+            store
+                .update(game_id, |game| {
+                    for name in ["Player1", "Player2", "Player3"] {
+                        game.add_player(Player::new(name))?;
+                    }
+                    game.start_game(None)?;
+                    game.open_reveals()
+                })
+                .unwrap()
+                .unwrap();
+        }
+        store
+            .update(&family, |game| {
+                game.set_schedule(Schedule {
+                    reveals_at: None,
+                    exchange_at: Some(1_000),
+                })
+            })
+            .unwrap()
+            .unwrap();
+
+        let mut changes = store.subscribe();
+        assert_eq!(store.advance_schedules(999).unwrap(), Vec::<String>::new());
+        assert_eq!(
+            store.advance_schedules(1_000).unwrap(),
+            vec![family.clone()]
+        );
+        assert_eq!(changes.try_recv().unwrap(), family);
+        assert!(changes.try_recv().is_err());
+        let statuses = [&family, &office].map(|id| store.read(id, |game| game.status).unwrap());
+        assert_eq!(statuses, [GameStatus::Closed, GameStatus::Revealing]);
+    }
+}
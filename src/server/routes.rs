@@ -1,142 +1,848 @@
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::future::ready;
+use std::time::Duration;
 
+use super::auth::Organizer;
 use super::error::CustomError;
-use super::models::{PickedResponse, PlayerInfo, Players, SantaGameInfo};
-use actix_web::{web, HttpResponse};
-use secret_santa::{Player, SecretSantaGame};
-
-async fn index(game_data: web::Data<Arc<Mutex<SecretSantaGame>>>) -> SantaGameInfo {
-    let game = game_data.lock().unwrap();
-    SantaGameInfo {
-        status: game.status.clone(),
-        players: game
-            .players
-            .iter()
-            .map(|player| PlayerInfo {
-                name: player.name.clone(),
-                has_picked: player.has_picked,
-            })
-            .collect(),
-    }
+use super::lobby::{self, Lobby};
+use super::mailer::{self, Mailer};
+use super::models::{
+    AddPlayersReport, Conversations, DrawSettings, DrawVerification, EmailReport, Exclusions,
+    GiftSettings, History, LateJoin, LatePlayer, NewGame, NewMessage, NewWebhook, NotifyPlayers,
+    PickedResponse, PlayerReport, PlayerTokens, Players, SantaGameInfo, StartGame,
+    WebhookDeliveries, WebhookInfo, WebhookList, Wishlist, Withdrawal, WithdrawalReport,
+};
+use super::repository::GameStore;
+use super::webhooks::WebhookDispatcher;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use futures_util::{stream, StreamExt};
+use secret_santa::{
+    generate_token, unix_now, EventDetails, GameError, NameRules, Player, Schedule, SecretSantaGame,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+// idle proxies and tunnels drop silent connections, so ping now and then
+const EVENTS_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// The body is optional, `{"reveals_at": ..., "exchange_at": ...}` schedules the game.
+async fn create_game(
+    schedule: Option<web::Json<Schedule>>,
+    store: web::Data<GameStore>,
+) -> Result<NewGame, CustomError> {
+    let schedule = schedule.map(web::Json::into_inner).unwrap_or_default();
+    schedule.validate()?;
+    let admin_token = generate_token();
+    let id = store.create_game(&admin_token)?;
+    store.update(&id, |game| game.set_schedule(schedule))??;
+    Ok(NewGame { id, admin_token })
+}
+
+async fn index(
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    store.read(&game_id, SantaGameInfo::new)
+}
+
+// Streams the same payload as the index route, once on connect and again
+// every time the game is saved.
+async fn events(
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<HttpResponse, CustomError> {
+    let game_id = game_id.into_inner();
+    let receiver = store.subscribe();
+    let current = store.read(&game_id, SantaGameInfo::new)?;
+
+    let updates = stream::unfold(receiver, move |mut receiver| {
+        let store = store.clone();
+        let game_id = game_id.clone();
+        async move {
+            loop {
+                match tokio::time::timeout(EVENTS_KEEP_ALIVE, receiver.recv()).await {
+                    Err(_) => {
+                        return Some((web::Bytes::from_static(b": keep-alive\n\n"), receiver))
+                    }
+                    Ok(Ok(changed)) if changed != game_id => continue,
+                    Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => {
+                        let info = store.read(&game_id, SantaGameInfo::new).ok()?;
+                        return Some((event_message(&info), receiver));
+                    }
+                    Ok(Err(RecvError::Closed)) => return None,
+                }
+            }
+        }
+    });
+    let body = stream::once(ready(event_message(&current)))
+        .chain(updates)
+        .map(Ok::<_, actix_web::Error>);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .streaming(body))
+}
+
+async fn join_lobby(
+    req: HttpRequest,
+    body: web::Payload,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+    lobby: web::Data<Lobby>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let game_id = game_id.into_inner();
+    // refuse unknown games before upgrading the connection
+    store.read(&game_id, |_| ())?;
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(lobby::run(session, stream, game_id, store, lobby));
+    Ok(response)
+}
+
+fn event_message(info: &SantaGameInfo) -> web::Bytes {
+    let data = serde_json::to_string(info).unwrap();
+    web::Bytes::from(format!("data: {}\n\n", data))
 }
 
 async fn add_players(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
     players: web::Json<Players>,
-    game_data: web::Data<Arc<Mutex<SecretSantaGame>>>,
-) -> Result<Players, CustomError> {
-    let mut game = game_data.lock().unwrap();
-    for player in players.names.iter() {
-        let result = game.add_player(Player::new(player));
-        if result.is_err() {
-            return Err(CustomError::ValidationError {
-                error: result.unwrap_err(),
-            });
+    store: web::Data<GameStore>,
+) -> Result<AddPlayersReport, CustomError> {
+    let players: Vec<Player> = players
+        .names
+        .iter()
+        .map(|name| {
+            let player = match players.groups.get(name) {
+                Some(group) if !group.trim().is_empty() => Player::with_group(name, group.trim()),
+                _ => Player::new(name),
+            };
+            match players.emails.get(name) {
+                Some(email) if !email.trim().is_empty() => player.with_email(email),
+                _ => player,
+            }
+        })
+        .collect();
+    // a rejected batch comes back as an error, so the game is not saved
+    let checked = store.update(&game_id, |game| {
+        let checks = game.check_players(&players);
+        let similar_names = game.similar_names(&players);
+        if checks.iter().all(Result::is_ok) && game.add_players(players.clone()).is_ok() {
+            let added = &game.players[game.players.len() - players.len()..];
+            let tokens = added
+                .iter()
+                .map(|p| p.token().map(str::to_string))
+                .collect();
+            Ok((checks, similar_names, tokens))
+        } else {
+            Err((checks, similar_names, vec![None; players.len()]))
         }
+    })?;
+    let applied = checked.is_ok();
+    let (checks, similar_names, tokens) = checked.unwrap_or_else(|rejected| rejected);
+    // no name can join once registration is over, so the whole batch fails
+    if let Some(Err(error @ (GameError::GameLocked | GameError::RegistrationClosed))) =
+        checks.first()
+    {
+        return Err(error.clone().into());
+    }
+
+    let players = players
+        .into_iter()
+        .zip(checks)
+        .zip(similar_names)
+        .zip(tokens)
+        .map(|(((player, check), similar_to), token)| PlayerReport {
+            warning: (!similar_to.is_empty()).then(|| {
+                format!(
+                    "{} looks like {}, check it is not the same person",
+                    player.display_name(),
+                    similar_to.join(", ")
+                )
+            }),
+            name: player.name,
+            accepted: check.is_ok(),
+            error: check.as_ref().err().map(|e| e.to_string()),
+            code: check.err().map(|e| e.code().to_string()),
+            similar_to,
+            token,
+        })
+        .collect();
+    Ok(AddPlayersReport {
+        applied,
+        players,
+        error: (!applied).then(|| "Some players were rejected, none was added".to_string()),
+        code: (!applied).then(|| "players_rejected".to_string()),
+    })
+}
+
+fn new_player(player: &LatePlayer) -> Player {
+    let new = match player.group.as_deref().map(str::trim) {
+        Some(group) if !group.is_empty() => Player::with_group(&player.name, group),
+        _ => Player::new(&player.name),
+    };
+    match player.email.as_deref().map(str::trim) {
+        Some(email) if !email.is_empty() => new.with_email(email),
+        _ => new,
     }
-    Ok(players.into_inner())
 }
 
+fn player_token(game: &SecretSantaGame, name: &str) -> Option<String> {
+    game.players
+        .iter()
+        .find(|p| p.name == name)
+        .and_then(|p| p.token())
+        .map(str::to_string)
+}
+
+async fn add_late_player(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    player: web::Json<LatePlayer>,
+    store: web::Data<GameStore>,
+) -> Result<LateJoin, CustomError> {
+    let player = new_player(&player);
+    let name = player.name.clone();
+    let (reassigned, token) = store.update(&game_id, |game| {
+        let reassigned = game.add_late_player(player)?;
+        Ok::<_, GameError>((reassigned, player_token(game, &name).unwrap_or_default()))
+    })??;
+    Ok(LateJoin {
+        name,
+        token,
+        reassigned,
+    })
+}
+
+async fn withdraw_player(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    withdrawal: web::Json<Withdrawal>,
+    store: web::Data<GameStore>,
+) -> Result<WithdrawalReport, CustomError> {
+    let replacement = withdrawal.replacement.as_ref().map(new_player);
+    let replacement_name = replacement.as_ref().map(|p| p.name.clone());
+    let (repair, token) = store.update(&game_id, |game| {
+        let repair = game.withdraw_player(&withdrawal.name, replacement)?;
+        let token = replacement_name.and_then(|name| player_token(game, &name));
+        Ok::<_, GameError>((repair, token))
+    })??;
+    Ok(WithdrawalReport {
+        reassigned: repair.reassigned,
+        reveal_again: repair.reveal_again,
+        token,
+    })
+}
+
+// Composes the emails under the lock, sending waits for the SMTP relay.
+async fn email_recipients(
+    game_id: &str,
+    store: &GameStore,
+    mailer: &Mailer,
+    language: Option<&str>,
+) -> Result<EmailReport, CustomError> {
+    let (emails, without_email) = store.read(game_id, |game| {
+        game.check_reveals_open(unix_now())?;
+        Ok::<_, GameError>((
+            mailer::recipient_emails(
+                game_id,
+                game,
+                language.unwrap_or_default(),
+                mailer.link.as_deref(),
+            ),
+            mailer::players_without_email(game),
+        ))
+    })??;
+    Ok(mailer.send_all(emails, without_email).await)
+}
+
+// The body is optional, `{"seed": "..."}` goes into the draw seed. Givers
+// with an email address get their recipients when reveals open right away.
 async fn start_game(
-    game_data: web::Data<Arc<Mutex<SecretSantaGame>>>,
-) -> Result<HttpResponse, CustomError> {
-    let mut game = game_data.lock().unwrap();
-    let result = game.start_game();
-    if result.is_err() {
-        return Err(CustomError::ValidationError {
-            error: result.unwrap_err(),
-        });
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    options: Option<web::Json<StartGame>>,
+    store: web::Data<GameStore>,
+    mailer: Option<web::Data<Mailer>>,
+) -> Result<PlayerTokens, CustomError> {
+    let options = options.map(web::Json::into_inner).unwrap_or_default();
+    let mut tokens = store.update(&game_id, |game| {
+        game.start_game(options.seed)?;
+        if !options.hold_reveals {
+            game.open_reveals()?;
+        }
+        Ok::<_, GameError>(player_tokens(game))
+    })??;
+    if let Some(mailer) = mailer {
+        let language = options.language.as_deref();
+        match email_recipients(&game_id, &store, &mailer, language).await {
+            Ok(report) => tokens.emails = Some(report),
+            Err(CustomError::GameError { .. }) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(tokens)
+}
+
+// Emails givers their recipients once reveals are open, for games started
+// with held or scheduled reveals.
+async fn notify_players(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    options: Option<web::Json<NotifyPlayers>>,
+    store: web::Data<GameStore>,
+    mailer: Option<web::Data<Mailer>>,
+) -> Result<EmailReport, CustomError> {
+    let mailer = mailer.ok_or(CustomError::EmailDisabled)?;
+    let options = options.map(web::Json::into_inner).unwrap_or_default();
+    email_recipients(&game_id, &store, &mailer, options.language.as_deref()).await
+}
+
+fn change_phase(
+    game_id: &str,
+    store: &GameStore,
+    change: fn(&mut SecretSantaGame) -> Result<(), GameError>,
+) -> Result<SantaGameInfo, CustomError> {
+    let info = store.update(game_id, |game| {
+        change(game).map(|_| SantaGameInfo::new(game))
+    })??;
+    Ok(info)
+}
+
+async fn close_registration(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    change_phase(&game_id, &store, SecretSantaGame::close_registration)
+}
+
+async fn reopen_registration(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    change_phase(&game_id, &store, SecretSantaGame::reopen_registration)
+}
+
+async fn open_reveals(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    change_phase(&game_id, &store, SecretSantaGame::open_reveals)
+}
+
+async fn close_exchange(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    change_phase(&game_id, &store, SecretSantaGame::close_exchange)
+}
+
+async fn archive_game(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    change_phase(&game_id, &store, SecretSantaGame::archive)
+}
+
+async fn clear_players(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    change_phase(&game_id, &store, SecretSantaGame::clear_players)
+}
+
+async fn set_draw_strategy(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    settings: web::Json<DrawSettings>,
+    store: web::Data<GameStore>,
+) -> Result<DrawSettings, CustomError> {
+    let settings = settings.into_inner();
+    store.update(&game_id, |game| game.set_draw_strategy(settings.strategy))??;
+    Ok(settings)
+}
+
+async fn set_gifts_per_person(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    settings: web::Json<GiftSettings>,
+    store: web::Data<GameStore>,
+) -> Result<GiftSettings, CustomError> {
+    let settings = settings.into_inner();
+    store.update(&game_id, |game| {
+        game.set_gifts_per_person(settings.gifts_per_person)
+    })??;
+    Ok(settings)
+}
+
+async fn set_schedule(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    schedule: web::Json<Schedule>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    let info = store.update(&game_id, |game| {
+        game.set_schedule(schedule.into_inner())?;
+        // a date already in the past applies right away
+        game.advance_schedule(secret_santa::unix_now());
+        Ok::<_, GameError>(SantaGameInfo::new(game))
+    })??;
+    Ok(info)
+}
+
+async fn set_event(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    event: web::Json<EventDetails>,
+    store: web::Data<GameStore>,
+) -> Result<SantaGameInfo, CustomError> {
+    let info = store.update(&game_id, |game| {
+        game.set_event(event.into_inner())
+            .map(|_| SantaGameInfo::new(game))
+    })??;
+    Ok(info)
+}
+
+async fn reveal_seed(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<DrawVerification, CustomError> {
+    store.update(&game_id, |game| game.reveal_seed())??;
+    verify_draw(game_id, store).await
+}
+
+async fn verify_draw(
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<DrawVerification, CustomError> {
+    let verification = store.read(&game_id, |game| {
+        let valid = game.verify_draw()?;
+        let audit = game.audit.as_ref().ok_or(GameError::NotStarted)?;
+        Ok::<_, GameError>(DrawVerification {
+            seed: audit.revealed_seed().unwrap_or_default().to_string(),
+            commitment: audit.commitment.clone(),
+            valid,
+            amended: audit.amended,
+        })
+    })??;
+    Ok(verification)
+}
+
+async fn show_player_tokens(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<PlayerTokens, CustomError> {
+    store.read(&game_id, player_tokens)
+}
+
+fn player_tokens(game: &SecretSantaGame) -> PlayerTokens {
+    let tokens = game
+        .players
+        .iter()
+        .filter_map(|p| p.token().map(|token| (p.name.clone(), token.to_string())))
+        .collect();
+    PlayerTokens {
+        tokens,
+        emails: None,
     }
-    Ok(HttpResponse::Ok().json("Game started"))
 }
 
-async fn reset_game(game_data: web::Data<Arc<Mutex<SecretSantaGame>>>) -> HttpResponse {
-    let mut game = game_data.lock().unwrap();
-    game.restart_game();
-    HttpResponse::Ok().json("Game restarted")
+async fn reset_game(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<HttpResponse, CustomError> {
+    store.update(&game_id, |game| game.restart_game())??;
+    Ok(HttpResponse::Ok().json("Game restarted"))
 }
 
 async fn pick_players(
-    player_name: web::Path<String>,
-    game_data: web::Data<Arc<Mutex<SecretSantaGame>>>,
+    path: web::Path<(String, String)>,
+    store: web::Data<GameStore>,
 ) -> Result<PickedResponse, CustomError> {
-    let mut game = game_data.lock().unwrap();
-    let result = game.player_pick(&player_name.into_inner());
-    match result {
-        Ok(player_name) => Ok(PickedResponse { name: player_name }),
-        Err(e) => return Err(CustomError::ValidationError { error: e }),
-    }
+    let (game_id, token) = path.into_inner();
+    let picked = store.update(&game_id, |game| {
+        let names = game.player_pick_by_token(&token)?;
+        Ok::<_, GameError>(PickedResponse::new(game, names))
+    })??;
+    Ok(picked)
+}
+
+// Recipients a player already revealed, with their wishlists as they are now.
+async fn show_recipients(
+    path: web::Path<(String, String)>,
+    store: web::Data<GameStore>,
+) -> Result<PickedResponse, CustomError> {
+    let (game_id, token) = path.into_inner();
+    let picked = store.read(&game_id, |game| {
+        let names = game.revealed_recipients(&token)?;
+        Ok::<_, GameError>(PickedResponse::new(game, names))
+    })??;
+    Ok(picked)
+}
+
+fn conversations(game: &SecretSantaGame, token: &str) -> Result<Conversations, GameError> {
+    Ok(Conversations {
+        recipients: game.threads_as_santa(token)?,
+        santas: game.threads_as_recipient(token)?,
+    })
+}
+
+async fn show_messages(
+    path: web::Path<(String, String)>,
+    store: web::Data<GameStore>,
+) -> Result<Conversations, CustomError> {
+    let (game_id, token) = path.into_inner();
+    let conversations = store.read(&game_id, |game| conversations(game, &token))??;
+    Ok(conversations)
+}
+
+async fn message_recipient(
+    path: web::Path<(String, String, String)>,
+    message: web::Json<NewMessage>,
+    store: web::Data<GameStore>,
+) -> Result<Conversations, CustomError> {
+    let (game_id, token, recipient) = path.into_inner();
+    let conversations = store.update(&game_id, |game| {
+        game.message_recipient(&token, &recipient, &message.text, secret_santa::unix_now())?;
+        conversations(game, &token)
+    })??;
+    Ok(conversations)
+}
+
+async fn message_santa(
+    path: web::Path<(String, String, String)>,
+    message: web::Json<NewMessage>,
+    store: web::Data<GameStore>,
+) -> Result<Conversations, CustomError> {
+    let (game_id, token, thread) = path.into_inner();
+    let conversations = store.update(&game_id, |game| {
+        game.message_santa(&token, &thread, &message.text, secret_santa::unix_now())?;
+        conversations(game, &token)
+    })??;
+    Ok(conversations)
+}
+
+async fn show_wishlist(
+    path: web::Path<(String, String)>,
+    store: web::Data<GameStore>,
+) -> Result<Wishlist, CustomError> {
+    let (game_id, token) = path.into_inner();
+    let items = store.read(&game_id, |game| game.wishlist(&token).map(<[_]>::to_vec))??;
+    Ok(Wishlist { items })
+}
+
+async fn set_wishlist(
+    path: web::Path<(String, String)>,
+    wishlist: web::Json<Wishlist>,
+    store: web::Data<GameStore>,
+) -> Result<Wishlist, CustomError> {
+    let (game_id, token) = path.into_inner();
+    let items = store.update(&game_id, |game| {
+        game.set_wishlist(&token, wishlist.into_inner().items)?;
+        game.wishlist(&token).map(<[_]>::to_vec)
+    })??;
+    Ok(Wishlist { items })
 }
 
 async fn remove_player(
-    player_name: web::Path<String>,
-    game_data: web::Data<Arc<Mutex<SecretSantaGame>>>,
+    _organizer: Organizer,
+    path: web::Path<(String, String)>,
+    store: web::Data<GameStore>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut game = game_data.lock().unwrap();
-    let result = game.remove_player(&player_name.into_inner());
-    if result.is_err() {
-        return Err(CustomError::ValidationError {
-            error: result.unwrap_err(),
-        });
-    }
+    let (game_id, player_name) = path.into_inner();
+    store.update(&game_id, |game| game.remove_player(&player_name))??;
     Ok(HttpResponse::Ok().json("Player removed"))
 }
 
-async fn show_players(game_data: web::Data<Arc<Mutex<SecretSantaGame>>>) -> Players {
-    let game = game_data.lock().unwrap();
-    let mut players = Vec::new();
-    for player in game.players.iter() {
-        players.push(player.name.clone());
-    }
-    Players { names: players }
+async fn show_players(
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<Players, CustomError> {
+    store.read(&game_id, |game| {
+        let mut players = Vec::new();
+        let mut groups = HashMap::new();
+        for player in game.players.iter() {
+            players.push(player.name.clone());
+            if let Some(group) = &player.group {
+                groups.insert(player.name.clone(), group.clone());
+            }
+        }
+        Players {
+            names: players,
+            groups,
+            emails: HashMap::new(),
+        }
+    })
+}
+
+async fn add_exclusions(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    exclusions: web::Json<Exclusions>,
+    store: web::Data<GameStore>,
+) -> Result<Exclusions, CustomError> {
+    // the whole batch is checked on a copy, so it is added entirely or not at all
+    store.update(&game_id, |game| {
+        let mut checked = game.clone();
+        for exclusion in exclusions.exclusions.iter() {
+            checked.add_exclusion(exclusion.clone())?;
+        }
+        game.exclusions = checked.exclusions;
+        Ok::<_, GameError>(())
+    })??;
+    Ok(exclusions.into_inner())
+}
+
+async fn remove_exclusion(
+    _organizer: Organizer,
+    path: web::Path<(String, String, String)>,
+    store: web::Data<GameStore>,
+) -> Result<HttpResponse, CustomError> {
+    let (game_id, giver, receiver) = path.into_inner();
+    store.update(&game_id, |game| game.remove_exclusion(&giver, &receiver))??;
+    Ok(HttpResponse::Ok().json("Exclusion removed"))
+}
+
+async fn show_exclusions(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<Exclusions, CustomError> {
+    store.read(&game_id, |game| Exclusions {
+        exclusions: game.exclusions.clone(),
+    })
+}
+
+async fn show_webhooks(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<WebhookList, CustomError> {
+    store.read(&game_id, WebhookList::new)
+}
+
+// Answers with the secret that signs the payloads, it is not shown again.
+async fn add_webhook(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    webhook: web::Json<NewWebhook>,
+    store: web::Data<GameStore>,
+    dispatcher: web::Data<WebhookDispatcher>,
+) -> Result<WebhookInfo, CustomError> {
+    let NewWebhook { url, events } = webhook.into_inner();
+    dispatcher.check_target(&url).await?;
+    let webhook = store.update(&game_id, |game| game.add_webhook(&url, events))??;
+    Ok(WebhookInfo {
+        secret: Some(webhook.secret.clone()),
+        ..WebhookInfo::new(&webhook)
+    })
+}
+
+async fn remove_webhook(
+    _organizer: Organizer,
+    path: web::Path<(String, String)>,
+    store: web::Data<GameStore>,
+) -> Result<WebhookList, CustomError> {
+    let (game_id, webhook_id) = path.into_inner();
+    let webhooks = store.update(&game_id, |game| {
+        game.remove_webhook(&webhook_id)
+            .map(|_| WebhookList::new(game))
+    })??;
+    Ok(webhooks)
+}
+
+async fn webhook_deliveries(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    dispatcher: web::Data<WebhookDispatcher>,
+) -> Result<WebhookDeliveries, CustomError> {
+    Ok(WebhookDeliveries {
+        deliveries: dispatcher.deliveries(&game_id),
+    })
+}
+
+async fn set_history(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    history: web::Json<History>,
+    store: web::Data<GameStore>,
+) -> Result<History, CustomError> {
+    let history = history.into_inner();
+    store.update(&game_id, |game| {
+        game.set_history(history.draws.clone(), history.policy.clone())
+    })??;
+    Ok(history)
+}
+
+async fn show_history(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<History, CustomError> {
+    store.read(&game_id, |game| History {
+        draws: game.history.clone(),
+        policy: game.history_policy.clone(),
+    })
+}
+
+async fn set_name_rules(
+    _organizer: Organizer,
+    game_id: web::Path<String>,
+    rules: web::Json<NameRules>,
+    store: web::Data<GameStore>,
+) -> Result<HttpResponse, CustomError> {
+    let rules = rules.into_inner();
+    store.update(&game_id, |game| game.set_name_rules(rules.clone()))??;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+async fn show_name_rules(
+    game_id: web::Path<String>,
+    store: web::Data<GameStore>,
+) -> Result<HttpResponse, CustomError> {
+    let rules = store.read(&game_id, |game| game.name_rules.clone())?;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+async fn export_draw(
+    _organizer: Organizer,
+    path: web::Path<(String, u16)>,
+    store: web::Data<GameStore>,
+) -> Result<HttpResponse, CustomError> {
+    let (game_id, year) = path.into_inner();
+    let draw = store.read(&game_id, |game| game.export_draw(year))??;
+    Ok(HttpResponse::Ok().json(draw))
 }
 
 pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("games").route(web::post().to(create_game)))
+        .service(web::scope("{game_id}").configure(game_routes));
+}
+
+fn game_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("").route(web::get().to(index)))
+        .service(web::resource("events").route(web::get().to(events)))
+        .service(web::resource("lobby").route(web::get().to(join_lobby)))
         .service(web::resource("start-game").route(web::post().to(start_game)))
+        .service(web::resource("notify-players").route(web::post().to(notify_players)))
         .service(web::resource("reset-game").route(web::post().to(reset_game)))
+        .service(web::resource("close-registration").route(web::post().to(close_registration)))
+        .service(web::resource("reopen-registration").route(web::post().to(reopen_registration)))
+        .service(web::resource("open-reveals").route(web::post().to(open_reveals)))
+        .service(web::resource("close-exchange").route(web::post().to(close_exchange)))
+        .service(web::resource("archive").route(web::post().to(archive_game)))
+        .service(web::resource("clear-players").route(web::post().to(clear_players)))
+        .service(web::resource("set-draw-strategy").route(web::post().to(set_draw_strategy)))
+        .service(web::resource("set-gifts-per-person").route(web::post().to(set_gifts_per_person)))
+        .service(web::resource("set-schedule").route(web::post().to(set_schedule)))
+        .service(web::resource("set-event").route(web::post().to(set_event)))
+        .service(web::resource("reveal-seed").route(web::post().to(reveal_seed)))
+        .service(web::resource("verify-draw").route(web::get().to(verify_draw)))
         .service(web::resource("show-players").route(web::get().to(show_players)))
-        .service(web::resource("player-pick/{player_name}").route(web::get().to(pick_players)))
+        .service(web::resource("player-tokens").route(web::get().to(show_player_tokens)))
+        .service(web::resource("player-pick/{token}").route(web::get().to(pick_players)))
+        .service(web::resource("recipients/{token}").route(web::get().to(show_recipients)))
+        .service(web::resource("messages/{token}").route(web::get().to(show_messages)))
+        .service(
+            web::resource("messages/{token}/recipient/{recipient}")
+                .route(web::post().to(message_recipient)),
+        )
+        .service(
+            web::resource("messages/{token}/santa/{thread}").route(web::post().to(message_santa)),
+        )
+        .service(
+            web::resource("wishlist/{token}")
+                .route(web::get().to(show_wishlist))
+                .route(web::post().to(set_wishlist)),
+        )
         .service(web::resource("remove-player/{player_name}").route(web::post().to(remove_player)))
-        .service(web::resource("add-players").route(web::post().to(add_players)));
+        .service(web::resource("add-players").route(web::post().to(add_players)))
+        .service(web::resource("add-late-player").route(web::post().to(add_late_player)))
+        .service(web::resource("withdraw-player").route(web::post().to(withdraw_player)))
+        .service(web::resource("show-exclusions").route(web::get().to(show_exclusions)))
+        .service(web::resource("add-exclusions").route(web::post().to(add_exclusions)))
+        .service(
+            web::resource("remove-exclusion/{giver}/{receiver}")
+                .route(web::post().to(remove_exclusion)),
+        )
+        .service(web::resource("show-webhooks").route(web::get().to(show_webhooks)))
+        .service(web::resource("add-webhook").route(web::post().to(add_webhook)))
+        .service(web::resource("remove-webhook/{webhook_id}").route(web::post().to(remove_webhook)))
+        .service(web::resource("webhook-deliveries").route(web::get().to(webhook_deliveries)))
+        .service(web::resource("show-history").route(web::get().to(show_history)))
+        .service(web::resource("set-history").route(web::post().to(set_history)))
+        .service(web::resource("show-name-rules").route(web::get().to(show_name_rules)))
+        .service(web::resource("set-name-rules").route(web::post().to(set_name_rules)))
+        .service(web::resource("export-draw/{year}").route(web::get().to(export_draw)));
 }
 
 #[cfg(test)]
 mod tests {
+    use actix_web::body::{BoxBody, MessageBody};
     use actix_web::{test, App};
-    use secret_santa::GameStatus;
+    use secret_santa::{
+        DrawStrategy, Exclusion, GameStatus, HistoryPolicy, PastDraw, WishlistItem,
+    };
+    use std::future::poll_fn;
+    use std::pin::Pin;
 
+    use super::super::repository::{GameRepository, InMemoryRepository, SqliteRepository};
+    use super::super::storage::StateFile;
     use super::*;
 
+    const GAME_ID: &str = "test-game";
+    const ADMIN_SECRET: &str = "organizer-secret";
+
+    fn game_store(game: SecretSantaGame) -> web::Data<GameStore> {
+        let repository = InMemoryRepository::default();
+        repository.create(GAME_ID, ADMIN_SECRET).unwrap();
+        repository.save(GAME_ID, &game).unwrap();
+        web::Data::new(GameStore::new(repository))
+    }
+
+    fn admin_header() -> (&'static str, String) {
+        ("Authorization", format!("Bearer {}", ADMIN_SECRET))
+    }
+
     #[actix_rt::test]
     async fn test_index() {
         let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
+        let secret_santa_game = game_store(game);
 
-        let mut app = test::init_service(
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
 
-        let req = test::TestRequest::get().uri("/secret-santa").to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         let body = test::read_body(resp).await;
         let game_info: SantaGameInfo = serde_json::from_slice(&body).unwrap();
-        assert_eq!(game_info.status, GameStatus::NotStarted);
+        assert_eq!(game_info.status, GameStatus::Open);
         assert_eq!(game_info.players.len(), 0);
     }
 
     #[actix_rt::test]
     async fn test_add_players() {
         let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
+        let secret_santa_game = game_store(game);
 
-        let mut app = test::init_service(
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
@@ -144,56 +850,95 @@ mod tests {
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
             .set_json(&Players {
                 names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
             })
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         let body = test::read_body(resp).await;
-        let players: Players = serde_json::from_slice(&body).unwrap();
-        assert_eq!(players.names.len(), 2);
-        assert_eq!(players.names[0], "Player1");
-        assert_eq!(players.names[1], "Player2");
+        let report: AddPlayersReport = serde_json::from_slice(&body).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.players.len(), 2);
+        assert_eq!(report.players[0].name, "Player1");
+        assert_eq!(report.players[1].name, "Player2");
+        assert!(report.players.iter().all(|p| p.accepted));
+        let token = report.players[0].token.as_deref().unwrap();
+        let name = secret_santa_game
+            .read(GAME_ID, |game| {
+                game.player_by_token(token).unwrap().name.clone()
+            })
+            .unwrap();
+        assert_eq!(name, "Player1");
     }
 
     #[actix_rt::test]
-    async fn test_start_game() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-
-        let mut app = test::init_service(
+    async fn test_add_players_rejects_whole_batch() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
+        let mut changes = secret_santa_game.subscribe();
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
             .set_json(&Players {
-                names: vec!["Player1".to_string(), "Player2".to_string()],
+                names: vec![
+                    "Player2".to_string(),
+                    "Player1".to_string(),
+                    "".to_string(),
+                    "Player2".to_string(),
+                ],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
             })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
-
-        let req = test::TestRequest::post()
-            .uri("/secret-santa/start-game")
-            .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_success());
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let report: AddPlayersReport = test::read_body_json(resp).await;
+        assert!(!report.applied);
+        assert!(report.players.iter().all(|p| p.token.is_none()));
+        assert_eq!(report.code.as_deref(), Some("players_rejected"));
+        let codes: Vec<Option<&str>> = report.players.iter().map(|p| p.code.as_deref()).collect();
+        assert_eq!(
+            codes,
+            vec![
+                None,
+                Some("player_already_exists"),
+                Some("empty_player_name"),
+                Some("duplicate_player")
+            ]
+        );
+        assert_eq!(
+            secret_santa_game
+                .read(GAME_ID, |game| game.players.len())
+                .unwrap(),
+            1
+        );
+        // nothing changed, so nobody is told it did
+        assert!(changes.try_recv().is_err());
     }
 
     #[actix_rt::test]
-    async fn test_reset_game() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-
-        let mut app = test::init_service(
+    async fn test_add_players_warns_about_similar_names() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Ana")).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
@@ -201,41 +946,57 @@ mod tests {
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
-            .set_json(&Players {
-                names: vec!["Player1".to_string(), "Player2".to_string()],
+            .uri("/secret-santa/test-game/set-name-rules")
+            .insert_header(admin_header())
+            .set_json(&NameRules {
+                min_length: 2,
+                max_length: 10,
             })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/start-game")
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["ana ".to_string(), "Bartholomew Jr".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
+        let report: AddPlayersReport = test::read_body_json(resp).await;
+        assert_eq!(
+            report.players[0].code.as_deref(),
+            Some("player_already_exists")
+        );
+        assert_eq!(report.players[1].code.as_deref(), Some("name_too_long"));
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/reset-game")
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec![" Ána".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_success());
-
-        let req = test::TestRequest::get().uri("/secret-santa").to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-
-        let body = test::read_body(resp).await;
-        let santa_game_response: SantaGameInfo = serde_json::from_slice(&body).unwrap();
-        assert_eq!(santa_game_response.status, GameStatus::NotStarted);
-        assert_eq!(santa_game_response.players.len(), 0);
+        let report: AddPlayersReport = test::read_body_json(resp).await;
+        assert!(report.applied);
+        assert_eq!(report.players[0].name, "Ána");
+        assert_eq!(report.players[0].similar_to, vec!["Ana".to_string()]);
+        assert!(report.players[0].warning.is_some());
     }
 
     #[actix_rt::test]
-    async fn test_pick_players() {
+    async fn test_start_game() {
         let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
+        let secret_santa_game = game_store(game);
 
-        let mut app = test::init_service(
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
@@ -243,36 +1004,31 @@ mod tests {
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
             .set_json(&Players {
                 names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
             })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let _ = test::call_service(&app, req).await;
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/start-game")
-            .to_request();
-        let _ = test::call_service(&mut app, req).await;
-
-        let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-
-        let body = test::read_body(resp).await;
-        let picked: PickedResponse = serde_json::from_slice(&body).unwrap();
-        assert_eq!(picked.name, "Player2");
     }
 
     #[actix_rt::test]
-    async fn test_show_players() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-
-        let mut app = test::init_service(
+    async fn test_seeded_draw_can_be_verified() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
@@ -280,33 +1036,60 @@ mod tests {
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
-            .set_json(&Players {
-                names: vec!["Player1".to_string(), "Player2".to_string()],
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .set_json(&StartGame {
+                seed: Some("5d81f3c09a6e2b47".to_string()),
+                ..Default::default()
             })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let tokens: PlayerTokens = test::read_body_json(resp).await;
 
         let req = test::TestRequest::get()
-            .uri("/secret-santa/show-players")
+            .uri("/secret-santa/test-game")
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_success());
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert!(info.commitment.is_some());
+        assert_eq!(info.seed, None);
 
-        let body = test::read_body(resp).await;
-        let players: Players = serde_json::from_slice(&body).unwrap();
-        assert_eq!(players.names.len(), 2);
-        assert_eq!(players.names[0], "Player1");
-        assert_eq!(players.names[1], "Player2");
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/verify-draw")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+
+        for token in tokens.tokens.values() {
+            let req = test::TestRequest::get()
+                .uri(&format!("/secret-santa/test-game/player-pick/{}", token))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/close-exchange")
+            .insert_header(admin_header())
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.status, GameStatus::Closed);
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/reveal-seed")
+            .insert_header(admin_header())
+            .to_request();
+        let verification: DrawVerification = test::call_and_read_body_json(&app, req).await;
+        assert!(verification.seed.starts_with("5d81f3c09a6e2b47:"));
+        assert_eq!(Some(verification.commitment), info.commitment);
+        assert!(verification.valid);
     }
 
     #[actix_rt::test]
-    async fn test_add_players_with_invalid_name() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-
-        let mut app = test::init_service(
+    async fn test_set_draw_strategy() {
+        let mut game = SecretSantaGame::default();
+        for name in ["Player1", "Player2", "Player3"] {
+            game.add_player(Player::new(name)).unwrap();
+        }
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
@@ -314,183 +1097,1691 @@ mod tests {
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
-            .set_json(&Players {
-                names: vec![
-                    "Player 1".to_string(),
-                    "Player 2".to_string(),
-                    "Player 1".to_string(),
-                ],
-            })
+            .uri("/secret-santa/test-game/set-draw-strategy")
+            .insert_header(admin_header())
+            .set_json(serde_json::json!({ "strategy": "NoMutualPairs" }))
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error());
-    }
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
 
-    #[actix_rt::test]
-    async fn test_start_game_with_no_players() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game")
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.draw_strategy, DrawStrategy::NoMutualPairs);
 
-        let mut app = test::init_service(
-            App::new()
-                .app_data(secret_santa_game.clone())
-                .service(web::scope("/secret-santa").configure(routes)),
-        )
-        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
 
         let req = test::TestRequest::post()
-            .uri("/secret-santa/start-game")
+            .uri("/secret-santa/test-game/set-draw-strategy")
+            .insert_header(admin_header())
+            .set_json(serde_json::json!({ "strategy": "SingleCycle" }))
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error());
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
     }
 
     #[actix_rt::test]
-    async fn test_pick_players_with_no_game_started() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-
-        let mut app = test::init_service(
+    async fn test_pick_several_gifts() {
+        let mut game = SecretSantaGame::default();
+        for name in ["Player1", "Player2", "Player3", "Player4", "Player5"] {
+            game.add_player(Player::new(name)).unwrap();
+        }
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
 
-        let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/set-gifts-per-person")
+            .insert_header(admin_header())
+            .set_json(&GiftSettings {
+                gifts_per_person: 2,
+            })
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error());
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let tokens: PlayerTokens = test::call_and_read_body_json(&app, req).await;
+
+        let mut received = HashMap::new();
+        for (name, token) in tokens.tokens.iter() {
+            let req = test::TestRequest::get()
+                .uri(&format!("/secret-santa/test-game/player-pick/{}", token))
+                .to_request();
+            let picked: PickedResponse = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(picked.names.len(), 2);
+            assert!(!picked.names.contains(name));
+            assert_ne!(picked.names[0], picked.names[1]);
+            for receiver in picked.names {
+                *received.entry(receiver).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(received.len(), 5);
+        assert!(received.values().all(|&count| count == 2));
     }
 
     #[actix_rt::test]
-    async fn test_pick_players_with_invalid_player_name() {
-        let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-        let mut app = test::init_service(
+    async fn test_add_late_player() {
+        let mut game = SecretSantaGame::default();
+        for name in ["Player1", "Player2", "Player3", "Player4"] {
+            game.add_player(Player::new(name)).unwrap();
+        }
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
+
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
-            .set_json(&Players {
-                names: vec!["Player1".to_string(), "Player2".to_string()],
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let tokens: PlayerTokens = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens["Player1"]
+            ))
+            .to_request();
+        let revealed: PickedResponse = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-late-player")
+            .set_json(&LatePlayer {
+                name: "Player5".to_string(),
+                group: None,
+                email: None,
             })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
         let req = test::TestRequest::post()
-            .uri("/secret-santa/start-game")
+            .uri("/secret-santa/test-game/add-late-player")
+            .insert_header(admin_header())
+            .set_json(&LatePlayer {
+                name: "Player5".to_string(),
+                group: None,
+                email: None,
+            })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let joined: LateJoin = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(joined.name, "Player5");
+        assert_eq!(joined.reassigned.len(), 1);
+        assert!(!joined.reassigned.contains(&"Player1".to_string()));
+
         let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player3")
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                joined.token
+            ))
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error());
-    }
+        let picked: PickedResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(picked.names.len(), 1);
+        assert_ne!(picked.names[0], "Player5");
 
-    #[actix_rt::test]
+        let kept = secret_santa_game
+            .read(GAME_ID, |game| {
+                let player = game.players.iter().find(|p| p.name == "Player1").unwrap();
+                player.picked().to_vec()
+            })
+            .unwrap();
+        assert_eq!(kept, revealed.names);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-late-player")
+            .insert_header(admin_header())
+            .set_json(&LatePlayer {
+                name: "player5".to_string(),
+                group: None,
+                email: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    }
+
+    #[actix_rt::test]
+    async fn test_withdraw_player() {
+        let mut game = SecretSantaGame::default();
+        for name in ["Player1", "Player2", "Player3", "Player4"] {
+            game.add_player(Player::new(name)).unwrap();
+        }
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let tokens: PlayerTokens = test::call_and_read_body_json(&app, req).await;
+        let giver = secret_santa_game
+            .read(GAME_ID, |game| {
+                let giver = game.players.iter().find(|p| p.picked() == ["Player3"]);
+                giver.unwrap().name.clone()
+            })
+            .unwrap();
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens[&giver]
+            ))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/withdraw-player")
+            .insert_header(admin_header())
+            .set_json(&Withdrawal {
+                name: "Player3".to_string(),
+                replacement: Some(LatePlayer {
+                    name: "Player5".to_string(),
+                    group: None,
+                    email: None,
+                }),
+            })
+            .to_request();
+        let report: WithdrawalReport = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(report.reassigned, vec![giver.clone()]);
+        assert_eq!(report.reveal_again, vec![giver.clone()]);
+        assert!(report.token.is_some());
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens[&giver]
+            ))
+            .to_request();
+        let picked: PickedResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(picked.names, vec!["Player5".to_string()]);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens["Player3"]
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_reset_game() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/reset-game")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let santa_game_response: SantaGameInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(santa_game_response.status, GameStatus::Open);
+        assert_eq!(santa_game_response.players.len(), 2);
+        assert!(santa_game_response.players.iter().all(|p| !p.has_picked));
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/clear-players")
+            .insert_header(admin_header())
+            .to_request();
+        let santa_game_response: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(santa_game_response.players.len(), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_game_phases() {
+        let mut game = SecretSantaGame::default();
+        for name in ["Player1", "Player2", "Player3"] {
+            game.add_player(Player::new(name)).unwrap();
+        }
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/close-registration")
+            .insert_header(admin_header())
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.status, GameStatus::Draft);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player4".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "registration_closed");
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .set_json(&StartGame {
+                hold_reveals: true,
+                ..Default::default()
+            })
+            .to_request();
+        let tokens: PlayerTokens = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens["Player1"]
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/archive")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "invalid_transition");
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/open-reveals")
+            .insert_header(admin_header())
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.status, GameStatus::Revealing);
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens["Player1"]
+            ))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_create_game_is_capped() {
+        let secret_santa_game =
+            web::Data::new(GameStore::new(InMemoryRepository::default()).with_max_games(2));
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri("/secret-santa/games")
+                .to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+        }
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/games")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "too_many_games");
+        assert_eq!(secret_santa_game.game_ids().unwrap().len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_scheduled_game() {
+        let secret_santa_game = web::Data::new(GameStore::new(InMemoryRepository::default()));
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/games")
+            .set_json(Schedule {
+                reveals_at: Some(2_000),
+                exchange_at: Some(1_000),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert!(secret_santa_game.game_ids().unwrap().is_empty());
+
+        let schedule = Schedule {
+            reveals_at: Some(secret_santa::unix_now() + 3_600),
+            exchange_at: None,
+        };
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/games")
+            .set_json(schedule)
+            .to_request();
+        let room: NewGame = test::call_and_read_body_json(&app, req).await;
+        let auth = ("Authorization", format!("Bearer {}", room.admin_token));
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-players", room.id))
+            .insert_header(auth.clone())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/start-game", room.id))
+            .insert_header(auth.clone())
+            .to_request();
+        let tokens: PlayerTokens = test::call_and_read_body_json(&app, req).await;
+
+        let pick = format!(
+            "/secret-santa/{}/player-pick/{}",
+            room.id, tokens.tokens["Player1"]
+        );
+        let req = test::TestRequest::get().uri(&pick).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "reveals_scheduled");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/{}", room.id))
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.schedule, schedule);
+
+        // moving the dates into the past opens reveals and closes the exchange
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/set-schedule", room.id))
+            .insert_header(auth)
+            .set_json(Schedule {
+                reveals_at: Some(1_000),
+                exchange_at: Some(2_000),
+            })
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.status, GameStatus::Closed);
+        let req = test::TestRequest::get().uri(&pick).to_request();
+        let resp = test::call_service(&app, req).await;
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "game_finished");
+    }
+
+    #[actix_rt::test]
+    async fn test_set_event() {
+        let secret_santa_game = game_store(SecretSantaGame::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let event = EventDetails {
+            title: Some("Family Christmas".to_string()),
+            budget_max: Some(30),
+            currency: Some("BRL".to_string()),
+            rules: Some("Handmade gifts count double".to_string()),
+            ..Default::default()
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/set-event")
+            .set_json(&event)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/set-event")
+            .insert_header(admin_header())
+            .set_json(EventDetails {
+                currency: Some("R$".to_string()),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "invalid_currency");
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/set-event")
+            .insert_header(admin_header())
+            .set_json(&event)
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game")
+            .to_request();
+        let info: SantaGameInfo = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info.event, event);
+    }
+
+    #[actix_rt::test]
+    async fn test_pick_players() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let player_tokens: PlayerTokens = serde_json::from_slice(&body).unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                player_tokens.tokens["Player1"]
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let picked: PickedResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(picked.names, vec!["Player2".to_string()]);
+    }
+
+    #[actix_rt::test]
+    async fn test_wishlists() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let token = |name: &str| {
+            game.players
+                .iter()
+                .find(|p| p.name == name)
+                .and_then(|p| p.token())
+                .unwrap()
+                .to_string()
+        };
+        let (first, second) = (token("Player1"), token("Player2"));
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/test-game/wishlist/{}", second))
+            .set_json(Wishlist {
+                items: vec![WishlistItem {
+                    link: Some("https://example.com/scarf".to_string()),
+                    ..WishlistItem::new("Scarf")
+                }],
+            })
+            .to_request();
+        let wishlist: Wishlist = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(wishlist.items[0].title, "Scarf");
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/wishlist/unknown")
+            .set_json(Wishlist { items: vec![] })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/test-game/recipients/{}", first))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/test-game/player-pick/{}", first))
+            .to_request();
+        let picked: PickedResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(picked.recipients[0].name, "Player2");
+        assert_eq!(picked.recipients[0].wishlist, wishlist.items);
+
+        // a later change shows up when coming back
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/test-game/wishlist/{}", second))
+            .set_json(Wishlist {
+                items: vec![WishlistItem::new("Gloves")],
+            })
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/test-game/recipients/{}", first))
+            .to_request();
+        let picked: PickedResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(picked.recipients[0].wishlist[0].title, "Gloves");
+    }
+
+    #[actix_rt::test]
+    async fn test_anonymous_messages() {
+        let mut game = SecretSantaGame::default();
+        for name in ["Player1", "Player2", "Player3"] {
+            game.add_player(Player::new(name)).unwrap();
+        }
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let santa = game
+            .players
+            .iter()
+            .find(|p| p.picked().contains(&"Player1".to_string()))
+            .unwrap();
+        let santa_name = santa.name.clone();
+        let santa_token = santa.token().unwrap().to_string();
+        let token = game
+            .players
+            .iter()
+            .find(|p| p.name == "Player1")
+            .and_then(|p| p.token())
+            .unwrap()
+            .to_string();
+        game.player_pick(&santa_name).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/secret-santa/test-game/messages/{}/recipient/Player1",
+                santa_token
+            ))
+            .set_json(NewMessage {
+                text: "Any allergies?".to_string(),
+            })
+            .to_request();
+        let conversations: Conversations = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(conversations.recipients[0].with, "Player1");
+        assert!(conversations.recipients[0].messages[0].mine);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/test-game/messages/{}", token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        assert!(!String::from_utf8_lossy(&body).contains(&santa_name));
+        let conversations: Conversations = serde_json::from_slice(&body).unwrap();
+        let thread = conversations.santas[0].with.clone();
+        assert_eq!(conversations.santas[0].messages[0].text, "Any allergies?");
+
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/secret-santa/test-game/messages/{}/santa/{}",
+                token, thread
+            ))
+            .set_json(NewMessage {
+                text: "Peanuts".to_string(),
+            })
+            .to_request();
+        let conversations: Conversations = test::call_and_read_body_json(&app, req).await;
+        assert!(conversations.santas[0].messages[1].mine);
+
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/secret-santa/test-game/messages/{}/santa/unknown",
+                token
+            ))
+            .set_json(NewMessage {
+                text: "Hello?".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_show_players() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/show-players")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let players: Players = serde_json::from_slice(&body).unwrap();
+        assert_eq!(players.names.len(), 2);
+        assert_eq!(players.names[0], "Player1");
+        assert_eq!(players.names[1], "Player2");
+    }
+
+    #[actix_rt::test]
+    async fn test_add_players_with_invalid_name() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec![
+                    "Player 1".to_string(),
+                    "Player 2".to_string(),
+                    "Player 1".to_string(),
+                ],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_start_game_with_no_players() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_pick_players_with_no_game_started() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/player-pick/Player1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_pick_players_with_invalid_player_name() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/player-pick/Player3")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
     async fn test_pick_players_with_player_already_picked() {
         let game = SecretSantaGame::default();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-        let mut app = test::init_service(
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let player_tokens: PlayerTokens = serde_json::from_slice(&body).unwrap();
+        let uri = format!(
+            "/secret-santa/test-game/player-pick/{}",
+            player_tokens.tokens["Player1"]
+        );
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_pick_players_with_game_already_finished() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        game.player_pick("Player1").unwrap();
+        game.player_pick("Player2").unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/player-pick/Player1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_pick_players_with_game_not_started() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/player-pick/Player1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_pick_players_with_game_already_restarted() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        game.restart_game().unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/player-pick/Player1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn test_add_exclusions() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.add_player(Player::new("Player3")).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
         let req = test::TestRequest::post()
-            .uri("/secret-santa/add-players")
-            .set_json(&Players {
-                names: vec!["Player1".to_string(), "Player2".to_string()],
+            .uri("/secret-santa/test-game/add-exclusions")
+            .insert_header(admin_header())
+            .set_json(&Exclusions {
+                exclusions: vec![Exclusion::symmetric("Player1", "Player2")],
             })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game/show-exclusions")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let exclusions: Exclusions = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            exclusions.exclusions,
+            vec![Exclusion::symmetric("Player1", "Player2")]
+        );
+
         let req = test::TestRequest::post()
-            .uri("/secret-santa/start-game")
+            .uri("/secret-santa/test-game/remove-exclusion/Player1/Player2")
+            .insert_header(admin_header())
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
-        let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(secret_santa_game
+            .read(GAME_ID, |game| game.exclusions.is_empty())
+            .unwrap());
+
+        // a batch with one bad exclusion adds none of them
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-exclusions")
+            .insert_header(admin_header())
+            .set_json(&Exclusions {
+                exclusions: vec![
+                    Exclusion::new("Player1", "Player3"),
+                    Exclusion::new("Player2", "Player2"),
+                ],
+            })
             .to_request();
-        let _ = test::call_service(&mut app, req).await;
-        let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert!(secret_santa_game
+            .read(GAME_ID, |game| game.exclusions.is_empty())
+            .unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_start_game_with_impossible_exclusions() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.add_exclusion(Exclusion::new("Player1", "Player2"))
+            .unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
     }
 
     #[actix_rt::test]
-    async fn test_pick_players_with_game_already_finished() {
+    async fn test_add_players_with_groups() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::from([("Player1".to_string(), "Family".to_string())]),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/test-game")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let game_info: SantaGameInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(game_info.players[0].group, Some("Family".to_string()));
+        assert_eq!(game_info.players[1].group, None);
+    }
+
+    #[actix_rt::test]
+    async fn test_export_draw_as_history() {
         let mut game = SecretSantaGame::default();
         game.add_player(Player::new("Player1")).unwrap();
         game.add_player(Player::new("Player2")).unwrap();
-        game.start_game().unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
         game.player_pick("Player1").unwrap();
         game.player_pick("Player2").unwrap();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-        let mut app = test::init_service(
+        game.close_exchange().unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
         let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+            .uri("/secret-santa/test-game/export-draw/2024")
+            .insert_header(admin_header())
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error());
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let draw: PastDraw = serde_json::from_slice(&body).unwrap();
+        assert_eq!(draw.year, 2024);
+        assert_eq!(draw.pairings.len(), 2);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/reset-game")
+            .insert_header(admin_header())
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/set-history")
+            .insert_header(admin_header())
+            .set_json(&History {
+                draws: vec![draw],
+                policy: HistoryPolicy::AvoidLastYears(1),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let game = secret_santa_game
+            .read(GAME_ID, |game| game.clone())
+            .unwrap();
+        assert_eq!(game.history.len(), 1);
+        assert_eq!(game.history_policy, HistoryPolicy::AvoidLastYears(1));
     }
 
     #[actix_rt::test]
-    async fn test_pick_players_with_game_not_started() {
+    async fn test_pick_players_with_bare_name() {
         let mut game = SecretSantaGame::default();
         game.add_player(Player::new("Player1")).unwrap();
         game.add_player(Player::new("Player2")).unwrap();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-        let mut app = test::init_service(
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
         let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+            .uri("/secret-santa/test-game/player-pick/Player1")
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
+        assert!(secret_santa_game
+            .read(GAME_ID, |game| game.players.iter().all(|p| !p.has_picked))
+            .unwrap());
     }
 
     #[actix_rt::test]
-    async fn test_pick_players_with_game_already_restarted() {
+    async fn test_admin_routes_require_organizer_token() {
+        let game = SecretSantaGame::default();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/reset-game")
+            .insert_header(("Authorization", "Bearer wrong-secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert!(secret_santa_game
+            .read(GAME_ID, |game| game.players.is_empty())
+            .unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_mutations_are_saved_to_state_file() {
+        let path = std::env::temp_dir().join(format!(
+            "secret-santa-routes-{}.json",
+            secret_santa::generate_token()
+        ));
+        let repository = InMemoryRepository::with_state_file(HashMap::new(), StateFile::new(&path));
+        repository.create(GAME_ID, ADMIN_SECRET).unwrap();
+        let secret_santa_game = web::Data::new(GameStore::new(repository));
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+
+        let restored = StateFile::new(&path).load().unwrap().unwrap();
+        let restored = &restored[GAME_ID].game;
+        assert_eq!(restored.status, GameStatus::Revealing);
+        assert_eq!(
+            restored.players,
+            secret_santa_game
+                .read(GAME_ID, |game| game.players.clone())
+                .unwrap()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_routes_with_sqlite_repository() {
+        let repository = SqliteRepository::in_memory().unwrap();
+        repository.create(GAME_ID, ADMIN_SECRET).unwrap();
+        let secret_santa_game = web::Data::new(GameStore::new(repository));
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/add-players")
+            .insert_header(admin_header())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let tokens: PlayerTokens = serde_json::from_slice(&body).unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens["Player1"]
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let picked: PickedResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(picked.names, vec!["Player2".to_string()]);
+
+        let game = secret_santa_game
+            .read(GAME_ID, |game| game.clone())
+            .unwrap();
+        assert_eq!(game.status, GameStatus::Revealing);
+        assert!(game
+            .players
+            .iter()
+            .any(|p| p.name == "Player1" && p.has_picked));
+    }
+
+    #[actix_rt::test]
+    async fn test_rooms_are_isolated() {
+        let secret_santa_game = web::Data::new(GameStore::new(InMemoryRepository::default()));
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let mut rooms = Vec::new();
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri("/secret-santa/games")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+            let body = test::read_body(resp).await;
+            let room: NewGame = serde_json::from_slice(&body).unwrap();
+            rooms.push(room);
+        }
+        let (family, office) = (&rooms[0], &rooms[1]);
+        assert_ne!(family.id, office.id);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-players", family.id))
+            .insert_header(("Authorization", format!("Bearer {}", family.admin_token)))
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        // the family organizer has no say over the office game
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/reset-game", office.id))
+            .insert_header(("Authorization", format!("Bearer {}", family.admin_token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/{}", office.id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        let info: SantaGameInfo = serde_json::from_slice(&body).unwrap();
+        assert!(info.players.is_empty());
+
+        let req = test::TestRequest::get()
+            .uri("/secret-santa/unknown-game")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    async fn next_event(body: &mut BoxBody) -> SantaGameInfo {
+        let chunk = poll_fn(|cx| Pin::new(&mut *body).poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        let data = chunk.strip_prefix("data: ").unwrap().trim_end();
+        serde_json::from_str(data).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_events_stream_game_changes() {
         let mut game = SecretSantaGame::default();
         game.add_player(Player::new("Player1")).unwrap();
         game.add_player(Player::new("Player2")).unwrap();
-        game.start_game().unwrap();
-        game.restart_game();
-        let game_data = Arc::new(Mutex::new(game));
-        let secret_santa_game = web::Data::new(game_data);
-        let mut app = test::init_service(
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
             App::new()
                 .app_data(secret_santa_game.clone())
                 .service(web::scope("/secret-santa").configure(routes)),
         )
         .await;
         let req = test::TestRequest::get()
-            .uri("/secret-santa/player-pick/Player1")
+            .uri("/secret-santa/test-game/events")
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error());
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        let mut body = resp.into_body();
+
+        let info = next_event(&mut body).await;
+        assert_eq!(info.status, GameStatus::Open);
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/start-game")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body_bytes = test::read_body(resp).await;
+        let tokens: PlayerTokens = serde_json::from_slice(&body_bytes).unwrap();
+        let info = next_event(&mut body).await;
+        assert_eq!(info.status, GameStatus::Revealing);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/secret-santa/test-game/player-pick/{}",
+                tokens.tokens["Player1"]
+            ))
+            .to_request();
+        let _ = test::call_service(&app, req).await;
+        let info = next_event(&mut body).await;
+        assert!(info
+            .players
+            .iter()
+            .any(|player| player.name == "Player1" && player.has_picked));
+    }
+
+    #[actix_rt::test]
+    async fn test_lobby_upgrades_to_websocket() {
+        let secret_santa_game = game_store(SecretSantaGame::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .app_data(web::Data::new(Lobby::default()))
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let handshake = |uri: &str| {
+            test::TestRequest::get()
+                .uri(uri)
+                .insert_header((header::CONNECTION, "upgrade"))
+                .insert_header((header::UPGRADE, "websocket"))
+                .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+                .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, handshake("/secret-santa/test-game/lobby")).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::SWITCHING_PROTOCOLS
+        );
+
+        let resp = test::call_service(&app, handshake("/secret-santa/unknown-game/lobby")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_game_errors_have_status_and_code() {
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        let secret_santa_game = game_store(game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+
+        let cases = [
+            (
+                "/secret-santa/test-game/remove-player/Nobody",
+                actix_web::http::StatusCode::NOT_FOUND,
+                "player_not_found",
+            ),
+            (
+                "/secret-santa/test-game/start-game",
+                actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+                "not_enough_players",
+            ),
+        ];
+        for (uri, status, code) in cases {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .insert_header(admin_header())
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), status);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["code"], code);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/remove-exclusion/Player1/Nobody")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "exclusion_not_found");
+        assert_eq!(body["error"], "Exclusion not found");
+    }
+
+    #[actix_rt::test]
+    async fn test_start_game_emails_recipients() {
+        let secret_santa_game = web::Data::new(GameStore::new(InMemoryRepository::default()));
+        let dir = std::env::temp_dir().join(format!("secret-santa-routes-{}", std::process::id()));
+        let mailer = web::Data::new(Mailer::dry_run(
+            dir.clone(),
+            "santa@example.com".parse().unwrap(),
+            None,
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .app_data(mailer)
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/games")
+            .to_request();
+        let room: NewGame = test::call_and_read_body_json(&app, req).await;
+        let auth = ("Authorization", format!("Bearer {}", room.admin_token));
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-players", room.id))
+            .insert_header(auth.clone())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::from([("Player1".to_string(), "player1".to_string())]),
+            })
+            .to_request();
+        let report: AddPlayersReport = test::call_and_read_body_json(&app, req).await;
+        assert!(!report.applied);
+        assert_eq!(
+            report.players[0].error.as_deref(),
+            Some("player1 is not a valid email address")
+        );
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-players", room.id))
+            .insert_header(auth.clone())
+            .set_json(&Players {
+                names: vec!["Player1".to_string(), "Player2".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::from([(
+                    "Player1".to_string(),
+                    " player1@example.com ".to_string(),
+                )]),
+            })
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/start-game", room.id))
+            .insert_header(auth.clone())
+            .set_json(&StartGame {
+                language: Some("pt_BR".to_string()),
+                ..Default::default()
+            })
+            .to_request();
+        let tokens: PlayerTokens = test::call_and_read_body_json(&app, req).await;
+        let emails = tokens.emails.unwrap();
+        assert_eq!(emails.sent, vec!["Player1".to_string()]);
+        assert_eq!(emails.without_email, vec!["Player2".to_string()]);
+        let file = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.to_string_lossy().ends_with("-Player1.eml"))
+            .unwrap();
+        let email = std::fs::read_to_string(file).unwrap();
+        assert!(email.contains("To: player1@example.com"));
+        assert!(email.contains("Seu amigo secreto"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_notify_players_needs_a_mailer() {
+        let secret_santa_game = game_store(SecretSantaGame::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/test-game/notify-players")
+            .insert_header(admin_header())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "email_disabled");
+    }
+
+    #[actix_rt::test]
+    async fn test_webhooks() {
+        let secret_santa_game = web::Data::new(GameStore::new(InMemoryRepository::default()));
+        let dispatcher = web::Data::new(super::super::webhooks::tests::stub_dispatcher(vec![]));
+        WebhookDispatcher::listen(dispatcher.clone(), &secret_santa_game);
+        let app = test::init_service(
+            App::new()
+                .app_data(secret_santa_game.clone())
+                .app_data(dispatcher)
+                .service(web::scope("/secret-santa").configure(routes)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/secret-santa/games")
+            .to_request();
+        let room: NewGame = test::call_and_read_body_json(&app, req).await;
+        let auth = ("Authorization", format!("Bearer {}", room.admin_token));
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-webhook", room.id))
+            .insert_header(auth.clone())
+            .set_json(NewWebhook {
+                url: "chat-bot".to_string(),
+                events: vec![],
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        // cloud metadata and the private network stay out of reach
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-webhook", room.id))
+            .insert_header(auth.clone())
+            .set_json(NewWebhook {
+                url: "http://169.254.169.254/latest/meta-data".to_string(),
+                events: vec![],
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "webhook_target_forbidden");
+
+        let (url, mut requests) = super::super::webhooks::tests::http_stub(vec![200]).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-webhook", room.id))
+            .insert_header(auth.clone())
+            .set_json(NewWebhook {
+                url,
+                events: vec!["player_added".to_string()],
+            })
+            .to_request();
+        let webhook: WebhookInfo = test::call_and_read_body_json(&app, req).await;
+        let secret = webhook.secret.unwrap();
+        let req = test::TestRequest::get()
+            .uri(&format!("/secret-santa/{}/show-webhooks", room.id))
+            .insert_header(auth.clone())
+            .to_request();
+        let list: WebhookList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list.webhooks.len(), 1);
+        assert_eq!(list.webhooks[0].events, vec!["player_added".to_string()]);
+        assert!(list.webhooks[0].secret.is_none());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/secret-santa/{}/add-players", room.id))
+            .insert_header(auth.clone())
+            .set_json(&Players {
+                names: vec!["Player1".to_string()],
+                groups: HashMap::new(),
+                emails: HashMap::new(),
+            })
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let request = requests.recv().await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let signature = super::super::webhooks::signature(&secret, body.as_bytes());
+        assert!(head
+            .to_lowercase()
+            .contains(&format!("x-secret-santa-signature: {}", signature)));
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["event"], "player_added");
+        assert_eq!(payload["data"]["name"], "Player1");
+
+        // the log is written once the stub has answered
+        let mut deliveries = WebhookDeliveries { deliveries: vec![] };
+        for _ in 0..50 {
+            let req = test::TestRequest::get()
+                .uri(&format!("/secret-santa/{}/webhook-deliveries", room.id))
+                .insert_header(auth.clone())
+                .to_request();
+            deliveries = test::call_and_read_body_json(&app, req).await;
+            if !deliveries.deliveries.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(deliveries.deliveries[0].id, payload["id"]);
+        assert!(deliveries.deliveries[0].delivered);
+
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/secret-santa/{}/remove-webhook/{}",
+                room.id, webhook.id
+            ))
+            .insert_header(auth.clone())
+            .to_request();
+        let list: WebhookList = test::call_and_read_body_json(&app, req).await;
+        assert!(list.webhooks.is_empty());
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/secret-santa/{}/remove-webhook/{}",
+                room.id, webhook.id
+            ))
+            .insert_header(auth)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
     }
 }
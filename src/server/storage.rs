@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::repository::Room;
+
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        StateFile { path: path.into() }
+    }
+
+    pub fn load(&self) -> io::Result<Option<HashMap<String, Room>>> {
+        match fs::read_to_string(&self.path) {
+            Ok(snapshot) => Ok(Some(serde_json::from_str(&snapshot)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Loads the saved rooms, an unreadable snapshot is moved aside first so
+    // the next save cannot overwrite the games it may still hold.
+    pub fn restore(&self) -> io::Result<HashMap<String, Room>> {
+        match self.load() {
+            Ok(Some(rooms)) => {
+                log::info!("Restored game state from {}", self.path.display());
+                Ok(rooms)
+            }
+            Ok(None) => Ok(HashMap::new()),
+            Err(err) => {
+                let corrupt_path = corrupt_path(&self.path);
+                fs::rename(&self.path, &corrupt_path)?;
+                log::error!(
+                    "Failed to load game state from {}: {}, moved it to {}",
+                    self.path.display(),
+                    err,
+                    corrupt_path.display()
+                );
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    // Write to a sibling file first and rename it over the snapshot, so a
+    // crash in the middle of a save never leaves a truncated state file.
+    pub fn save(&self, rooms: &HashMap<String, Room>) -> io::Result<()> {
+        let snapshot = serde_json::to_string_pretty(rooms)?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, snapshot)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+// Timestamped, so a second bad snapshot does not replace the first.
+fn corrupt_path(path: &Path) -> PathBuf {
+    let mut corrupt_path = path.as_os_str().to_owned();
+    corrupt_path.push(format!(".{}.corrupt", secret_santa::unix_now()));
+    PathBuf::from(corrupt_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secret_santa::{GameStatus, Player, SecretSantaGame};
+
+    #[test]
+    fn save_and_load_rooms() {
+        let path = std::env::temp_dir().join(format!(
+            "secret-santa-state-{}.json",
+            secret_santa::generate_token()
+        ));
+        let state_file = StateFile::new(&path);
+        assert!(state_file.load().unwrap().is_none());
+
+        let mut game = SecretSantaGame::default();
+        game.add_player(Player::new("Player1")).unwrap();
+        game.add_player(Player::new("Player2")).unwrap();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        let mut rooms = HashMap::new();
+        rooms.insert(
+            "family".to_string(),
+            Room {
+                admin_token: "organizer-secret".to_string(),
+                game: game.clone(),
+            },
+        );
+        state_file.save(&rooms).unwrap();
+
+        let restored = state_file.load().unwrap().unwrap();
+        let room = &restored["family"];
+        assert_eq!(room.admin_token, "organizer-secret");
+        assert_eq!(room.game.status, GameStatus::Revealing);
+        assert_eq!(room.game.players, game.players);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_state_file_is_not_overwritten() {
+        let dir = std::env::temp_dir().join(format!(
+            "secret-santa-corrupt-{}",
+            secret_santa::generate_token()
+        ));
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("state.json");
+        fs::write(&path, "{\"family\": {\"admin_token\": ").unwrap();
+
+        let state_file = StateFile::new(&path);
+        assert!(state_file.restore().unwrap().is_empty());
+        assert!(!path.exists());
+        state_file.save(&HashMap::new()).unwrap();
+
+        let kept: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| p.to_string_lossy().ends_with(".corrupt"))
+            .collect();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(
+            fs::read_to_string(&kept[0]).unwrap(),
+            "{\"family\": {\"admin_token\": "
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -12,10 +12,7 @@ pub async fn open_browser(url: &str) {
         return;
     };
 
-    if let Err(err) = Command::new(command)
-        .args(&args)
-        .spawn()
-    {
+    if let Err(err) = Command::new(command).args(&args).spawn() {
         log::error!("Failed to open browser: {}", err);
     }
 }
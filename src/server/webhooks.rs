@@ -0,0 +1,618 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::web;
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use secret_santa::{generate_token, unix_now, GameError, GameStatus, SecretSantaGame, Webhook};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::error::CustomError;
+use super::models::WebhookDelivery;
+use super::repository::GameStore;
+
+pub const SIGNATURE_HEADER: &str = "X-Secret-Santa-Signature";
+pub const EVENT_HEADER: &str = "X-Secret-Santa-Event";
+pub const DELIVERY_HEADER: &str = "X-Secret-Santa-Delivery";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_LOGGED_DELIVERIES: usize = 50;
+
+// Something that happened to a game, with the webhooks that want to hear
+// about it. `data` holds names and counts, never who gives to whom.
+#[derive(Debug, Clone)]
+pub struct GameEvent {
+    pub game_id: String,
+    pub event: &'static str,
+    pub data: Value,
+    pub webhooks: Vec<Webhook>,
+}
+
+fn is_drawn(status: GameStatus) -> bool {
+    matches!(
+        status,
+        GameStatus::Drawn | GameStatus::Revealing | GameStatus::Closed
+    )
+}
+
+// The events between two states of the same game, in the order they happened.
+pub fn game_events(
+    before: &SecretSantaGame,
+    after: &SecretSantaGame,
+) -> Vec<(&'static str, Value)> {
+    let mut events = vec![];
+    let known = |game: &SecretSantaGame, name: &str| game.players.iter().any(|p| p.name == name);
+    for player in after.players.iter().filter(|p| !known(before, &p.name)) {
+        events.push(("player_added", json!({ "name": player.display_name() })));
+    }
+    for player in before.players.iter().filter(|p| !known(after, &p.name)) {
+        events.push(("player_removed", json!({ "name": player.display_name() })));
+    }
+    if is_drawn(before.status) && after.status == GameStatus::Open {
+        events.push(("game_reset", json!({})));
+    }
+    if !is_drawn(before.status) && is_drawn(after.status) {
+        events.push(("game_started", json!({ "players": after.players.len() })));
+    }
+    if before.status != GameStatus::Revealing && after.status == GameStatus::Revealing {
+        events.push(("reveals_opened", json!({})));
+    }
+    for player in after.players.iter().filter(|p| p.has_picked) {
+        let revealed_before = before
+            .players
+            .iter()
+            .any(|p| p.name == player.name && p.has_picked);
+        if !revealed_before {
+            events.push(("player_revealed", json!({ "name": player.display_name() })));
+        }
+    }
+    if before.status != GameStatus::Closed && after.status == GameStatus::Closed {
+        events.push(("game_finished", json!({})));
+    }
+    events
+}
+
+// `sha256=` and the hex HMAC-SHA256 of the body, keyed with the webhook secret.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+// The server itself, its private network, link-local addresses such as
+// cloud metadata endpoints and anything not meant for a single public host,
+// which organizers must not make the server call.
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // "this network" 0.0.0.0/8 and reserved 240.0.0.0/4
+                || first == 0
+                || first >= 240
+                // carrier-grade NAT, 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64)
+                // benchmarking, 198.18.0.0/15
+                || (first == 198 && second & 0xfe == 18)
+        }
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(ip) => is_internal(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+// The IPv4 address inside IPv4-compatible (::/96), IPv4-mapped
+// (::ffff:0:0/96), IPv4-translated (::ffff:0:0:0/96), NAT64 (64:ff9b::/96)
+// and 6to4 (2002::/16) addresses, which reach that IPv4 host.
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    let last = Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]);
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0 | 0xffff, _, _]
+        | [0, 0, 0, 0, 0xffff, 0, _, _]
+        | [0x64, 0xff9b, 0, 0, 0, 0, _, _] => Some(last),
+        [0x2002, ..] => Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5])),
+        _ => None,
+    }
+}
+
+// Whether `host` may be called when it resolves to `addresses`.
+fn target_allowed(host: &str, addresses: &[IpAddr], allowed_hosts: &[String]) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        || !addresses.iter().any(|&ip| is_internal(ip))
+}
+
+// Resolves host names for deliveries and refuses internal addresses, so a
+// name cannot point somewhere else once its webhook was accepted.
+struct PublicResolver {
+    allowed_hosts: Arc<[String]>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed_hosts = self.allowed_hosts.clone();
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            let ips: Vec<IpAddr> = addresses.iter().map(SocketAddr::ip).collect();
+            if !target_allowed(name.as_str(), &ips, &allowed_hosts) {
+                return Err(format!("{} resolves to an internal address", name.as_str()).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+// Posts game events to their webhooks, retrying failed deliveries after each
+// of `retry_delays`, and keeps the last deliveries of every game. Internal
+// addresses are refused unless their host is in `allowed_hosts`.
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    retry_delays: Vec<Duration>,
+    allowed_hosts: Arc<[String]>,
+    log: Mutex<HashMap<String, VecDeque<WebhookDelivery>>>,
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        WebhookDispatcher::with_retry_delays(vec![
+            Duration::from_secs(5),
+            Duration::from_secs(30),
+            Duration::from_secs(120),
+            Duration::from_secs(600),
+        ])
+    }
+}
+
+impl WebhookDispatcher {
+    pub fn with_retry_delays(retry_delays: Vec<Duration>) -> Self {
+        WebhookDispatcher {
+            client: client(Arc::from([])),
+            retry_delays,
+            allowed_hosts: Arc::from([]),
+            log: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Hosts that may be internal, such as a local stub while developing.
+    pub fn with_allowed_hosts(self, allowed_hosts: Vec<String>) -> Self {
+        let allowed_hosts: Arc<[String]> = Arc::from(allowed_hosts);
+        WebhookDispatcher {
+            client: client(allowed_hosts.clone()),
+            allowed_hosts,
+            ..self
+        }
+    }
+
+    // Refuses webhooks whose host is, or resolves to, an internal address.
+    pub async fn check_target(&self, url: &str) -> Result<(), CustomError> {
+        let invalid = || GameError::InvalidWebhookUrl {
+            url: url.to_string(),
+        };
+        let parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+        let host = parsed.host_str().ok_or_else(invalid)?;
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        let addresses: Vec<IpAddr> = match literal.parse() {
+            Ok(ip) => vec![ip],
+            Err(_) => tokio::net::lookup_host((host, port))
+                .await
+                .map(|addresses| addresses.map(|address| address.ip()).collect())
+                .unwrap_or_default(),
+        };
+        if addresses.is_empty() || !target_allowed(host, &addresses, &self.allowed_hosts) {
+            return Err(CustomError::WebhookTargetForbidden {
+                url: url.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // Newest first.
+    pub fn deliveries(&self, game_id: &str) -> Vec<WebhookDelivery> {
+        let log = self.log.lock().unwrap();
+        log.get(game_id)
+            .map(|deliveries| deliveries.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn record(&self, game_id: &str, delivery: WebhookDelivery) {
+        let mut log = self.log.lock().unwrap();
+        let deliveries = log.entry(game_id.to_string()).or_default();
+        match deliveries.iter_mut().find(|d| d.id == delivery.id) {
+            Some(logged) => *logged = delivery,
+            None => {
+                deliveries.push_back(delivery);
+                if deliveries.len() > MAX_LOGGED_DELIVERIES {
+                    deliveries.pop_front();
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, event: &GameEvent, webhook: &Webhook) {
+        let mut delivery = WebhookDelivery {
+            id: generate_token()[..16].to_string(),
+            webhook_id: webhook.id.clone(),
+            event: event.event.to_string(),
+            sent_at: unix_now(),
+            attempts: 0,
+            status: None,
+            error: None,
+            delivered: false,
+        };
+        let body = json!({
+            "id": delivery.id,
+            "event": event.event,
+            "game_id": event.game_id,
+            "sent_at": delivery.sent_at,
+            "data": event.data,
+        })
+        .to_string();
+        let signature = signature(&webhook.secret, body.as_bytes());
+
+        // literal addresses skip the resolver, and may have been stored
+        // before targets were checked
+        if let Err(error) = self.check_target(&webhook.url).await {
+            delivery.error = Some(error.to_string());
+            self.record(&event.game_id, delivery);
+            log::warn!(
+                "Webhook {} of game {}: {}",
+                webhook.id,
+                event.game_id,
+                error
+            );
+            return;
+        }
+
+        for attempt in 0..=self.retry_delays.len() {
+            if attempt > 0 {
+                tokio::time::sleep(self.retry_delays[attempt - 1]).await;
+            }
+            let response = self
+                .client
+                .post(&webhook.url)
+                .header("Content-Type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .header(EVENT_HEADER, event.event)
+                .header(DELIVERY_HEADER, &delivery.id)
+                .body(body.clone())
+                .send()
+                .await;
+            delivery.attempts += 1;
+            // client errors will not go away by asking again
+            let retry = match response {
+                Ok(response) => {
+                    let status = response.status();
+                    delivery.status = Some(status.as_u16());
+                    delivery.delivered = status.is_success();
+                    delivery.error = (!status.is_success()).then(|| format!("HTTP {}", status));
+                    status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429
+                }
+                Err(error) => {
+                    delivery.status = None;
+                    delivery.error = Some(error.to_string());
+                    true
+                }
+            };
+            self.record(&event.game_id, delivery.clone());
+            if delivery.delivered || !retry {
+                break;
+            }
+        }
+        if !delivery.delivered {
+            log::warn!(
+                "Webhook {} of game {} failed for {}: {}",
+                webhook.id,
+                event.game_id,
+                event.event,
+                delivery.error.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    // Delivers every event the store publishes until the store is gone.
+    pub fn listen(dispatcher: web::Data<WebhookDispatcher>, store: &GameStore) {
+        let mut events = store.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        for webhook in event.webhooks.clone() {
+                            let dispatcher = dispatcher.clone();
+                            let event = event.clone();
+                            tokio::spawn(async move { dispatcher.deliver(&event, &webhook).await });
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        log::warn!("{} game events were not sent to webhooks", missed)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+// Redirects are not followed, they could lead to an internal address.
+fn client(allowed_hosts: Arc<[String]>) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver { allowed_hosts }))
+        .build()
+        .unwrap()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use secret_santa::Player;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    fn names(events: &[(&'static str, Value)]) -> Vec<&'static str> {
+        events.iter().map(|(event, _)| *event).collect()
+    }
+
+    #[test]
+    fn events_follow_the_game() {
+        let mut game = SecretSantaGame::default();
+        let before = game.clone();
+        game.add_players(vec![
+            Player::new("Ana"),
+            Player::new("Bruno"),
+            Player::new("Carla"),
+        ])
+        .unwrap();
+        let events = game_events(&before, &game);
+        assert_eq!(names(&events), vec!["player_added"; 3]);
+        assert_eq!(events[0].1, json!({ "name": "Ana" }));
+
+        let before = game.clone();
+        game.start_game(None).unwrap();
+        game.open_reveals().unwrap();
+        assert_eq!(
+            names(&game_events(&before, &game)),
+            vec!["game_started", "reveals_opened"]
+        );
+
+        let before = game.clone();
+        let token = game.players[0].token().unwrap().to_string();
+        game.player_pick_by_token(&token).unwrap();
+        let events = game_events(&before, &game);
+        assert_eq!(names(&events), vec!["player_revealed"]);
+        // the recipient stays out of the payload
+        assert_eq!(
+            events[0].1,
+            json!({ "name": game.players[0].display_name() })
+        );
+
+        let before = game.clone();
+        game.restart_game().unwrap();
+        assert_eq!(names(&game_events(&before, &game)), vec!["game_reset"]);
+        assert!(game_events(&game, &game).is_empty());
+    }
+
+    // Answers every request with the next of `statuses`, standing in for a
+    // chat bot, and hands over each request it gets.
+    pub(crate) async fn http_stub(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (requests, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 4096];
+                loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break;
+                        }
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests
+                    .send(String::from_utf8_lossy(&request).to_string())
+                    .unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn event(url: &str, events: Vec<String>) -> GameEvent {
+        let mut game = SecretSantaGame::default();
+        let webhook = game.add_webhook(url, events).unwrap();
+        GameEvent {
+            game_id: "family".to_string(),
+            event: "game_started",
+            data: json!({ "players": 3 }),
+            webhooks: vec![webhook],
+        }
+    }
+
+    // Allowed to reach the stub, which listens on the loopback address.
+    pub(crate) fn stub_dispatcher(retry_delays: Vec<Duration>) -> WebhookDispatcher {
+        WebhookDispatcher::with_retry_delays(retry_delays)
+            .with_allowed_hosts(vec!["127.0.0.1".to_string()])
+    }
+
+    #[test]
+    fn internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.127.255.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "224.0.0.1",
+            "239.255.255.250",
+            "240.0.0.1",
+            "255.255.255.255",
+            "198.18.0.1",
+            "198.19.255.254",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "ff02::1",
+            "ff05::1:3",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "::ffff:0:10.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::127.0.0.1",
+            "2002:a00:1::1",
+            "2002:7f00:1::",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "93.184.216.34",
+            "8.8.8.8",
+            "2606:4700::1111",
+            "172.32.0.1",
+            "100.128.0.1",
+            "198.20.0.1",
+            "64:ff9b::808:808",
+            "2002:808:808::1",
+        ] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_targets_are_refused() {
+        let dispatcher = WebhookDispatcher::default();
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://10.0.0.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://localhost/hook",
+        ] {
+            assert_eq!(
+                dispatcher.check_target(url).await,
+                Err(CustomError::WebhookTargetForbidden {
+                    url: url.to_string()
+                })
+            );
+        }
+        dispatcher
+            .check_target("https://93.184.216.34/hook")
+            .await
+            .unwrap();
+
+        let dispatcher = dispatcher.with_allowed_hosts(vec!["localhost".to_string()]);
+        dispatcher
+            .check_target("http://localhost:3000/hook")
+            .await
+            .unwrap();
+        assert!(dispatcher
+            .check_target("http://127.0.0.1:3000/hook")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn internal_targets_are_not_called() {
+        let (url, mut requests) = http_stub(vec![200]).await;
+        let dispatcher = WebhookDispatcher::with_retry_delays(vec![Duration::from_millis(10)]);
+        let event = event(&url, vec![]);
+        dispatcher.deliver(&event, &event.webhooks[0]).await;
+
+        let delivery = &dispatcher.deliveries("family")[0];
+        assert_eq!(delivery.attempts, 0);
+        assert!(!delivery.delivered);
+        assert!(delivery.error.is_some());
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_retried_and_logged() {
+        let (url, mut requests) = http_stub(vec![500, 200]).await;
+        let dispatcher = stub_dispatcher(vec![Duration::from_millis(10)]);
+        let event = event(&url, vec![]);
+        let webhook = &event.webhooks[0];
+        dispatcher.deliver(&event, webhook).await;
+
+        let first = requests.recv().await.unwrap();
+        let second = requests.recv().await.unwrap();
+        let (head, body) = second.split_once("\r\n\r\n").unwrap();
+        assert_eq!(first.split_once("\r\n\r\n").unwrap().1, body);
+        let head = head.to_lowercase();
+        assert!(head.contains("x-secret-santa-event: game_started"));
+        assert!(head.contains(&format!(
+            "x-secret-santa-signature: {}",
+            signature(&webhook.secret, body.as_bytes())
+        )));
+        let payload: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["event"], "game_started");
+        assert_eq!(payload["game_id"], "family");
+        assert_eq!(payload["data"], json!({ "players": 3 }));
+
+        let deliveries = dispatcher.deliveries("family");
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].id, payload["id"]);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].status, Some(200));
+        assert!(deliveries[0].delivered);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (url, mut requests) = http_stub(vec![404]).await;
+        let dispatcher = stub_dispatcher(vec![Duration::from_millis(10)]);
+        let event = event(&url, vec![]);
+        dispatcher.deliver(&event, &event.webhooks[0]).await;
+        requests.recv().await.unwrap();
+
+        let delivery = &dispatcher.deliveries("family")[0];
+        assert_eq!(delivery.attempts, 1);
+        assert!(!delivery.delivered);
+        assert_eq!(delivery.error.as_deref(), Some("HTTP 404 Not Found"));
+    }
+}